@@ -1,5 +1,3 @@
-use std::env;
-
 use rig::{
     embeddings::EmbeddingsBuilder,
     providers::cohere::{Client, EMBED_ENGLISH_V3},
@@ -21,11 +19,10 @@ struct WordDefinition {
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     // Create Cohere client
-    let cohere_api_key = env::var("COHERE_API_KEY").expect("COHERE_API_KEY not set");
-    let cohere_client = Client::new(&cohere_api_key);
+    let cohere_client = Client::try_from_env()?;
 
-    let document_model = cohere_client.embedding_model(EMBED_ENGLISH_V3, "search_document");
-    let search_model = cohere_client.embedding_model(EMBED_ENGLISH_V3, "search_query");
+    let document_model = cohere_client.embedding_model_for_documents(EMBED_ENGLISH_V3);
+    let search_model = cohere_client.embedding_model_for_search(EMBED_ENGLISH_V3);
 
     let embeddings = EmbeddingsBuilder::new(document_model.clone())
         .documents(vec![
@@ -1,15 +1,19 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    completion::{CompletionModel, Document},
-    tool::{Tool, ToolSet},
+    completion::{join_preamble, CompletionModel, Document},
+    memory::Memory,
+    tool::{Tool, ToolDyn, ToolSet, ToolSetError},
     vector_store::VectorStoreIndexDyn,
 };
 
 #[cfg(feature = "mcp")]
 use crate::tool::McpTool;
 
-use super::Agent;
+use super::{
+    Agent, PromptTemplate, TemplateContext, TemplateError, ToolCallSelection,
+    DEFAULT_MAX_TOOL_ITERATIONS,
+};
 
 /// A builder for creating an agent
 ///
@@ -45,6 +49,7 @@ pub struct AgentBuilder<M: CompletionModel> {
     additional_params: Option<serde_json::Value>,
     /// Maximum number of tokens for the completion
     max_tokens: Option<u64>,
+    stop_sequences: Vec<String>,
     /// List of vector store, with the sample number
     dynamic_context: Vec<(usize, Box<dyn VectorStoreIndexDyn>)>,
     /// Dynamic tools
@@ -53,6 +58,12 @@ pub struct AgentBuilder<M: CompletionModel> {
     temperature: Option<f64>,
     /// Actual tool implementations
     tools: ToolSet,
+    /// Memory of the conversation
+    memory: Option<Arc<dyn Memory>>,
+    /// Tool calls executed when the model calls several tools at once
+    tool_call_selection: ToolCallSelection,
+    /// Maximum number of tool round trips of [Agent::run_with_tools]
+    max_tool_iterations: usize,
 }
 
 impl<M: CompletionModel> AgentBuilder<M> {
@@ -64,10 +75,14 @@ impl<M: CompletionModel> AgentBuilder<M> {
             static_tools: vec![],
             temperature: None,
             max_tokens: None,
+            stop_sequences: vec![],
             additional_params: None,
             dynamic_context: vec![],
             dynamic_tools: vec![],
             tools: ToolSet::default(),
+            memory: None,
+            tool_call_selection: ToolCallSelection::default(),
+            max_tool_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
         }
     }
 
@@ -77,6 +92,16 @@ impl<M: CompletionModel> AgentBuilder<M> {
         self
     }
 
+    /// Set the preamble of the agent to `template` rendered with `context`, failing if `context`
+    /// lacks a variable of the template.
+    pub fn preamble_template(
+        self,
+        template: &PromptTemplate,
+        context: &TemplateContext,
+    ) -> Result<Self, TemplateError> {
+        Ok(self.preamble(&template.render(context)?))
+    }
+
     /// Append to the preamble of the agent
     pub fn append_preamble(mut self, doc: &str) -> Self {
         self.preamble = Some(format!(
@@ -87,6 +112,23 @@ impl<M: CompletionModel> AgentBuilder<M> {
         self
     }
 
+    /// Set the system prompt from a list of fragments (e.g.: persona, safety and task
+    /// instructions), joined in order as described in [join_preamble].
+    pub fn preamble_fragments<S: AsRef<str>>(
+        mut self,
+        fragments: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.preamble = join_preamble(fragments);
+        self
+    }
+
+    /// Append a fragment to the system prompt, separated from the previous fragments
+    /// as described in [join_preamble].
+    pub fn preamble_fragment(mut self, fragment: &str) -> Self {
+        self.preamble = join_preamble(self.preamble.iter().map(String::as_str).chain([fragment]));
+        self
+    }
+
     /// Add a static context document to the agent
     pub fn context(mut self, doc: &str) -> Self {
         self.static_context.push(Document {
@@ -105,6 +147,34 @@ impl<M: CompletionModel> AgentBuilder<M> {
         self
     }
 
+    /// Add several static tools to the agent, e.g.: the tools of a plugin registry.
+    /// Tools of different types can be added at once as `Box<dyn ToolDyn>`.
+    ///
+    /// Returns [ToolSetError::DuplicateToolError] if two tools have the same name, or if a tool
+    /// has the name of a tool already added to the agent.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::tool::ToolDyn;
+    ///
+    /// let tools: Vec<Box<dyn ToolDyn>> = vec![Box::new(Adder), Box::new(Subtract)];
+    /// let agent = AgentBuilder::new(model).tools(tools)?.build();
+    /// ```
+    pub fn tools<T: ToolDyn + 'static>(
+        mut self,
+        tools: impl IntoIterator<Item = T>,
+    ) -> Result<Self, ToolSetError> {
+        for tool in tools {
+            let toolname = tool.name();
+            if self.tools.contains(&toolname) {
+                return Err(ToolSetError::DuplicateToolError(toolname));
+            }
+            self.tools.add_tool(tool);
+            self.static_tools.push(toolname);
+        }
+        Ok(self)
+    }
+
     // Add an MCP tool to the agent
     #[cfg(feature = "mcp")]
     pub fn mcp_tool<T: mcp_core::transport::Transport>(
@@ -155,12 +225,42 @@ impl<M: CompletionModel> AgentBuilder<M> {
         self
     }
 
+    /// Set the sequences that stop the generation, sent with every request of the agent.
+    /// The stop sequences of a request (see [super::RequestOverrides::stop_sequences]) are
+    /// added to these.
+    pub fn stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+
     /// Set additional parameters to be passed to the model
     pub fn additional_params(mut self, params: serde_json::Value) -> Self {
         self.additional_params = Some(params);
         self
     }
 
+    /// Set the memory of the agent. When prompted without an explicit chat history, the agent
+    /// loads the history of the conversation from the memory, and appends the new turn to it.
+    pub fn memory(mut self, memory: impl Memory + 'static) -> Self {
+        self.memory = Some(Arc::new(memory));
+        self
+    }
+
+    /// Set which tool calls the agent executes when the model calls several tools at once.
+    /// By default, every tool call is executed.
+    pub fn tool_call_selection(mut self, tool_call_selection: ToolCallSelection) -> Self {
+        self.tool_call_selection = tool_call_selection;
+        self
+    }
+
+    /// Set the maximum number of tool round trips of [Agent::run_with_tools] (10 by default).
+    /// When the model is still calling tools after `max_tool_iterations` round trips, the run
+    /// stops with a [crate::completion::PromptError::MaxDepthError].
+    pub fn max_tool_iterations(mut self, max_tool_iterations: usize) -> Self {
+        self.max_tool_iterations = max_tool_iterations;
+        self
+    }
+
     /// Build the agent
     pub fn build(self) -> Agent<M> {
         Agent {
@@ -170,10 +270,93 @@ impl<M: CompletionModel> AgentBuilder<M> {
             static_tools: self.static_tools,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
+            stop_sequences: self.stop_sequences,
             additional_params: self.additional_params,
             dynamic_context: self.dynamic_context,
             dynamic_tools: self.dynamic_tools,
             tools: self.tools,
+            memory: self.memory,
+            tool_call_selection: self.tool_call_selection,
+            max_tool_iterations: self.max_tool_iterations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        completion::{CompletionError, CompletionRequest, CompletionResponse, ToolDefinition},
+        message::AssistantContent,
+        tool::ToolError,
+        OneOrMany,
+    };
+
+    #[derive(Clone)]
+    struct Model;
+
+    impl CompletionModel for Model {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text("")),
+                raw_response: (),
+            })
         }
     }
+
+    /// Tool named after its id
+    struct Named<const ID: u8>;
+
+    impl<const ID: u8> Tool for Named<ID> {
+        const NAME: &'static str = match ID {
+            0 => "zero",
+            _ => "one",
+        };
+        type Error = ToolError;
+        type Args = serde_json::Value;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: String::new(),
+                parameters: serde_json::json!({"type": "object"}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(Self::NAME.to_string())
+        }
+    }
+
+    #[test]
+    fn test_tools() {
+        let tools: Vec<Box<dyn ToolDyn>> = vec![Box::new(Named::<0>), Box::new(Named::<1>)];
+        let agent = AgentBuilder::new(Model).tools(tools).unwrap().build();
+
+        assert_eq!(agent.static_tools, vec!["zero", "one"]);
+        assert!(agent.tools.contains("zero") && agent.tools.contains("one"));
+    }
+
+    #[test]
+    fn test_tools_rejects_duplicates() {
+        let error = AgentBuilder::new(Model)
+            .tools([Named::<0>, Named::<0>])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "DuplicateToolError: a tool named `zero` is already registered"
+        );
+
+        assert!(AgentBuilder::new(Model)
+            .tool(Named::<1>)
+            .tools([Named::<1>])
+            .is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use futures::{stream, StreamExt, TryStreamExt};
 
@@ -7,6 +7,7 @@ use crate::{
         Chat, Completion, CompletionError, CompletionModel, CompletionRequestBuilder, Document,
         Message, Prompt, PromptError,
     },
+    memory::Memory,
     streaming::{
         StreamingChat, StreamingCompletion, StreamingCompletionModel, StreamingCompletionResponse,
         StreamingPrompt,
@@ -15,7 +16,14 @@ use crate::{
     vector_store::VectorStoreError,
 };
 
-use super::prompt_request::PromptRequest;
+use super::{
+    prompt_request::{PromptRequest, RequestOverrides, ToolCallSelection},
+    template::{PromptTemplate, TemplateContext, TemplateError},
+};
+
+/// Default maximum number of tool round trips of [Agent::run_with_tools] (see
+/// [super::AgentBuilder::max_tool_iterations])
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 10;
 
 /// Struct representing an LLM agent. An agent is an LLM model combined with a preamble
 /// (i.e.: system prompt) and a static set of context documents and tools.
@@ -50,6 +58,8 @@ pub struct Agent<M: CompletionModel> {
     pub temperature: Option<f64>,
     /// Maximum number of tokens for the completion
     pub max_tokens: Option<u64>,
+    /// Sequences that stop the generation, sent with every request of the agent
+    pub stop_sequences: Vec<String>,
     /// Additional parameters to be passed to the model
     pub additional_params: Option<serde_json::Value>,
     /// List of vector store, with the sample number
@@ -58,6 +68,86 @@ pub struct Agent<M: CompletionModel> {
     pub dynamic_tools: Vec<(usize, Box<dyn crate::vector_store::VectorStoreIndexDyn>)>,
     /// Actual tool implementations
     pub tools: ToolSet,
+    /// Memory of the conversation, used and updated when the agent is prompted without an
+    /// explicit chat history
+    pub memory: Option<Arc<dyn Memory>>,
+    /// Tool calls executed by the agent when the model calls several tools at once
+    pub tool_call_selection: ToolCallSelection,
+    /// Maximum number of tool round trips of [Agent::run_with_tools]
+    pub max_tool_iterations: usize,
+}
+
+impl<M: CompletionModel> Agent<M> {
+    /// Prompt the agent with per-request overrides of its completion parameters (e.g.: a higher
+    /// temperature for a creative prompt), without rebuilding the agent.
+    pub fn prompt_with(
+        &self,
+        prompt: impl Into<Message> + Send,
+        overrides: RequestOverrides,
+    ) -> PromptRequest<'_, M> {
+        PromptRequest::new(self, prompt).with_overrides(overrides)
+    }
+
+    /// Prompt the agent with `template` rendered with `context` (see [PromptTemplate]), failing
+    /// before sending anything if `context` lacks a variable of the template.
+    pub fn prompt_template(
+        &self,
+        template: &PromptTemplate,
+        context: &TemplateContext,
+    ) -> Result<PromptRequest<'_, M>, TemplateError> {
+        Ok(PromptRequest::new(self, template.render(context)?))
+    }
+
+    /// Chat with the agent, returning its answer with the updated chat history: `chat_history`
+    /// followed by the prompt, the tool calls and tool results of the turn (if any) and the
+    /// response of the model, as returned by the model.
+    ///
+    /// The history can be serialized (e.g.: to JSON) to persist the conversation, and passed back
+    /// to resume it.
+    ///
+    /// # Example
+    /// ```
+    /// let (answer, history) = agent.chat_with_history("Hi, I'm Ada", vec![]).await?;
+    /// std::fs::write("conversation.json", serde_json::to_string(&history)?)?;
+    ///
+    /// // Later, e.g.: in another process
+    /// let history: Vec<Message> = serde_json::from_str(&std::fs::read_to_string("conversation.json")?)?;
+    /// let (answer, history) = agent.chat_with_history("What is my name?", history).await?;
+    /// ```
+    pub async fn chat_with_history(
+        &self,
+        prompt: impl Into<Message> + Send,
+        mut chat_history: Vec<Message>,
+    ) -> Result<(String, Vec<Message>), PromptError> {
+        let response = PromptRequest::new(self, prompt)
+            .with_history(&mut chat_history)
+            .await?;
+
+        Ok((response, chat_history))
+    }
+
+    /// Prompt the agent, executing the tools it calls and sending their results back to the
+    /// model until it answers with text, in at most `max_tool_iterations` round trips (see
+    /// [super::AgentBuilder::max_tool_iterations], or [PromptRequest::multi_turn] to change the
+    /// limit of a single run). Use [PromptRequest::on_tool_call] to observe the intermediate
+    /// tool calls.
+    ///
+    /// When the limit is exceeded (e.g.: the model keeps calling the same tool), the run stops
+    /// with a [PromptError::MaxDepthError] holding the chat history so far, the last message of
+    /// which is the last response of the model.
+    ///
+    /// # Example
+    /// ```
+    /// let answer = agent
+    ///     .run_with_tools("What is the weather in Paris?")
+    ///     .on_tool_call(|tool_call, output| {
+    ///         println!("{}({}) -> {output}", tool_call.function.name, tool_call.function.arguments)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub fn run_with_tools(&self, prompt: impl Into<Message> + Send) -> PromptRequest<'_, M> {
+        PromptRequest::new(self, prompt).multi_turn(self.max_tool_iterations)
+    }
 }
 
 impl<M: CompletionModel> Completion<M> for Agent<M> {
@@ -84,6 +174,9 @@ impl<M: CompletionModel> Completion<M> for Agent<M> {
             .messages(chat_history)
             .temperature_opt(self.temperature)
             .max_tokens_opt(self.max_tokens)
+            .stop_sequences_opt(
+                (!self.stop_sequences.is_empty()).then(|| self.stop_sequences.clone()),
+            )
             .additional_params_opt(self.additional_params.clone())
             .documents(self.static_context.clone());
 
@@ -110,7 +110,9 @@
 mod builder;
 mod completion;
 mod prompt_request;
+mod template;
 
 pub use builder::AgentBuilder;
-pub use completion::Agent;
-pub use prompt_request::PromptRequest;
+pub use completion::{Agent, DEFAULT_MAX_TOOL_ITERATIONS};
+pub use prompt_request::{OnToolCall, PromptRequest, RequestOverrides, ToolCallSelection};
+pub use template::{PromptTemplate, TemplateContext, TemplateError};
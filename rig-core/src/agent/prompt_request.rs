@@ -3,14 +3,195 @@ use std::future::IntoFuture;
 use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
 
 use crate::{
-    completion::{Completion, CompletionError, CompletionModel, Message, PromptError},
-    message::{AssistantContent, UserContent},
+    completion::{
+        Completion, CompletionError, CompletionModel, CompletionRequestBuilder, Message,
+        PromptError,
+    },
+    message::{AssistantContent, ToolCall, UserContent},
     tool::ToolSetError,
     OneOrMany,
 };
 
 use super::Agent;
 
+/// Per-request overrides of the completion parameters of an agent, layered on top of the
+/// agent's defaults. Parameters that are not set keep the agent's value.
+///
+/// # Example
+/// ```
+/// use rig::agent::RequestOverrides;
+///
+/// let response = agent
+///     .prompt_with("Write a poem about the sea", RequestOverrides::new().temperature(1.2))
+///     .await?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestOverrides {
+    /// Temperature of the model
+    pub temperature: Option<f64>,
+    /// Maximum number of tokens for the completion
+    pub max_tokens: Option<u64>,
+    /// Random seed of the completion
+    pub seed: Option<u64>,
+    /// Stop sequences added to the agent's stop sequences
+    pub stop_sequences: Vec<String>,
+    /// Additional parameters merged into the agent's additional parameters
+    pub additional_params: Option<serde_json::Value>,
+}
+
+impl RequestOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the temperature of the model
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Override the maximum number of tokens for the completion
+    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set the random seed of the completion. Providers that do not support seeds (e.g.:
+    /// Anthropic) ignore it with a warning.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Add stop sequences to the agent's stop sequences, for this request only.
+    pub fn stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences.extend(stop_sequences);
+        self
+    }
+
+    /// Add additional parameters, merged into the agent's additional parameters
+    /// (keys set here take precedence).
+    pub fn additional_params(mut self, additional_params: serde_json::Value) -> Self {
+        self.additional_params = Some(additional_params);
+        self
+    }
+
+    pub(crate) fn apply<M: CompletionModel>(
+        &self,
+        mut builder: CompletionRequestBuilder<M>,
+    ) -> CompletionRequestBuilder<M> {
+        if let Some(temperature) = self.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        for stop_sequence in &self.stop_sequences {
+            builder = builder.stop_sequence(stop_sequence.clone());
+        }
+        if let Some(additional_params) = &self.additional_params {
+            // Merged with the agent's additional parameters using `json_utils::merge`
+            builder = builder.additional_params(additional_params.clone());
+        }
+        builder
+    }
+}
+
+/// Which of the tool calls returned by the model an agent executes, when the model calls several
+/// tools at once. The tool calls that are not executed are removed from the chat history.
+///
+/// # Example
+/// ```
+/// use rig::agent::ToolCallSelection;
+///
+/// // Prefer looking up the documentation over searching the web
+/// let agent = openai.agent("gpt-4o")
+///     .tool(docs_lookup)
+///     .tool(web_search)
+///     .tool_call_selection(ToolCallSelection::ByName(vec![
+///         "docs_lookup".to_string(),
+///         "web_search".to_string(),
+///     ]))
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToolCallSelection {
+    /// Execute every tool call
+    #[default]
+    All,
+    /// Execute the first tool call only
+    First,
+    /// Execute the last tool call only
+    Last,
+    /// Execute the first call to the highest priority tool, tools being listed by decreasing
+    /// priority. Falls back to the first tool call if none of the listed tools was called.
+    ByName(Vec<String>),
+}
+
+impl ToolCallSelection {
+    /// Content of the response of the model without the tool calls that are not selected.
+    pub(crate) fn select(
+        &self,
+        choice: &OneOrMany<AssistantContent>,
+    ) -> OneOrMany<AssistantContent> {
+        let tool_calls = choice
+            .iter()
+            .enumerate()
+            .filter_map(|(i, content)| match content {
+                AssistantContent::ToolCall(tool_call) => Some((i, tool_call)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let selected = match self {
+            ToolCallSelection::All => return choice.clone(),
+            ToolCallSelection::First => tool_calls.first(),
+            ToolCallSelection::Last => tool_calls.last(),
+            ToolCallSelection::ByName(names) => names
+                .iter()
+                .find_map(|name| {
+                    tool_calls
+                        .iter()
+                        .find(|(_, tool_call)| tool_call.function.name == *name)
+                })
+                .or(tool_calls.first()),
+        }
+        .map(|(i, _)| *i);
+
+        let dropped = tool_calls
+            .iter()
+            .filter(|(i, _)| Some(*i) != selected)
+            .map(|(_, tool_call)| format!("{} ({})", tool_call.function.name, tool_call.id))
+            .collect::<Vec<_>>();
+        if !dropped.is_empty() {
+            tracing::warn!(target: "rig",
+                "Dropping {} of the {} tool calls of the model ({:?}): {}",
+                dropped.len(),
+                tool_calls.len(),
+                self,
+                dropped.join(", ")
+            );
+        }
+
+        OneOrMany::many(
+            choice
+                .iter()
+                .enumerate()
+                .filter(|(i, content)| {
+                    !matches!(content, AssistantContent::ToolCall(_)) || Some(*i) == selected
+                })
+                .map(|(_, content)| content.clone()),
+        )
+        .expect("The selected tool call or the text content is kept")
+    }
+}
+
+/// Callback called with each tool call executed by an agent and the output of the tool.
+pub type OnToolCall<'a> = Box<dyn Fn(&ToolCall, &str) + Send + Sync + 'a>;
+
 /// A builder for creating prompt requests with customizable options.
 /// Uses generics to track which options have been set during the build process.
 pub struct PromptRequest<'a, M: CompletionModel> {
@@ -23,6 +204,10 @@ pub struct PromptRequest<'a, M: CompletionModel> {
     max_depth: usize,
     /// The agent to use for execution
     agent: &'a Agent<M>,
+    /// Overrides of the agent's completion parameters
+    overrides: RequestOverrides,
+    /// Optional callback called after each tool call
+    on_tool_call: Option<OnToolCall<'a>>,
 }
 
 impl<'a, M: CompletionModel> PromptRequest<'a, M> {
@@ -33,18 +218,24 @@ impl<'a, M: CompletionModel> PromptRequest<'a, M> {
             chat_history: None,
             max_depth: 0,
             agent,
+            overrides: RequestOverrides::default(),
+            on_tool_call: None,
         }
     }
 }
 
 impl<'a, M: CompletionModel> PromptRequest<'a, M> {
-    /// Set the maximum depth for multi-turn conversations
+    /// Set the maximum depth for multi-turn conversations, i.e.: the maximum number of tool round
+    /// trips (tool calls of the model executed and their results sent back). A request always
+    /// allows at least one round trip.
     pub fn multi_turn(self, depth: usize) -> PromptRequest<'a, M> {
         PromptRequest {
             prompt: self.prompt,
             chat_history: self.chat_history,
             max_depth: depth,
             agent: self.agent,
+            overrides: self.overrides,
+            on_tool_call: self.on_tool_call,
         }
     }
 
@@ -55,6 +246,25 @@ impl<'a, M: CompletionModel> PromptRequest<'a, M> {
             chat_history: Some(history),
             max_depth: self.max_depth,
             agent: self.agent,
+            overrides: self.overrides,
+            on_tool_call: self.on_tool_call,
+        }
+    }
+
+    /// Override the agent's completion parameters (e.g.: temperature) for this request
+    pub fn with_overrides(self, overrides: RequestOverrides) -> PromptRequest<'a, M> {
+        PromptRequest { overrides, ..self }
+    }
+
+    /// Call `callback` with each tool call executed while resolving the prompt and the output of
+    /// the tool (e.g.: to log or display the intermediate steps of a multi-turn request).
+    pub fn on_tool_call(
+        self,
+        callback: impl Fn(&ToolCall, &str) + Send + Sync + 'a,
+    ) -> PromptRequest<'a, M> {
+        PromptRequest {
+            on_tool_call: Some(Box::new(callback)),
+            ..self
         }
     }
 }
@@ -74,41 +284,56 @@ impl<'a, M: CompletionModel> IntoFuture for PromptRequest<'a, M> {
 impl<M: CompletionModel> PromptRequest<'_, M> {
     async fn send(self) -> Result<String, PromptError> {
         let agent = self.agent;
+        let on_tool_call = &self.on_tool_call;
         let mut prompt = self.prompt;
-        let chat_history = if let Some(history) = self.chat_history {
-            history
-        } else {
-            &mut Vec::new()
-        };
 
-        let mut current_max_depth = 0;
-        // We need to do atleast 2 loops for 1 roundtrip (user expects normal message)
-        while current_max_depth <= self.max_depth + 1 {
-            current_max_depth += 1;
+        // Without an explicit chat history, the history is loaded from the agent's memory (if any)
+        let memory = match self.chat_history {
+            Some(_) => None,
+            None => agent.memory.clone(),
+        };
+        let mut history = match &memory {
+            Some(memory) => memory.load().await?,
+            None => vec![],
+        };
+        let loaded = history.len();
+        let chat_history = match self.chat_history {
+            Some(history) => history,
+            None => &mut history,
+        };
 
+        // Number of tool round trips done so far: a round trip executes the tool calls of the
+        // model and sends their results back, so N round trips take N + 1 completions
+        let max_round_trips = self.max_depth.max(1);
+        let mut round_trips = 0;
+        loop {
             if self.max_depth > 1 {
                 tracing::info!(
                     "Current conversation depth: {}/{}",
-                    current_max_depth,
+                    round_trips + 1,
                     self.max_depth
                 );
             }
 
-            let resp = agent
-                .completion(prompt.clone(), chat_history.to_vec())
-                .await?
+            let resp = self
+                .overrides
+                .apply(
+                    agent
+                        .completion(prompt.clone(), chat_history.to_vec())
+                        .await?,
+                )
                 .send()
                 .await?;
 
-            chat_history.push(prompt);
+            chat_history.push(prompt.clone());
 
-            let (tool_calls, texts): (Vec<_>, Vec<_>) = resp
-                .choice
+            let choice = agent.tool_call_selection.select(&resp.choice);
+            let (tool_calls, texts): (Vec<_>, Vec<_>) = choice
                 .iter()
                 .partition(|choice| matches!(choice, AssistantContent::ToolCall(_)));
 
             chat_history.push(Message::Assistant {
-                content: resp.choice.clone(),
+                content: choice.clone(),
             });
 
             if tool_calls.is_empty() {
@@ -125,13 +350,25 @@ impl<M: CompletionModel> PromptRequest<'_, M> {
                     .join("\n");
 
                 if self.max_depth > 1 {
-                    tracing::info!("Depth reached: {}/{}", current_max_depth, self.max_depth);
+                    tracing::info!("Depth reached: {}/{}", round_trips + 1, self.max_depth);
+                }
+
+                // Remember the turn (i.e.: the prompt, the tool calls and the response)
+                if let Some(memory) = &memory {
+                    memory.append(chat_history[loaded..].to_vec()).await?;
                 }
 
                 // If there are no tool calls, depth is not relevant, we can just return the merged text.
                 return Ok(merged_texts);
             }
 
+            // The model is still calling tools after the last allowed round trip: stop without
+            // executing them
+            if round_trips == max_round_trips {
+                break;
+            }
+            round_trips += 1;
+
             let tool_content = stream::iter(tool_calls)
                 .then(|choice| async move {
                     if let AssistantContent::ToolCall(tool_call) = choice {
@@ -142,6 +379,9 @@ impl<M: CompletionModel> PromptRequest<'_, M> {
                                 tool_call.function.arguments.to_string(),
                             )
                             .await?;
+                        if let Some(on_tool_call) = on_tool_call {
+                            on_tool_call(tool_call, &output);
+                        }
                         Ok(UserContent::tool_result(
                             tool_call.id.clone(),
                             OneOrMany::one(output.into()),
@@ -163,7 +403,8 @@ impl<M: CompletionModel> PromptRequest<'_, M> {
             };
         }
 
-        // If we reach here, we never resolved the final tool call. We need to do ... something.
+        // If we reach here, we never resolved the final tool call. The chat history ends with the
+        // unresolved tool calls and `prompt` is the last prompt sent.
         Err(PromptError::MaxDepthError {
             max_depth: self.max_depth,
             chat_history: chat_history.clone(),
@@ -171,3 +412,381 @@ impl<M: CompletionModel> PromptRequest<'_, M> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::{CompletionRequest, CompletionResponse, Prompt},
+    };
+
+    /// Model answering with the parameters of the completion request it received
+    #[derive(Clone)]
+    struct ParamsModel;
+
+    impl CompletionModel for ParamsModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            let params = json!({
+                "temperature": request.temperature,
+                "max_tokens": request.max_tokens,
+                "seed": request.seed,
+                "stop_sequences": request.stop_sequences,
+                "additional_params": request.additional_params,
+            });
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text(params.to_string())),
+                raw_response: (),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_with_overrides() {
+        let agent = AgentBuilder::new(ParamsModel)
+            .temperature(0.2)
+            .max_tokens(100)
+            .stop_sequences(vec!["END".to_string()])
+            .additional_params(json!({"foo": "bar", "baz": 1}))
+            .build();
+
+        let params = |response: String| serde_json::from_str::<serde_json::Value>(&response);
+
+        assert_eq!(
+            params(agent.prompt("Hi").await.unwrap()).unwrap(),
+            json!({
+                "temperature": 0.2,
+                "max_tokens": 100,
+                "seed": null,
+                "stop_sequences": ["END"],
+                "additional_params": {"foo": "bar", "baz": 1},
+            })
+        );
+
+        let overrides = RequestOverrides::new()
+            .temperature(1.2)
+            .seed(42)
+            .stop_sequences(vec!["END".to_string(), "Observation:".to_string()])
+            .additional_params(json!({"baz": 2}));
+        assert_eq!(
+            params(agent.prompt_with("Hi", overrides).await.unwrap()).unwrap(),
+            json!({
+                "temperature": 1.2,
+                "max_tokens": 100,
+                "seed": 42,
+                "stop_sequences": ["END", "Observation:"],
+                "additional_params": {"foo": "bar", "baz": 2},
+            })
+        );
+    }
+
+    #[test]
+    fn test_tool_call_selection() {
+        let choice = OneOrMany::many(vec![
+            AssistantContent::text("Let me check."),
+            AssistantContent::tool_call("call_0", "web_search", json!({})),
+            AssistantContent::tool_call("call_1", "docs_lookup", json!({})),
+            AssistantContent::tool_call("call_2", "web_search", json!({})),
+        ])
+        .unwrap();
+        let selected_ids = |selection: ToolCallSelection| {
+            selection
+                .select(&choice)
+                .iter()
+                .map(|content| match content {
+                    AssistantContent::Text(text) => text.text.clone(),
+                    AssistantContent::ToolCall(tool_call) => tool_call.id.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            selected_ids(ToolCallSelection::All),
+            vec!["Let me check.", "call_0", "call_1", "call_2"]
+        );
+        assert_eq!(
+            selected_ids(ToolCallSelection::First),
+            vec!["Let me check.", "call_0"]
+        );
+        assert_eq!(
+            selected_ids(ToolCallSelection::Last),
+            vec!["Let me check.", "call_2"]
+        );
+        assert_eq!(
+            selected_ids(ToolCallSelection::ByName(vec![
+                "calculator".to_string(),
+                "docs_lookup".to_string()
+            ])),
+            vec!["Let me check.", "call_1"]
+        );
+        assert_eq!(
+            selected_ids(ToolCallSelection::ByName(vec!["calculator".to_string()])),
+            vec!["Let me check.", "call_0"]
+        );
+    }
+
+    #[test]
+    fn test_dropped_tool_calls_warning() {
+        use std::sync::{Arc, Mutex};
+
+        /// Writer of the logs into a shared buffer
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let choice = OneOrMany::many(vec![
+            AssistantContent::tool_call("call_0", "web_search", json!({})),
+            AssistantContent::tool_call("call_1", "docs_lookup", json!({})),
+            AssistantContent::tool_call("call_2", "web_search", json!({})),
+        ])
+        .unwrap();
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            ToolCallSelection::All.select(&choice);
+            ToolCallSelection::First.select(&choice);
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(logs.lines().count(), 1);
+        assert!(logs.contains("WARN"));
+        assert!(logs.contains(
+            "Dropping 2 of the 3 tool calls of the model (First): docs_lookup (call_1), web_search (call_2)"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_with_tools() {
+        use std::sync::Mutex;
+
+        use crate::{
+            completion::ToolDefinition,
+            tool::{Tool, ToolError},
+        };
+
+        /// Model calling the `double` tool until it gets a result above 10
+        #[derive(Clone)]
+        struct DoublingModel;
+
+        impl CompletionModel for DoublingModel {
+            type Response = ();
+
+            async fn completion(
+                &self,
+                request: CompletionRequest,
+            ) -> Result<CompletionResponse<()>, CompletionError> {
+                let last = match request.chat_history.iter().last() {
+                    Some(Message::User { content }) => match content.first() {
+                        UserContent::Text(text) => text.text.parse::<u64>().unwrap(),
+                        UserContent::ToolResult(result) => match result.content.first() {
+                            crate::message::ToolResultContent::Text(text) => {
+                                text.text.parse::<u64>().unwrap()
+                            }
+                            _ => unreachable!(),
+                        },
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                };
+
+                let choice = if last > 10 {
+                    AssistantContent::text(last.to_string())
+                } else {
+                    AssistantContent::tool_call(
+                        format!("call_{last}"),
+                        "double",
+                        json!({ "x": last }),
+                    )
+                };
+
+                Ok(CompletionResponse {
+                    choice: OneOrMany::one(choice),
+                    raw_response: (),
+                })
+            }
+        }
+
+        struct Double;
+
+        impl Tool for Double {
+            const NAME: &'static str = "double";
+            type Error = ToolError;
+            type Args = serde_json::Value;
+            type Output = u64;
+
+            async fn definition(&self, _prompt: String) -> ToolDefinition {
+                ToolDefinition {
+                    name: Self::NAME.to_string(),
+                    description: "Double a number".to_string(),
+                    parameters: json!({"type": "object"}),
+                }
+            }
+
+            async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+                Ok(args["x"].as_u64().unwrap() * 2)
+            }
+        }
+
+        let agent = AgentBuilder::new(DoublingModel).tool(Double).build();
+
+        let calls = Mutex::new(vec![]);
+        let answer = agent
+            .run_with_tools("3")
+            .on_tool_call(|tool_call, output| {
+                calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {output}", tool_call.id))
+            })
+            .await
+            .unwrap();
+        assert_eq!(answer, "12");
+        assert_eq!(calls.into_inner().unwrap(), vec!["call_3: 6", "call_6: 12"]);
+
+        // The loop stops after the maximum number of iterations
+        let calls = Mutex::new(0);
+        let error = agent
+            .run_with_tools("0")
+            .multi_turn(2)
+            .on_tool_call(|_, _| *calls.lock().unwrap() += 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            PromptError::MaxDepthError { max_depth: 2, .. }
+        ));
+        assert_eq!(calls.into_inner().unwrap(), 2);
+
+        let agent = AgentBuilder::new(DoublingModel)
+            .tool(Double)
+            .max_tool_iterations(1)
+            .build();
+        let calls = Mutex::new(vec![]);
+        let error = agent
+            .run_with_tools("1")
+            .on_tool_call(|tool_call, _| calls.lock().unwrap().push(tool_call.id.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(calls.into_inner().unwrap(), vec!["call_1"]);
+        match error {
+            PromptError::MaxDepthError {
+                max_depth,
+                chat_history,
+                ..
+            } => {
+                assert_eq!(max_depth, 1);
+                assert!(matches!(
+                    chat_history.last(),
+                    Some(Message::Assistant { .. })
+                ));
+            }
+            error => panic!("Unexpected error: {error}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_with_memory() {
+        use crate::{completion::Chat, memory::InMemoryMemory};
+
+        /// Model answering with the number of messages it received
+        #[derive(Clone)]
+        struct CountingModel;
+
+        impl CompletionModel for CountingModel {
+            type Response = ();
+
+            async fn completion(
+                &self,
+                request: CompletionRequest,
+            ) -> Result<CompletionResponse<()>, CompletionError> {
+                Ok(CompletionResponse {
+                    choice: OneOrMany::one(AssistantContent::text(
+                        request.chat_history.len().to_string(),
+                    )),
+                    raw_response: (),
+                })
+            }
+        }
+
+        let memory = InMemoryMemory::new();
+        let agent = AgentBuilder::new(CountingModel)
+            .memory(memory.clone())
+            .build();
+
+        assert_eq!(agent.prompt("Hello").await.unwrap(), "1");
+        assert_eq!(agent.prompt("Hello again").await.unwrap(), "3");
+        assert_eq!(memory.messages().len(), 4);
+
+        // An explicit chat history takes precedence over the memory
+        assert_eq!(agent.chat("Hi", vec![]).await.unwrap(), "1");
+        assert_eq!(memory.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_history() {
+        /// Model answering with the number of messages it received
+        #[derive(Clone)]
+        struct CountingModel;
+
+        impl CompletionModel for CountingModel {
+            type Response = ();
+
+            async fn completion(
+                &self,
+                request: CompletionRequest,
+            ) -> Result<CompletionResponse<()>, CompletionError> {
+                Ok(CompletionResponse {
+                    choice: OneOrMany::one(AssistantContent::text(
+                        request.chat_history.len().to_string(),
+                    )),
+                    raw_response: (),
+                })
+            }
+        }
+
+        let agent = AgentBuilder::new(CountingModel).build();
+
+        let (answer, history) = agent.chat_with_history("Hello", vec![]).await.unwrap();
+        assert_eq!(answer, "1");
+        assert_eq!(
+            history,
+            vec![Message::user("Hello"), Message::assistant("1"),]
+        );
+
+        // The history round trips through JSON to resume the conversation
+        let history: Vec<Message> =
+            serde_json::from_str(&serde_json::to_string(&history).unwrap()).unwrap();
+        let (answer, history) = agent
+            .chat_with_history("Hello again", history)
+            .await
+            .unwrap();
+        assert_eq!(answer, "3");
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[2], Message::user("Hello again"));
+    }
+}
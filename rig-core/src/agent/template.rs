@@ -0,0 +1,275 @@
+use std::{collections::HashMap, fmt::Display};
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    /// The template has an unmatched brace or an invalid variable name
+    #[error("InvalidTemplate: {0}")]
+    InvalidTemplate(String),
+
+    /// The context has no value for some variables of the template
+    #[error("MissingVariables: {}", .0.join(", "))]
+    MissingVariables(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// Prompt (or preamble) with `{variable}` placeholders, rendered with the values of a
+/// [TemplateContext]. Literal braces are escaped by doubling them (`{{` and `}}`), e.g.: to
+/// include JSON in the template.
+///
+/// Rendering fails with [TemplateError::MissingVariables] if the context lacks a value for any
+/// variable, instead of leaving a blank in the prompt.
+///
+/// # Example
+/// ```
+/// use rig::agent::{PromptTemplate, TemplateContext};
+///
+/// let template = PromptTemplate::new("Summarize the {kind} below in {words} words:\n{text}")?;
+///
+/// let context = TemplateContext::new()
+///     .with("kind", "article")
+///     .with("words", 50)
+///     .with("text", article);
+///
+/// let summary = agent.prompt_template(&template, &context)?.await?;
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PromptTemplate {
+    /// Parse `template`, failing with [TemplateError::InvalidTemplate] on an unmatched brace or
+    /// a variable name that is not made of letters, digits and underscores.
+    pub fn new(template: &str) -> Result<Self, TemplateError> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|(_, c)| *c == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|(_, c)| *c == '}').is_some() => text.push('}'),
+                '{' => {
+                    let end = template[position..].find('}').ok_or_else(|| {
+                        TemplateError::InvalidTemplate(format!(
+                            "Unmatched '{{' at position {position}"
+                        ))
+                    })?;
+                    let name = template[position + 1..position + end].trim();
+
+                    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return Err(TemplateError::InvalidTemplate(format!(
+                            "Invalid variable name '{name}' at position {position}"
+                        )));
+                    }
+
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Variable(name.to_string()));
+
+                    while chars.next_if(|(i, _)| *i <= position + end).is_some() {}
+                }
+                '}' => {
+                    return Err(TemplateError::InvalidTemplate(format!(
+                        "Unmatched '}}' at position {position}"
+                    )))
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Names of the variables of the template, in order of first appearance.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = vec![];
+        for segment in &self.segments {
+            if let Segment::Variable(name) = segment {
+                if !variables.contains(&name.as_str()) {
+                    variables.push(name.as_str());
+                }
+            }
+        }
+        variables
+    }
+
+    /// Render the template with the values of `context`. Values of the context that are not
+    /// variables of the template are ignored.
+    pub fn render(&self, context: &TemplateContext) -> Result<String, TemplateError> {
+        let missing = self
+            .variables()
+            .into_iter()
+            .filter(|name| !context.values.contains_key(*name))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(TemplateError::MissingVariables(missing));
+        }
+
+        Ok(self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Variable(name) => context.values[name].as_str(),
+            })
+            .collect())
+    }
+}
+
+/// Values of the variables of a [PromptTemplate]. Values are inserted verbatim: braces in a
+/// value do not need to be escaped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TemplateContext {
+    values: HashMap<String, String>,
+}
+
+impl TemplateContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of the variable `name`, formatted with its [Display] implementation.
+    pub fn with(mut self, name: &str, value: impl Display) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Set the value of the variable `name`, formatted with its [Display] implementation.
+    pub fn insert(&mut self, name: &str, value: impl Display) {
+        self.values.insert(name.to_string(), value.to_string());
+    }
+}
+
+impl<K: Into<String>, V: Display> FromIterator<(K, V)> for TemplateContext {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            values: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PromptTemplate, TemplateContext, TemplateError};
+    use crate::{
+        agent::AgentBuilder,
+        completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse},
+        message::{AssistantContent, Message, UserContent},
+        OneOrMany,
+    };
+
+    #[test]
+    fn test_render() {
+        let template =
+            PromptTemplate::new("Hello {name}, you are {age}. {{\"name\": \"{ name }\"}}").unwrap();
+        assert_eq!(template.variables(), vec!["name", "age"]);
+
+        let context = TemplateContext::new().with("name", "Ada").with("age", 36);
+        assert_eq!(
+            template.render(&context).unwrap(),
+            "Hello Ada, you are 36. {\"name\": \"Ada\"}"
+        );
+
+        let context = [("name", "{age}")].into_iter().collect::<TemplateContext>();
+        assert_eq!(
+            template.render(&context),
+            Err(TemplateError::MissingVariables(vec!["age".to_string()]))
+        );
+        assert_eq!(
+            PromptTemplate::new("").unwrap().render(&context).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for (template, error) in [
+            ("Hello {name", "Unmatched '{' at position 6"),
+            ("Hello name}", "Unmatched '}' at position 10"),
+            ("Hello {}", "Invalid variable name '' at position 6"),
+            (
+                "Hello {first name}",
+                "Invalid variable name 'first name' at position 6",
+            ),
+        ] {
+            assert_eq!(
+                PromptTemplate::new(template),
+                Err(TemplateError::InvalidTemplate(error.to_string()))
+            );
+        }
+    }
+
+    /// Model answering with the preamble and the prompt
+    #[derive(Clone)]
+    struct EchoModel;
+
+    impl CompletionModel for EchoModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            let prompt = match request.chat_history.iter().last() {
+                Some(Message::User { content }) => match content.first() {
+                    UserContent::Text(text) => text.text,
+                    _ => String::new(),
+                },
+                _ => String::new(),
+            };
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text(format!(
+                    "{} / {prompt}",
+                    request.preamble.unwrap_or_default()
+                ))),
+                raw_response: (),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_templates() {
+        let context = TemplateContext::new()
+            .with("language", "French")
+            .with("text", "Hello");
+
+        let agent = AgentBuilder::new(EchoModel)
+            .preamble_template(
+                &PromptTemplate::new("Answer in {language}.").unwrap(),
+                &context,
+            )
+            .unwrap()
+            .build();
+        assert_eq!(agent.preamble, "Answer in French.");
+
+        let template = PromptTemplate::new("Translate: {text}").unwrap();
+        let answer = agent
+            .prompt_template(&template, &context)
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(answer, "Answer in French. / Translate: Hello");
+
+        assert!(agent
+            .prompt_template(&template, &TemplateContext::new())
+            .is_err());
+    }
+}
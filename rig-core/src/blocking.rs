@@ -0,0 +1,170 @@
+//! Blocking wrappers of the async API, for scripts and CLI tools making a few calls without
+//! running an async runtime. Requires the `blocking` feature.
+//!
+//! # Runtime
+//! The HTTP clients of the providers need a tokio runtime. The blocking wrappers run their
+//! future on a single-threaded tokio runtime created on first use and shared by all the
+//! blocking calls of the process, so that connections are reused between calls. This runtime
+//! lives until the end of the process.
+//!
+//! The blocking wrappers must not be called from async code (i.e.: from within another tokio
+//! runtime), where they panic: use the async API there instead.
+//!
+//! # Example
+//! ```
+//! use rig::{blocking::PromptBlocking, providers::openai};
+//!
+//! fn main() {
+//!     let agent = openai::Client::from_env().agent("gpt-4o").build();
+//!
+//!     let answer = agent.prompt_blocking("Who are you?").expect("Failed to prompt the agent");
+//!     println!("{answer}");
+//! }
+//! ```
+
+use std::{future::IntoFuture, sync::OnceLock};
+
+use crate::{
+    agent::PromptRequest,
+    completion::{
+        Chat, CompletionError, CompletionModel, CompletionRequest, CompletionResponse, Message,
+        Prompt, PromptError,
+    },
+    embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+};
+
+/// Run `future` to completion on the runtime shared by the blocking calls, blocking the current
+/// thread. Panics if called from within a tokio runtime.
+pub fn block_on<F: IntoFuture>(future: F) -> F::Output {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Blocking runtime should build")
+        })
+        .block_on(future.into_future())
+}
+
+/// Blocking version of [Prompt].
+pub trait PromptBlocking: Prompt {
+    /// Blocking version of [Prompt::prompt].
+    #[allow(clippy::result_large_err)]
+    fn prompt_blocking(&self, prompt: impl Into<Message> + Send) -> Result<String, PromptError> {
+        block_on(self.prompt(prompt))
+    }
+}
+
+impl<T: Prompt> PromptBlocking for T {}
+
+/// Blocking version of [Chat].
+pub trait ChatBlocking: Chat {
+    /// Blocking version of [Chat::chat].
+    #[allow(clippy::result_large_err)]
+    fn chat_blocking(
+        &self,
+        prompt: impl Into<Message> + Send,
+        chat_history: Vec<Message>,
+    ) -> Result<String, PromptError> {
+        block_on(self.chat(prompt, chat_history))
+    }
+}
+
+impl<T: Chat> ChatBlocking for T {}
+
+/// Blocking version of [CompletionModel].
+pub trait CompletionModelBlocking: CompletionModel {
+    /// Blocking version of [CompletionModel::completion].
+    fn completion_blocking(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        block_on(self.completion(request))
+    }
+}
+
+impl<M: CompletionModel> CompletionModelBlocking for M {}
+
+/// Blocking version of [EmbeddingModel].
+pub trait EmbeddingModelBlocking: EmbeddingModel {
+    /// Blocking version of [EmbeddingModel::embed_text].
+    fn embed_text_blocking(&self, text: &str) -> Result<Embedding, EmbeddingError> {
+        block_on(self.embed_text(text))
+    }
+
+    /// Blocking version of [EmbeddingModel::embed_texts].
+    fn embed_texts_blocking(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        block_on(self.embed_texts(texts))
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModelBlocking for M {}
+
+/// Blocking version of a [PromptRequest], to prompt with options
+/// (`agent.prompt("...").multi_turn(2).wait()`). Use [block_on] for other futures.
+pub trait Wait {
+    /// Send the prompt request, blocking the current thread (see [block_on]).
+    #[allow(clippy::result_large_err)]
+    fn wait(self) -> Result<String, PromptError>;
+}
+
+impl<M: CompletionModel> Wait for PromptRequest<'_, M> {
+    fn wait(self) -> Result<String, PromptError> {
+        block_on(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{agent::AgentBuilder, message::AssistantContent, OneOrMany};
+
+    /// Model echoing the prompt
+    #[derive(Clone)]
+    struct EchoModel;
+
+    impl CompletionModel for EchoModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            let prompt = match request.chat_history.iter().last() {
+                Some(Message::User { content }) => match content.first() {
+                    crate::message::UserContent::Text(text) => text.text,
+                    _ => String::new(),
+                },
+                _ => String::new(),
+            };
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text(prompt)),
+                raw_response: (),
+            })
+        }
+    }
+
+    #[test]
+    fn test_blocking_wrappers() {
+        let agent = AgentBuilder::new(EchoModel).build();
+
+        assert_eq!(agent.prompt_blocking("Hello").unwrap(), "Hello");
+        assert_eq!(agent.chat_blocking("Hi", vec![]).unwrap(), "Hi");
+        assert_eq!(agent.prompt("Bye").multi_turn(1).wait().unwrap(), "Bye");
+
+        let request = crate::completion::Completion::completion(&agent, "Hey", vec![]);
+        let response = EchoModel
+            .completion_blocking(block_on(request).unwrap().build())
+            .unwrap();
+        assert_eq!(
+            response.choice,
+            OneOrMany::one(AssistantContent::text("Hey"))
+        );
+    }
+}
@@ -62,8 +62,9 @@
 //!
 //! For more information on how to use the completion functionality, refer to the documentation of
 //! the individual traits, structs, and enums defined in this module.
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use futures::future::{self, BoxFuture, Either};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -99,6 +100,39 @@ pub enum CompletionError {
     /// Error returned by the completion model provider
     #[error("ProviderError: {0}")]
     ProviderError(String),
+
+    /// The provider rejected the credentials of the request
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// The provider rate limited the request. `retry_after` is the delay requested by the
+    /// provider before retrying, if any
+    #[error("RateLimited: {message}")]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+        message: String,
+    },
+
+    /// The provider rejected the request as invalid (e.g.: unknown parameter or model)
+    #[error("InvalidRequest: {0}")]
+    InvalidRequest(String),
+
+    /// The provider failed to process the request
+    #[error("ServerError ({status}): {message}")]
+    ServerError { status: u16, message: String },
+
+    /// The arguments of a tool call returned by the model do not match the tool definition
+    #[error("InvalidToolCall: {0}")]
+    InvalidToolCall(String),
+
+    /// The provider returned neither text nor tool calls (e.g.: when the response was blocked by
+    /// a content filter)
+    #[error("EmptyResponse: {0}")]
+    EmptyResponse(String),
+
+    /// The completion was cancelled before the provider responded
+    #[error("Cancelled")]
+    Cancelled,
 }
 
 #[derive(Debug, Error)]
@@ -109,6 +143,9 @@ pub enum PromptError {
     #[error("ToolCallError: {0}")]
     ToolError(#[from] ToolSetError),
 
+    #[error("MemoryError: {0}")]
+    MemoryError(#[from] crate::memory::MemoryError),
+
     #[error("MaxDepthError: (reached limit: {max_depth})")]
     MaxDepthError {
         max_depth: usize,
@@ -154,6 +191,86 @@ pub struct ToolDefinition {
     pub parameters: serde_json::Value,
 }
 
+impl ToolDefinition {
+    /// Check that the `arguments` of a call to the tool match the JSON schema of its parameters.
+    ///
+    /// Only the `type`, `enum`, `required`, `properties` and `items` keywords are checked, which
+    /// is enough to catch missing and mistyped arguments.
+    pub fn validate_arguments(&self, arguments: &serde_json::Value) -> Result<(), CompletionError> {
+        validate_schema(&self.parameters, arguments, "arguments").map_err(|message| {
+            CompletionError::InvalidToolCall(format!("Tool `{}`: {message}", self.name))
+        })
+    }
+}
+
+fn validate_schema(
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    path: &str,
+) -> Result<(), String> {
+    use serde_json::Value;
+
+    let types = match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    if !types.is_empty() && !types.iter().any(|ty| matches_type(ty, value)) {
+        return Err(format!("{path} should be of type {}", types.join(" or ")));
+    }
+
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        if !values.contains(value) {
+            return Err(format!("{path} should be one of {}", schema["enum"]));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                if let Some(key) = required
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .find(|key| !object.contains_key(*key))
+                {
+                    return Err(format!("{path}.{key} is required"));
+                }
+            }
+
+            if let Some(Value::Object(properties)) = schema.get("properties") {
+                for (key, value) in object {
+                    if let Some(schema) = properties.get(key) {
+                        validate_schema(schema, value, &format!("{path}.{key}"))?;
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_schema(schema, item, &format!("{path}[{i}]"))?;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn matches_type(ty: &str, value: &serde_json::Value) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
 // ================================================================
 // Implementations
 // ================================================================
@@ -221,6 +338,50 @@ pub struct CompletionResponse<T> {
     pub raw_response: T,
 }
 
+impl<T> CompletionResponse<T> {
+    /// The text of the response, i.e.: its text content concatenated, or `None` if the response
+    /// has no text content (e.g.: it only contains tool calls).
+    pub fn text(&self) -> Option<String> {
+        let texts = self
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        (!texts.is_empty()).then(|| texts.concat())
+    }
+
+    /// Number of tool calls of the response.
+    pub fn tool_call_count(&self) -> usize {
+        self.choice
+            .iter()
+            .filter(|content| matches!(content, AssistantContent::ToolCall(_)))
+            .count()
+    }
+
+    /// Check the tool calls of the response against the given tool definitions, returning
+    /// [CompletionError::InvalidToolCall] if a call targets an unknown tool or if its arguments
+    /// do not match the tool's parameters (see [ToolDefinition::validate_arguments]).
+    pub fn validate_tool_calls(&self, tools: &[ToolDefinition]) -> Result<(), CompletionError> {
+        self.choice.iter().try_for_each(|content| match content {
+            AssistantContent::ToolCall(tool_call) => {
+                let name = &tool_call.function.name;
+                tools
+                    .iter()
+                    .find(|tool| &tool.name == name)
+                    .ok_or_else(|| {
+                        CompletionError::InvalidToolCall(format!("Unknown tool `{name}`"))
+                    })?
+                    .validate_arguments(&tool_call.function.arguments)
+            }
+            _ => Ok(()),
+        })
+    }
+}
+
 /// Trait defining a completion model that can be used to generate completion responses.
 /// This trait is meant to be implemented by the user to define a custom completion model,
 /// either from a third party provider (e.g.: OpenAI) or a local model.
@@ -235,13 +396,117 @@ pub trait CompletionModel: Clone + Send + Sync {
     ) -> impl std::future::Future<Output = Result<CompletionResponse<Self::Response>, CompletionError>>
            + Send;
 
+    /// Generates a completion response for the given completion request, unless `cancel`
+    /// completes first, in which case the request is aborted (releasing its connection) and
+    /// [CompletionError::Cancelled] is returned.
+    ///
+    /// `cancel` can be any future, e.g.: `token.cancelled()` for a `tokio_util` `CancellationToken`.
+    fn completion_with_cancel(
+        &self,
+        request: CompletionRequest,
+        cancel: impl std::future::Future<Output = ()> + Send,
+    ) -> impl std::future::Future<Output = Result<CompletionResponse<Self::Response>, CompletionError>>
+           + Send {
+        async move {
+            let completion = std::pin::pin!(self.completion(request));
+            let cancel = std::pin::pin!(cancel);
+
+            match future::select(completion, cancel).await {
+                Either::Left((response, _)) => response,
+                Either::Right(((), _)) => Err(CompletionError::Cancelled),
+            }
+        }
+    }
+
     /// Generates a completion request builder for the given `prompt`.
     fn completion_request(&self, prompt: impl Into<Message>) -> CompletionRequestBuilder<Self> {
         CompletionRequestBuilder::new(self.clone(), prompt)
     }
+
+    /// Size (in tokens) of the context window of the model, shared by the request (i.e.: the
+    /// preamble, chat history, documents and tools) and the response, or `None` if unknown.
+    /// Useful to size the budget of a [HistoryTruncation](crate::memory::HistoryTruncation).
+    fn max_context_tokens(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Separator inserted between the fragments of a preamble by [join_preamble].
+pub const PREAMBLE_SEPARATOR: &str = "\n\n";
+
+/// Join preamble fragments into a single preamble, in order, separated by [PREAMBLE_SEPARATOR].
+/// Fragments are trimmed and empty fragments are skipped. Returns `None` if no fragment is left.
+pub fn join_preamble<S: AsRef<str>>(fragments: impl IntoIterator<Item = S>) -> Option<String> {
+    let fragments = fragments
+        .into_iter()
+        .filter_map(|fragment| {
+            let fragment = fragment.as_ref().trim();
+            (!fragment.is_empty()).then(|| fragment.to_string())
+        })
+        .collect::<Vec<_>>();
+
+    (!fragments.is_empty()).then(|| fragments.join(PREAMBLE_SEPARATOR))
+}
+
+/// Object-safe version of [CompletionModel], with the raw response erased to a `serde_json::Value`.
+/// Implemented by every [CompletionModel] whose raw response can be serialized, so that models
+/// of different providers can be stored and swapped at runtime (e.g.: in a registry keyed by name).
+///
+/// `Arc<dyn CompletionModelDyn>` implements [CompletionModel] and can be used to build agents.
+pub trait CompletionModelDyn: Send + Sync {
+    fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'_, Result<CompletionResponse<serde_json::Value>, CompletionError>>;
+
+    fn max_context_tokens(&self) -> Option<usize>;
+}
+
+impl<M> CompletionModelDyn for M
+where
+    M: CompletionModel,
+    M::Response: Serialize,
+{
+    fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxFuture<'_, Result<CompletionResponse<serde_json::Value>, CompletionError>> {
+        Box::pin(async move {
+            let response = CompletionModel::completion(self, request).await?;
+
+            Ok(CompletionResponse {
+                choice: response.choice,
+                raw_response: serde_json::to_value(response.raw_response)?,
+            })
+        })
+    }
+
+    fn max_context_tokens(&self) -> Option<usize> {
+        CompletionModel::max_context_tokens(self)
+    }
+}
+
+impl CompletionModel for Arc<dyn CompletionModelDyn> {
+    type Response = serde_json::Value;
+
+    #[cfg_attr(feature = "worker", worker::send)]
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        CompletionModelDyn::completion(self.as_ref(), request).await
+    }
+
+    fn max_context_tokens(&self) -> Option<usize> {
+        CompletionModelDyn::max_context_tokens(self.as_ref())
+    }
 }
 
 /// Struct representing a general completion request that can be sent to a completion model provider.
+///
+/// Providers forward the sampling parameters (e.g.: `top_p`) under their own names, and ignore
+/// with a warning the ones they do not support (e.g.: `top_k` for OpenAI).
+#[derive(Clone, Debug)]
 pub struct CompletionRequest {
     /// The preamble to be sent to the completion model provider
     pub preamble: Option<String>,
@@ -256,6 +521,18 @@ pub struct CompletionRequest {
     pub temperature: Option<f64>,
     /// The max tokens to be sent to the completion model provider
     pub max_tokens: Option<u64>,
+    /// The nucleus sampling (top-p) probability mass to be sent to the completion model provider
+    pub top_p: Option<f64>,
+    /// The top-k sampling cutoff to be sent to the completion model provider
+    pub top_k: Option<u64>,
+    /// The sequences that stop the generation, to be sent to the completion model provider
+    pub stop_sequences: Option<Vec<String>>,
+    /// The random seed to be sent to the completion model provider, for reproducible completions
+    pub seed: Option<u64>,
+    /// The frequency penalty to be sent to the completion model provider
+    pub frequency_penalty: Option<f64>,
+    /// The presence penalty to be sent to the completion model provider
+    pub presence_penalty: Option<f64>,
     /// Additional provider-specific parameters to be sent to the completion model provider
     pub additional_params: Option<serde_json::Value>,
 }
@@ -289,6 +566,96 @@ impl CompletionRequest {
             content: OneOrMany::many(messages).expect("There will be atleast one document"),
         })
     }
+
+    /// Sampling parameters set on the request (e.g.: top-p), as a JSON object keyed by the names
+    /// the provider gives them (see [SamplingParams]). Parameters the provider does not support
+    /// are left out with a warning rather than silently ignored.
+    ///
+    /// Providers call it before moving the fields of the request into their request body.
+    pub(crate) fn sampling_params(&self, params: &SamplingParams) -> serde_json::Value {
+        let values = [
+            (
+                "top_p",
+                params.top_p,
+                self.top_p.map(serde_json::Value::from),
+            ),
+            (
+                "top_k",
+                params.top_k,
+                self.top_k.map(serde_json::Value::from),
+            ),
+            (
+                "stop_sequences",
+                params.stop_sequences,
+                self.stop_sequences.clone().map(serde_json::Value::from),
+            ),
+            ("seed", params.seed, self.seed.map(serde_json::Value::from)),
+            (
+                "frequency_penalty",
+                params.frequency_penalty,
+                self.frequency_penalty.map(serde_json::Value::from),
+            ),
+            (
+                "presence_penalty",
+                params.presence_penalty,
+                self.presence_penalty.map(serde_json::Value::from),
+            ),
+        ];
+
+        let mut object = serde_json::Map::new();
+        for (name, key, value) in values {
+            match (key, value) {
+                (Some(key), Some(value)) => {
+                    object.insert(key.to_string(), value);
+                }
+                (None, Some(_)) => tracing::warn!(target: "rig",
+                    "{} does not support the `{name}` parameter, ignoring it",
+                    params.provider
+                ),
+                (_, None) => {}
+            }
+        }
+
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Names of the sampling parameters of a [CompletionRequest] in the request body of a provider,
+/// `None` for the parameters the provider does not support.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SamplingParams {
+    /// Name of the provider, for the warnings about unsupported parameters
+    pub provider: &'static str,
+    pub top_p: Option<&'static str>,
+    pub top_k: Option<&'static str>,
+    pub stop_sequences: Option<&'static str>,
+    pub seed: Option<&'static str>,
+    pub frequency_penalty: Option<&'static str>,
+    pub presence_penalty: Option<&'static str>,
+}
+
+impl SamplingParams {
+    /// No supported parameter
+    pub(crate) const NONE: Self = Self {
+        provider: "",
+        top_p: None,
+        top_k: None,
+        stop_sequences: None,
+        seed: None,
+        frequency_penalty: None,
+        presence_penalty: None,
+    };
+
+    /// Parameters of the OpenAI chat completions API, shared by the OpenAI compatible providers
+    pub(crate) const OPENAI: Self = Self {
+        provider: "OpenAI",
+        top_p: Some("top_p"),
+        stop_sequences: Some("stop"),
+        seed: Some("seed"),
+        frequency_penalty: Some("frequency_penalty"),
+        presence_penalty: Some("presence_penalty"),
+        ..Self::NONE
+    };
 }
 
 /// Builder struct for constructing a completion request.
@@ -344,6 +711,12 @@ pub struct CompletionRequestBuilder<M: CompletionModel> {
     tools: Vec<ToolDefinition>,
     temperature: Option<f64>,
     max_tokens: Option<u64>,
+    top_p: Option<f64>,
+    top_k: Option<u64>,
+    stop_sequences: Option<Vec<String>>,
+    seed: Option<u64>,
+    frequency_penalty: Option<f64>,
+    presence_penalty: Option<f64>,
     additional_params: Option<serde_json::Value>,
 }
 
@@ -358,6 +731,12 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
             additional_params: None,
         }
     }
@@ -368,6 +747,16 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
         self
     }
 
+    /// Sets the preamble for the completion request from a list of fragments (e.g.: persona,
+    /// safety and task instructions), joined in order as described in [join_preamble].
+    pub fn preamble_fragments<S: AsRef<str>>(
+        mut self,
+        fragments: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.preamble = join_preamble(fragments);
+        self
+    }
+
     /// Adds a message to the chat history for the completion request.
     pub fn message(mut self, message: Message) -> Self {
         self.chat_history.push(message);
@@ -409,9 +798,17 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
 
     /// Adds additional parameters to the completion request.
     /// This can be used to set additional provider-specific parameters. For example,
-    /// Cohere's completion models accept a `connectors` parameter that can be used to
-    /// specify the data connectors used by Cohere when executing the completion
-    /// (see `examples/cohere_connectors.rs`).
+    /// Cohere's completion models accept a `safety_mode` parameter that can be used to
+    /// control the safety instructions added to the prompt.
+    ///
+    /// Note: Cohere's chat API (v2) no longer supports `connectors` (e.g.: web search).
+    /// Retrieved content should be passed to the model as documents instead.
+    ///
+    /// Calling this method several times merges the parameters shallowly: top level keys set
+    /// again replace their previous value, arrays included. The parameters are merged the same
+    /// way into the request body built by the provider, so e.g. a `tools` array replaces the
+    /// tools of the request (see the provider's documentation for alternatives, like
+    /// [with_array_merge](crate::providers::cohere::CompletionModel::with_array_merge) for Cohere).
     pub fn additional_params(mut self, additional_params: serde_json::Value) -> Self {
         match self.additional_params {
             Some(params) => {
@@ -426,9 +823,11 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
 
     /// Sets the additional parameters for the completion request.
     /// This can be used to set additional provider-specific parameters. For example,
-    /// Cohere's completion models accept a `connectors` parameter that can be used to
-    /// specify the data connectors used by Cohere when executing the completion
-    /// (see `examples/cohere_connectors.rs`).
+    /// Cohere's completion models accept a `safety_mode` parameter that can be used to
+    /// control the safety instructions added to the prompt.
+    ///
+    /// Note: Cohere's chat API (v2) no longer supports `connectors` (e.g.: web search).
+    /// Retrieved content should be passed to the model as documents instead.
     pub fn additional_params_opt(mut self, additional_params: Option<serde_json::Value>) -> Self {
         self.additional_params = additional_params;
         self
@@ -460,6 +859,94 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
         self
     }
 
+    /// Sets the nucleus sampling (top-p) probability mass for the completion request.
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the nucleus sampling (top-p) probability mass for the completion request.
+    pub fn top_p_opt(mut self, top_p: Option<f64>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    /// Sets the top-k sampling cutoff for the completion request.
+    pub fn top_k(mut self, top_k: u64) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Sets the top-k sampling cutoff for the completion request.
+    pub fn top_k_opt(mut self, top_k: Option<u64>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Adds a stop sequence to the completion request, unless it already has it.
+    pub fn stop_sequence(mut self, stop_sequence: impl Into<String>) -> Self {
+        let stop_sequence = stop_sequence.into();
+        let stop_sequences = self.stop_sequences.get_or_insert_with(Vec::new);
+        if !stop_sequences.contains(&stop_sequence) {
+            stop_sequences.push(stop_sequence);
+        }
+        self
+    }
+
+    /// Sets the stop sequences for the completion request.
+    pub fn stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = Some(stop_sequences);
+        self
+    }
+
+    /// Sets the stop sequences for the completion request.
+    pub fn stop_sequences_opt(mut self, stop_sequences: Option<Vec<String>>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+
+    /// Sets the random seed for the completion request.
+    /// Providers that support it make a best effort to return the same completion for the
+    /// same request and seed.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets the random seed for the completion request.
+    pub fn seed_opt(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the frequency penalty for the completion request, penalizing tokens proportionally
+    /// to how often they already appeared in the generated text. The valid range depends on the
+    /// provider (e.g.: -2.0 to 2.0 for OpenAI, 0.0 to 1.0 for Cohere).
+    pub fn frequency_penalty(mut self, frequency_penalty: f64) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    /// Sets the frequency penalty for the completion request.
+    pub fn frequency_penalty_opt(mut self, frequency_penalty: Option<f64>) -> Self {
+        self.frequency_penalty = frequency_penalty;
+        self
+    }
+
+    /// Sets the presence penalty for the completion request, penalizing tokens that already
+    /// appeared in the generated text. The valid range depends on the provider (e.g.: -2.0 to
+    /// 2.0 for OpenAI, 0.0 to 1.0 for Cohere).
+    pub fn presence_penalty(mut self, presence_penalty: f64) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    /// Sets the presence penalty for the completion request.
+    pub fn presence_penalty_opt(mut self, presence_penalty: Option<f64>) -> Self {
+        self.presence_penalty = presence_penalty;
+        self
+    }
+
     /// Builds the completion request.
     pub fn build(self) -> CompletionRequest {
         let chat_history = OneOrMany::many([self.chat_history, vec![self.prompt]].concat())
@@ -472,6 +959,12 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             tools: self.tools,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            stop_sequences: self.stop_sequences,
+            seed: self.seed,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
             additional_params: self.additional_params,
         }
     }
@@ -552,6 +1045,12 @@ mod tests {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
             additional_params: None,
         };
 
@@ -583,9 +1082,225 @@ mod tests {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
             additional_params: None,
         };
 
         assert_eq!(request.normalized_documents(), None);
     }
+
+    #[derive(Clone)]
+    struct EchoModel;
+
+    #[derive(Serialize)]
+    struct EchoResponse {
+        prompt: String,
+    }
+
+    impl CompletionModel for EchoModel {
+        type Response = EchoResponse;
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+            let prompt = match request.chat_history.into_iter().last().unwrap() {
+                Message::User { content } => match content.first() {
+                    UserContent::Text(text) => text.text,
+                    _ => String::new(),
+                },
+                _ => String::new(),
+            };
+
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text(&prompt)),
+                raw_response: EchoResponse { prompt },
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_model_dyn() {
+        let models: HashMap<&str, Arc<dyn CompletionModelDyn>> =
+            HashMap::from([("echo", Arc::new(EchoModel) as Arc<dyn CompletionModelDyn>)]);
+        let model = models["echo"].clone();
+
+        let response = model.completion_request("Hello").send().await.unwrap();
+        assert_eq!(response.choice.first(), AssistantContent::text("Hello"));
+        assert_eq!(
+            response.raw_response,
+            serde_json::json!({"prompt": "Hello"})
+        );
+
+        let agent = crate::agent::AgentBuilder::new(model).build();
+        assert_eq!(
+            crate::completion::Prompt::prompt(&agent, "Hi")
+                .await
+                .unwrap(),
+            "Hi"
+        );
+    }
+
+    #[test]
+    fn test_join_preamble() {
+        assert_eq!(
+            join_preamble(["You are a helpful assistant.", "", "  Never share PII.\n"]),
+            Some("You are a helpful assistant.\n\nNever share PII.".to_string())
+        );
+        assert_eq!(join_preamble(Vec::<String>::new()), None);
+        assert_eq!(join_preamble([" "]), None);
+
+        let agent = crate::agent::AgentBuilder::new(EchoModel)
+            .preamble_fragments(["You are a helpful assistant."])
+            .preamble_fragment("Never share PII.")
+            .preamble_fragment("Answer in French.")
+            .build();
+        assert_eq!(
+            agent.preamble,
+            "You are a helpful assistant.\n\nNever share PII.\n\nAnswer in French."
+        );
+    }
+
+    #[derive(Clone)]
+    struct PendingModel;
+
+    impl CompletionModel for PendingModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+            future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_cancel() {
+        let request = PendingModel.completion_request("Hello").build();
+        let result = PendingModel
+            .completion_with_cancel(
+                request,
+                tokio::time::sleep(std::time::Duration::from_millis(10)),
+            )
+            .await;
+        assert!(matches!(result, Err(CompletionError::Cancelled)));
+
+        let request = EchoModel.completion_request("Hello").build();
+        let response = EchoModel
+            .completion_with_cancel(request, future::pending())
+            .await
+            .unwrap();
+        assert_eq!(response.choice.first(), AssistantContent::text("Hello"));
+    }
+
+    #[test]
+    fn test_completion_response_text() {
+        let response = CompletionResponse {
+            choice: OneOrMany::many(vec![
+                AssistantContent::text("Hello, "),
+                AssistantContent::tool_call("call_0", "greet", serde_json::json!({})),
+                AssistantContent::text("world!"),
+            ])
+            .unwrap(),
+            raw_response: (),
+        };
+        assert_eq!(response.text(), Some("Hello, world!".to_string()));
+
+        let response = CompletionResponse {
+            choice: OneOrMany::one(AssistantContent::tool_call(
+                "call_0",
+                "greet",
+                serde_json::json!({}),
+            )),
+            raw_response: (),
+        };
+        assert_eq!(response.text(), None);
+    }
+
+    #[test]
+    fn test_validate_tool_arguments() {
+        let tool = ToolDefinition {
+            name: "search".to_string(),
+            description: "Search the web".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer" },
+                    "sources": {
+                        "type": "array",
+                        "items": { "type": "string", "enum": ["web", "news"] }
+                    }
+                },
+                "required": ["query"]
+            }),
+        };
+
+        assert!(tool
+            .validate_arguments(
+                &serde_json::json!({"query": "rust", "limit": 5, "sources": ["news"]})
+            )
+            .is_ok());
+
+        let error = |arguments| tool.validate_arguments(&arguments).unwrap_err().to_string();
+        assert_eq!(
+            error(serde_json::json!({"limit": 5})),
+            "InvalidToolCall: Tool `search`: arguments.query is required"
+        );
+        assert_eq!(
+            error(serde_json::json!({"query": "rust", "limit": "5"})),
+            "InvalidToolCall: Tool `search`: arguments.limit should be of type integer"
+        );
+        assert_eq!(
+            error(serde_json::json!({"query": "rust", "sources": ["blogs"]})),
+            r#"InvalidToolCall: Tool `search`: arguments.sources[0] should be one of ["web","news"]"#
+        );
+        assert_eq!(
+            error(serde_json::json!("rust")),
+            "InvalidToolCall: Tool `search`: arguments should be of type object"
+        );
+    }
+
+    #[test]
+    fn test_validate_tool_calls() {
+        let tool = ToolDefinition {
+            name: "greet".to_string(),
+            description: "Greet someone".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": { "name": { "type": "string" } },
+                "required": ["name"]
+            }),
+        };
+        let tools = [tool];
+        let response = |name, arguments| CompletionResponse {
+            choice: OneOrMany::many(vec![
+                AssistantContent::text("Sure!"),
+                AssistantContent::tool_call("call_0", name, arguments),
+            ])
+            .unwrap(),
+            raw_response: (),
+        };
+
+        assert!(response("greet", serde_json::json!({"name": "Ada"}))
+            .validate_tool_calls(&tools)
+            .is_ok());
+        assert!(matches!(
+            response("greet", serde_json::json!({"name": 42})).validate_tool_calls(&tools),
+            Err(CompletionError::InvalidToolCall(_))
+        ));
+        assert_eq!(
+            response("wave", serde_json::json!({}))
+                .validate_tool_calls(&tools)
+                .unwrap_err()
+                .to_string(),
+            "InvalidToolCall: Unknown tool `wave`"
+        );
+    }
 }
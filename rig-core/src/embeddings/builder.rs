@@ -2,17 +2,21 @@
 //! and batch generates the embeddings for each object when built.
 //! Only types that implement the [Embed] trait can be added to the [EmbeddingsBuilder].
 
-use std::{cmp::max, collections::HashMap};
+use std::{cmp::max, collections::HashMap, sync::Arc};
 
-use futures::{stream, StreamExt};
+use futures::{stream, Stream, StreamExt};
 
 use crate::{
     embeddings::{
-        embed::TextEmbedder, Embed, EmbedError, Embedding, EmbeddingError, EmbeddingModel,
+        embed::TextEmbedder, DocumentEmbedder, Embed, EmbedError, Embedding, EmbeddingError,
+        EmbeddingModel, InvalidDocumentError,
     },
     OneOrMany,
 };
 
+/// Default maximum number of embedding requests in flight at the same time.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
 /// Builder for creating embeddings from one or more documents of type `T`.
 /// Note: `T` can be any type that implements the [Embed] trait.
 ///
@@ -50,17 +54,44 @@ use crate::{
 pub struct EmbeddingsBuilder<M: EmbeddingModel, T: Embed> {
     model: M,
     documents: Vec<(T, Vec<String>)>,
+    max_concurrency: Option<usize>,
+    progress: Option<ProgressCallback>,
 }
 
+/// Callback receiving the progress of an [EmbeddingsBuilder], see [EmbeddingsBuilder::on_progress].
+type ProgressCallback = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
 impl<M: EmbeddingModel, T: Embed> EmbeddingsBuilder<M, T> {
     /// Create a new embedding builder with the given embedding model
-    pub fn new(model: M) -> Self {
+    /// (any [EmbeddingModel], or a model only meant to embed documents).
+    pub fn new(model: impl DocumentEmbedder<Model = M>) -> Self {
         Self {
-            model,
+            model: model.into_document_model(),
             documents: vec![],
+            max_concurrency: None,
+            progress: None,
         }
     }
 
+    /// Set the maximum number of embedding requests in flight at the same time.
+    /// Defaults to [DEFAULT_MAX_CONCURRENCY], or less for models accepting large batches
+    /// (i.e.: at most 1024 texts are embedded concurrently by default).
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max(1, max_concurrency));
+        self
+    }
+
+    /// Set a callback invoked after each embedded batch with the number of documents processed
+    /// so far and the total number of documents, e.g.: to render a progress bar.
+    /// A document is processed once all its texts are embedded (or failed to be embedded).
+    ///
+    /// The callback is called from the future returned by [EmbeddingsBuilder::build], so it
+    /// should return quickly.
+    pub fn on_progress(mut self, callback: impl Fn(usize, usize) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
     /// Add a document to be embedded to the builder. `document` must implement the [Embed] trait.
     pub fn document(mut self, document: T) -> Result<Self, EmbedError> {
         let mut embedder = TextEmbedder::default();
@@ -85,52 +116,32 @@ impl<M: EmbeddingModel, T: Embed> EmbeddingsBuilder<M, T> {
 impl<M: EmbeddingModel, T: Embed + Send> EmbeddingsBuilder<M, T> {
     /// Generate embeddings for all documents in the builder.
     /// Returns a vector of tuples, where the first element is the document and the second element is the embeddings (either one embedding or many).
+    ///
+    /// Texts are split into batches of at most [EmbeddingModel::MAX_DOCUMENTS] and the batches are
+    /// sent concurrently, with at most [EmbeddingsBuilder::max_concurrency] requests in flight.
+    /// The returned vector preserves the order in which documents were added, and the embeddings
+    /// of each document are in the same order as its texts.
     pub async fn build(self) -> Result<Vec<(T, OneOrMany<Embedding>)>, EmbeddingError> {
         use stream::TryStreamExt;
 
-        // Store the documents and their texts in a HashMap for easy access.
-        let mut docs = HashMap::new();
-        let mut texts = Vec::new();
-
-        // Iterate over all documents in the builder and insert their docs and texts into the lookup stores.
-        for (i, (doc, doc_texts)) in self.documents.into_iter().enumerate() {
-            docs.insert(i, doc);
-            texts.push((i, doc_texts));
-        }
+        let (docs, batches) = self.embed_batches();
 
-        // Compute the embeddings.
-        let mut embeddings = stream::iter(texts.into_iter())
-            // Merge the texts of each document into a single list of texts.
-            .flat_map(|(i, texts)| stream::iter(texts.into_iter().map(move |text| (i, text))))
-            // Chunk them into batches. Each batch size is at most the embedding API limit per request.
-            .chunks(M::MAX_DOCUMENTS)
-            // Generate the embeddings for each batch.
-            .map(|text| async {
-                let (ids, docs): (Vec<_>, Vec<_>) = text.into_iter().unzip();
-
-                let embeddings = self.model.embed_texts(docs).await?;
-                Ok::<_, EmbeddingError>(ids.into_iter().zip(embeddings).collect::<Vec<_>>())
-            })
-            // Parallelize the embeddings generation over 10 concurrent requests
-            .buffer_unordered(max(1, 1024 / M::MAX_DOCUMENTS))
-            // Collect the embeddings into a HashMap.
+        // Group the embeddings by document index, stopping at the first failed batch.
+        let mut embeddings = batches
+            .map(|(ids, embeddings)| embeddings.map(|embeddings| (ids, embeddings)))
             .try_fold(
                 HashMap::new(),
-                |mut acc: HashMap<_, OneOrMany<Embedding>>, embeddings| async move {
-                    embeddings.into_iter().for_each(|(i, embedding)| {
-                        acc.entry(i)
-                            .and_modify(|embeddings| embeddings.push(embedding.clone()))
-                            .or_insert(OneOrMany::one(embedding.clone()));
-                    });
-
+                |mut acc: HashMap<_, OneOrMany<Embedding>>, (ids, embeddings)| async move {
+                    group_embeddings(&mut acc, ids, embeddings);
                     Ok(acc)
                 },
             )
             .await?;
 
-        // Merge the embeddings with their respective documents
+        // Merge the embeddings with their respective documents, in insertion order.
         Ok(docs
             .into_iter()
+            .enumerate()
             .map(|(i, doc)| {
                 (
                     doc,
@@ -139,10 +150,163 @@ impl<M: EmbeddingModel, T: Embed + Send> EmbeddingsBuilder<M, T> {
             })
             .collect())
     }
+
+    /// Generate embeddings for all documents in the builder, like [EmbeddingsBuilder::build],
+    /// but without aborting when a batch fails.
+    ///
+    /// Documents with at least one text in a failed batch are returned as failures, along with
+    /// the error of the batch, so that they can be embedded again later. Both the embedded and
+    /// the failed documents preserve the order in which documents were added.
+    pub async fn build_partial(self) -> PartialEmbeddings<T> {
+        let (docs, batches) = self.embed_batches();
+
+        let (mut embeddings, mut errors) = batches
+            .fold(
+                (HashMap::new(), HashMap::new()),
+                |(mut embeddings, mut errors), (ids, result)| async move {
+                    match result {
+                        Ok(batch) => group_embeddings(&mut embeddings, ids, batch),
+                        Err(error) => {
+                            let error = Arc::new(error);
+                            ids.into_iter().for_each(|i| {
+                                errors.entry(i).or_insert_with(|| error.clone());
+                            });
+                        }
+                    }
+
+                    (embeddings, errors)
+                },
+            )
+            .await;
+
+        let mut result = PartialEmbeddings {
+            embedded: vec![],
+            failed: vec![],
+        };
+        docs.into_iter().enumerate().for_each(|(i, doc)| {
+            match (errors.remove(&i), embeddings.remove(&i)) {
+                (Some(error), _) => result.failed.push((doc, error)),
+                (None, Some(embeddings)) => result.embedded.push((doc, embeddings)),
+                (None, None) => unreachable!("Document should be present"),
+            }
+        });
+
+        result
+    }
+
+    /// Split the documents from their texts, and embed the texts in batches.
+    /// Returns the documents (in insertion order) and the stream of embedded batches, each with
+    /// the index of the document of each text of the batch.
+    fn embed_batches(self) -> (Vec<T>, impl Stream<Item = EmbeddedBatch>) {
+        let max_concurrency = self
+            .max_concurrency
+            .unwrap_or(max(1, DEFAULT_MAX_CONCURRENCY.min(1024 / M::MAX_DOCUMENTS)));
+
+        let mut docs = Vec::with_capacity(self.documents.len());
+        let mut texts = Vec::with_capacity(self.documents.len());
+        // Number of texts of each document that are not embedded yet, to report the progress
+        let mut remaining = Vec::with_capacity(self.documents.len());
+
+        // Split the documents from their texts, keeping track of each document's index.
+        for (i, (doc, doc_texts)) in self.documents.into_iter().enumerate() {
+            docs.push(doc);
+            remaining.push(doc_texts.len());
+            texts.push((i, doc_texts));
+        }
+
+        let total = docs.len();
+        let mut done = remaining.iter().filter(|texts| **texts == 0).count();
+        let progress = self.progress;
+
+        let model = self.model;
+        let batches = stream::iter(texts)
+            // Merge the texts of each document into a single list of texts.
+            .flat_map(|(i, texts)| stream::iter(texts.into_iter().map(move |text| (i, text))))
+            // Chunk them into batches. Each batch size is at most the embedding API limit per request.
+            .chunks(M::MAX_DOCUMENTS)
+            // Generate the embeddings for each batch.
+            .map(move |text| {
+                let model = model.clone();
+                async move {
+                    let (ids, docs): (Vec<_>, Vec<_>) = text.into_iter().unzip();
+                    let embeddings = model
+                        .embed_texts(docs)
+                        .await
+                        .map_err(|error| locate_invalid_document(error, &ids));
+                    (ids, embeddings)
+                }
+            })
+            // Parallelize the embeddings generation while yielding the batches in submission order
+            .buffered(max_concurrency)
+            .inspect(move |(ids, _)| {
+                if let Some(progress) = &progress {
+                    ids.iter().for_each(|i| {
+                        remaining[*i] -= 1;
+                        if remaining[*i] == 0 {
+                            done += 1;
+                        }
+                    });
+                    progress(done, total);
+                }
+            });
+
+        (docs, batches)
+    }
+}
+
+/// Index of the document of each text of a batch, and the embeddings of the batch.
+type EmbeddedBatch = (Vec<usize>, Result<Vec<Embedding>, EmbeddingError>);
+
+/// Result of [EmbeddingsBuilder::build_partial].
+pub struct PartialEmbeddings<T> {
+    /// Documents that were embedded, with their embeddings
+    pub embedded: Vec<(T, OneOrMany<Embedding>)>,
+    /// Documents that could not be embedded, with the error of the failed batch.
+    /// The error is shared by all the documents of the batch.
+    pub failed: Vec<(T, Arc<EmbeddingError>)>,
+}
+
+/// Replace the index of the invalid text of a batch in an [InvalidDocumentError] with the index
+/// of its document in the builder, the batches being chunks of the texts of all the documents.
+fn locate_invalid_document(error: EmbeddingError, ids: &[usize]) -> EmbeddingError {
+    let EmbeddingError::DocumentError(error) = error else {
+        return error;
+    };
+
+    match error.downcast::<InvalidDocumentError>() {
+        Ok(mut invalid) => {
+            if let Some(&document) = ids.get(invalid.index) {
+                invalid.index = document;
+            }
+            EmbeddingError::DocumentError(invalid)
+        }
+        Err(error) => EmbeddingError::DocumentError(error),
+    }
+}
+
+/// Add the embeddings of a batch to the embeddings of their respective documents.
+fn group_embeddings(
+    acc: &mut HashMap<usize, OneOrMany<Embedding>>,
+    ids: Vec<usize>,
+    embeddings: Vec<Embedding>,
+) {
+    ids.into_iter()
+        .zip(embeddings)
+        .for_each(|(i, embedding)| match acc.get_mut(&i) {
+            Some(embeddings) => embeddings.push(embedding),
+            None => {
+                acc.insert(i, OneOrMany::one(embedding));
+            }
+        });
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
     use crate::{
         embeddings::{embed::EmbedError, embed::TextEmbedder, Embedding, EmbeddingModel},
         Embed,
@@ -352,6 +516,198 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn test_build_preserves_order_across_batches() {
+        let fake_definitions = (0..23)
+            .map(|i| WordDefinition {
+                id: format!("doc{i}"),
+                definitions: vec![format!("definition {i}.0"), format!("definition {i}.1")],
+            })
+            .collect::<Vec<_>>();
+
+        let result = EmbeddingsBuilder::new(Model)
+            .documents(fake_definitions)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 23);
+
+        for (i, (definition, embeddings)) in result.iter().enumerate() {
+            assert_eq!(definition.id, format!("doc{i}"));
+            assert_eq!(embeddings.len(), 2);
+            assert_eq!(embeddings.first().document, format!("definition {i}.0"));
+            assert_eq!(embeddings.rest()[0].document, format!("definition {i}.1"));
+        }
+    }
+
+    /// Model tracking the maximum number of concurrent `embed_texts` calls
+    #[derive(Clone, Default)]
+    struct ConcurrencyModel {
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl EmbeddingModel for ConcurrencyModel {
+        const MAX_DOCUMENTS: usize = 1;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            documents: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<crate::embeddings::Embedding>, crate::embeddings::EmbeddingError> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(documents
+                .into_iter()
+                .map(|document| Embedding {
+                    document,
+                    vec: vec![0.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_max_concurrency() {
+        let model = ConcurrencyModel::default();
+
+        let result = EmbeddingsBuilder::new(model.clone())
+            .documents((0..20).map(|i| format!("document {i}")))
+            .unwrap()
+            .max_concurrency(2)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 20);
+        assert_eq!(model.max_in_flight.load(Ordering::SeqCst), 2);
+
+        let model = ConcurrencyModel::default();
+        EmbeddingsBuilder::new(model.clone())
+            .documents((0..20).map(|i| format!("document {i}")))
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            model.max_in_flight.load(Ordering::SeqCst),
+            super::DEFAULT_MAX_CONCURRENCY
+        );
+    }
+
+    /// Model failing to embed batches containing the text "fail"
+    #[derive(Clone)]
+    struct FailingModel;
+
+    impl EmbeddingModel for FailingModel {
+        const MAX_DOCUMENTS: usize = 2;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            documents: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<crate::embeddings::Embedding>, crate::embeddings::EmbeddingError> {
+            documents
+                .into_iter()
+                .map(|document| {
+                    if document == "fail" {
+                        Err(crate::embeddings::EmbeddingError::ProviderError(
+                            "Batch failed".to_string(),
+                        ))
+                    } else {
+                        Ok(Embedding {
+                            document,
+                            vec: vec![0.0],
+                        })
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_partial() {
+        // Batches: ["a", "b"], ["fail", "c"], ["d"]
+        let documents = vec![
+            vec!["a".to_string()],
+            vec!["b".to_string(), "fail".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        ];
+
+        let result = EmbeddingsBuilder::new(FailingModel)
+            .documents(documents.clone())
+            .unwrap()
+            .build_partial()
+            .await;
+
+        assert_eq!(
+            result
+                .embedded
+                .iter()
+                .map(|(doc, _)| doc[0].as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "d"]
+        );
+        assert_eq!(
+            result
+                .failed
+                .iter()
+                .map(|(doc, _)| doc[0].as_str())
+                .collect::<Vec<_>>(),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            result.failed[0].1.to_string(),
+            "ProviderError: Batch failed"
+        );
+
+        assert!(EmbeddingsBuilder::new(FailingModel)
+            .documents(documents)
+            .unwrap()
+            .build()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_progress() {
+        // Batches: ["a", "b"], ["x", "c"], ["d"]
+        let documents = vec![
+            vec!["a".to_string()],
+            vec!["b".to_string(), "x".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        ];
+        let progress = Arc::new(std::sync::Mutex::new(vec![]));
+
+        let result = EmbeddingsBuilder::new(FailingModel)
+            .documents(documents)
+            .unwrap()
+            .on_progress({
+                let progress = progress.clone();
+                move |done, total| progress.lock().unwrap().push((done, total))
+            })
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 4);
+        assert_eq!(*progress.lock().unwrap(), vec![(1, 4), (3, 4), (4, 4)]);
+    }
+
     #[tokio::test]
     async fn test_build_string() {
         let bindings = definitions_multiple_text();
@@ -4,6 +4,7 @@ pub trait VectorDistance {
 
     /// Get cosine similarity of two embedding vectors.
     /// If `normalized` is true, the dot product is returned.
+    /// The similarity with a zero (or empty) vector is 0.0.
     fn cosine_similarity(&self, other: &Self, normalized: bool) -> f64;
 
     /// Get angular distance of two embedding vectors.
@@ -38,6 +39,10 @@ impl VectorDistance for crate::embeddings::Embedding {
             let magnitude1: f64 = self.vec.iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
             let magnitude2: f64 = other.vec.iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
 
+            if magnitude1 == 0.0 || magnitude2 == 0.0 {
+                return 0.0;
+            }
+
             dot_product / (magnitude1 * magnitude2)
         }
     }
@@ -96,6 +101,10 @@ mod rayon {
                 let magnitude1: f64 = self.vec.par_iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
                 let magnitude2: f64 = other.vec.par_iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
 
+                if magnitude1 == 0.0 || magnitude2 == 0.0 {
+                    return 0.0;
+                }
+
                 dot_product / (magnitude1 * magnitude2)
             }
         }
@@ -168,6 +177,19 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_cosine_similarity_zero_vector() {
+        let (embedding, _) = embeddings();
+        let zero = Embedding {
+            document: "zero".to_string(),
+            vec: vec![0.0, 0.0, 0.0],
+        };
+        let empty = Embedding::default();
+
+        assert_eq!(embedding.cosine_similarity(&zero, false), 0.0);
+        assert_eq!(empty.cosine_similarity(&empty, false), 0.0);
+    }
+
     #[test]
     fn test_angular_distance() {
         let (embedding_1, embedding_2) = embeddings();
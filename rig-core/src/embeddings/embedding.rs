@@ -6,8 +6,13 @@
 //! Finally, the module defines the [EmbeddingError] enum, which represents various errors that
 //! can occur during embedding generation or processing.
 
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 
+use super::distance::VectorDistance;
+
 #[derive(Debug, thiserror::Error)]
 pub enum EmbeddingError {
     /// Http error (e.g.: connection error, timeout, etc.)
@@ -31,6 +36,19 @@ pub enum EmbeddingError {
     ProviderError(String),
 }
 
+/// Error about one of the texts passed to [EmbeddingModel::embed_texts] (e.g.: an empty text),
+/// returned as an [EmbeddingError::DocumentError].
+///
+/// `index` is the position of the text in the texts passed to the model: the
+/// [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder), which embeds the texts in batches,
+/// replaces it with the index of the document of the text in the builder.
+#[derive(Debug, thiserror::Error)]
+#[error("Document at index {index} {reason}")]
+pub struct InvalidDocumentError {
+    pub index: usize,
+    pub reason: String,
+}
+
 /// Trait for embedding models that can generate embeddings for documents.
 pub trait EmbeddingModel: Clone + Sync + Send {
     /// The maximum number of documents that can be embedded in a single request.
@@ -60,6 +78,126 @@ pub trait EmbeddingModel: Clone + Sync + Send {
     }
 }
 
+/// Embedding model for the documents stored in a vector store, accepted by
+/// [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+///
+/// Implemented by every [EmbeddingModel], and by the models only meant to embed documents (e.g.:
+/// [cohere::DocumentEmbeddingModel](crate::providers::cohere::DocumentEmbeddingModel)), which do
+/// not implement [QueryEmbedder] so that they cannot be used to embed the queries.
+pub trait DocumentEmbedder {
+    type Model: EmbeddingModel;
+
+    /// The embedding model embedding the documents.
+    fn into_document_model(self) -> Self::Model;
+}
+
+impl<M: EmbeddingModel> DocumentEmbedder for M {
+    type Model = M;
+
+    fn into_document_model(self) -> M {
+        self
+    }
+}
+
+/// Embedding model for the queries run against a vector store, accepted by the vector store
+/// indexes (e.g.: [InMemoryVectorStore::index](crate::vector_store::in_memory_store::InMemoryVectorStore::index)).
+///
+/// Implemented by every [EmbeddingModel], and by the models only meant to embed queries (e.g.:
+/// [cohere::QueryEmbeddingModel](crate::providers::cohere::QueryEmbeddingModel)), which do not
+/// implement [DocumentEmbedder] so that they cannot be used to embed the documents.
+pub trait QueryEmbedder {
+    type Model: EmbeddingModel;
+
+    /// The embedding model embedding the queries.
+    fn into_query_model(self) -> Self::Model;
+}
+
+impl<M: EmbeddingModel> QueryEmbedder for M {
+    type Model = M;
+
+    fn into_query_model(self) -> M {
+        self
+    }
+}
+
+/// Object-safe version of [EmbeddingModel], implemented by every embedding model, so that models
+/// of different providers can be stored and swapped at runtime (e.g.: chosen from a config file).
+///
+/// `Arc<dyn EmbeddingModelDyn>` implements [EmbeddingModel] and can be used to build embeddings
+/// and vector store indexes.
+///
+/// # Example
+/// ```
+/// use std::sync::Arc;
+/// use rig::{embeddings::EmbeddingModelDyn, providers::{cohere, openai}};
+///
+/// let model: Arc<dyn EmbeddingModelDyn> = match provider.as_str() {
+///     "cohere" => Arc::new(cohere::Client::from_env().embedding_model(cohere::EMBED_ENGLISH_V3, "search_document")),
+///     _ => Arc::new(openai::Client::from_env().embedding_model(openai::TEXT_EMBEDDING_3_SMALL)),
+/// };
+/// let embedding = model.embed_text("Hello").await?;
+/// ```
+pub trait EmbeddingModelDyn: Send + Sync {
+    /// The maximum number of documents that can be embedded in a single request
+    /// (i.e.: [EmbeddingModel::MAX_DOCUMENTS]).
+    fn max_documents(&self) -> usize;
+
+    /// The number of dimensions in the embedding vector.
+    fn ndims(&self) -> usize;
+
+    /// Embed multiple text documents in a single request.
+    fn embed_texts(
+        &self,
+        texts: Vec<String>,
+    ) -> BoxFuture<'_, Result<Vec<Embedding>, EmbeddingError>>;
+}
+
+impl<M: EmbeddingModel> EmbeddingModelDyn for M {
+    fn max_documents(&self) -> usize {
+        M::MAX_DOCUMENTS
+    }
+
+    fn ndims(&self) -> usize {
+        EmbeddingModel::ndims(self)
+    }
+
+    fn embed_texts(
+        &self,
+        texts: Vec<String>,
+    ) -> BoxFuture<'_, Result<Vec<Embedding>, EmbeddingError>> {
+        Box::pin(EmbeddingModel::embed_texts(self, texts))
+    }
+}
+
+impl EmbeddingModel for Arc<dyn EmbeddingModelDyn> {
+    /// The limit of the wrapped model is only known at runtime: the texts are sent in batches of
+    /// at most 1024 documents, further split according to the limit of the wrapped model.
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        EmbeddingModelDyn::ndims(self.as_ref())
+    }
+
+    #[cfg_attr(feature = "worker", worker::send)]
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let mut texts = texts.into_iter().collect::<Vec<_>>();
+        // Not `self.max_documents()`, which would be the limit of the `Arc` (i.e.: 1024)
+        let max_documents = self.as_ref().max_documents().max(1);
+
+        let mut embeddings = Vec::with_capacity(texts.len());
+        while !texts.is_empty() {
+            let rest = texts.split_off(texts.len().min(max_documents));
+            embeddings.extend(EmbeddingModelDyn::embed_texts(self.as_ref(), texts).await?);
+            texts = rest;
+        }
+
+        Ok(embeddings)
+    }
+}
+
 /// Trait for embedding models that can generate embeddings for images.
 pub trait ImageEmbeddingModel: Clone + Sync + Send {
     /// The maximum number of images that can be embedded in a single request.
@@ -105,3 +243,223 @@ impl PartialEq for Embedding {
 }
 
 impl Eq for Embedding {}
+
+impl Embedding {
+    /// Tolerance used by [Embedding::is_normalized] when comparing the L2 norm to 1.0.
+    const NORM_TOLERANCE: f64 = 1e-6;
+
+    /// Maximum number of decimals kept by [Embedding::round]: 17 significant digits are enough
+    /// to represent any `f64`, so rounding the components of an embedding (at most about 1.0 in
+    /// absolute value) to more decimals leaves them unchanged.
+    const MAX_ROUND_DECIMALS: u32 = 17;
+
+    /// Return a copy of this embedding whose vector is scaled to have an L2 norm of 1.0.
+    /// A zero vector cannot be normalized and is returned unchanged.
+    pub fn normalize(&self) -> Self {
+        let norm = self.l2_norm();
+
+        if norm == 0.0 {
+            tracing::warn!(target: "rig", "Cannot normalize a zero vector embedding, returning it unchanged");
+            return self.clone();
+        }
+
+        Self {
+            document: self.document.clone(),
+            vec: self.vec.iter().map(|x| x / norm).collect(),
+        }
+    }
+
+    /// Return a copy of this embedding with each component of the vector rounded to `decimals`
+    /// decimal places, e.g.: to shrink the size of the embedding serialized as JSON.
+    /// The embedding is returned unchanged for more than 17 decimals.
+    pub fn round(&self, decimals: u32) -> Self {
+        if decimals > Self::MAX_ROUND_DECIMALS {
+            return self.clone();
+        }
+        let scale = 10f64.powi(decimals as i32);
+
+        Self {
+            document: self.document.clone(),
+            vec: self
+                .vec
+                .iter()
+                .map(|x| (x * scale).round() / scale)
+                .collect(),
+        }
+    }
+
+    /// Whether the L2 norm of the embedding vector is 1.0, within floating-point tolerance.
+    pub fn is_normalized(&self) -> bool {
+        (self.l2_norm() - 1.0).abs() < Self::NORM_TOLERANCE
+    }
+
+    /// L2 (euclidean) norm of the embedding vector, 0.0 for an empty vector.
+    pub fn l2_norm(&self) -> f64 {
+        self.vec.iter().map(|x| x * x).sum::<f64>().sqrt()
+    }
+
+    /// Dot product of the embedding vectors, which must have the same length (0.0 if both are
+    /// empty). Shorthand for [VectorDistance::dot_product], see [VectorDistance] for other
+    /// similarity and distance measures, e.g.: cosine similarity.
+    pub fn dot(&self, other: &Self) -> f64 {
+        debug_assert_eq!(
+            self.vec.len(),
+            other.vec.len(),
+            "Embeddings should have the same number of dimensions"
+        );
+
+        self.dot_product(other)
+    }
+}
+
+/// Single precision variant of [Embedding], taking half the memory.
+/// Useful to store large indexes when the loss of precision is acceptable.
+#[derive(Clone, Default, Deserialize, Serialize, Debug)]
+pub struct EmbeddingF32 {
+    /// The document that was embedded. Used for debugging.
+    pub document: String,
+    /// The embedding vector
+    pub vec: Vec<f32>,
+}
+
+impl PartialEq for EmbeddingF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.document == other.document
+    }
+}
+
+impl Eq for EmbeddingF32 {}
+
+impl From<Embedding> for EmbeddingF32 {
+    fn from(embedding: Embedding) -> Self {
+        Self {
+            document: embedding.document,
+            vec: embedding.vec.into_iter().map(|x| x as f32).collect(),
+        }
+    }
+}
+
+impl From<EmbeddingF32> for Embedding {
+    fn from(embedding: EmbeddingF32) -> Self {
+        Self {
+            document: embedding.document,
+            vec: embedding.vec.into_iter().map(f64::from).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Embedding, EmbeddingError, EmbeddingF32, EmbeddingModel, EmbeddingModelDyn};
+
+    fn embedding(vec: Vec<f64>) -> Embedding {
+        Embedding {
+            document: "test".to_string(),
+            vec,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalized = embedding(vec![3.0, 4.0]).normalize();
+
+        assert_eq!(normalized.document, "test");
+        assert_eq!(normalized.vec, vec![0.6, 0.8]);
+        assert!(normalized.is_normalized());
+        assert!(!embedding(vec![3.0, 4.0]).is_normalized());
+    }
+
+    #[test]
+    fn test_normalize_zero_vector() {
+        let normalized = embedding(vec![0.0, 0.0, 0.0]).normalize();
+
+        assert_eq!(normalized.vec, vec![0.0, 0.0, 0.0]);
+        assert!(!normalized.is_normalized());
+    }
+
+    #[test]
+    fn test_dot_and_l2_norm() {
+        let a = embedding(vec![1.0, 2.0, 3.0]);
+        let b = embedding(vec![1.0, 5.0, 7.0]);
+
+        assert_eq!(a.dot(&b), 32.0);
+        assert_eq!(embedding(vec![3.0, 4.0]).l2_norm(), 5.0);
+        assert_eq!(embedding(vec![]).l2_norm(), 0.0);
+        assert_eq!(embedding(vec![]).dot(&embedding(vec![])), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "same number of dimensions")]
+    #[cfg(debug_assertions)]
+    fn test_dot_different_dimensions() {
+        embedding(vec![1.0, 2.0]).dot(&embedding(vec![1.0]));
+    }
+
+    #[test]
+    fn test_round() {
+        let rounded = embedding(vec![0.123456789, -0.98765, 1e-9]).round(3);
+
+        assert_eq!(rounded.document, "test");
+        assert_eq!(rounded.vec, vec![0.123, -0.988, 0.0]);
+
+        let vec = vec![0.123456789, -0.98765, 1e-9];
+        assert_eq!(embedding(vec.clone()).round(400).vec, vec);
+        assert_eq!(embedding(vec.clone()).round(u32::MAX).vec, vec);
+    }
+
+    #[test]
+    fn test_embedding_f32_conversion() {
+        let compact = EmbeddingF32::from(embedding(vec![0.5, -0.25]));
+        assert_eq!(compact.document, "test");
+        assert_eq!(compact.vec, vec![0.5f32, -0.25f32]);
+
+        assert_eq!(Embedding::from(compact).vec, vec![0.5, -0.25]);
+    }
+
+    /// Model embedding each text as its length, at most 2 texts per request
+    #[derive(Clone)]
+    struct LengthModel;
+
+    impl EmbeddingModel for LengthModel {
+        const MAX_DOCUMENTS: usize = 2;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<Embedding>, EmbeddingError> {
+            let texts = texts.into_iter().collect::<Vec<_>>();
+            assert!(texts.len() <= Self::MAX_DOCUMENTS);
+
+            Ok(texts
+                .into_iter()
+                .map(|text| Embedding {
+                    vec: vec![text.len() as f64],
+                    document: text,
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embedding_model_dyn() {
+        let model: std::sync::Arc<dyn EmbeddingModelDyn> = std::sync::Arc::new(LengthModel);
+        assert_eq!(model.as_ref().max_documents(), 2);
+        assert_eq!(EmbeddingModel::ndims(&model), 1);
+
+        let embeddings = EmbeddingModel::embed_texts(&model, ["a", "bb", "ccc"].map(String::from))
+            .await
+            .unwrap();
+        assert_eq!(
+            embeddings
+                .iter()
+                .map(|embedding| (embedding.document.as_str(), embedding.vec[0]))
+                .collect::<Vec<_>>(),
+            vec![("a", 1.0), ("bb", 2.0), ("ccc", 3.0)]
+        );
+        assert_eq!(model.embed_text("dddd").await.unwrap().vec, vec![4.0]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Storage of embeddings as JSON lines (one [Embedding] per line), e.g.: to persist a large
+//! index to disk and load it back without recomputing the embeddings.
+//!
+//! Embeddings are written with the full precision of their vectors by default. Since the
+//! shortest representation of each number is written, rounding the vectors to a few decimal
+//! places (see [Precision::Decimals]) substantially reduces the size of the file, with a
+//! negligible loss of retrieval quality.
+//!
+//! # Example
+//! ```
+//! use std::{fs::File, io::{BufReader, BufWriter}};
+//! use rig::embeddings::jsonl::{self, Precision};
+//!
+//! jsonl::write(BufWriter::new(File::create("index.jsonl")?), &embeddings, Precision::Decimals(5))?;
+//! let embeddings = jsonl::read(BufReader::new(File::open("index.jsonl")?))?;
+//! ```
+
+use std::io::{BufRead, Write};
+
+use super::{Embedding, EmbeddingError};
+
+/// Precision of the vectors of the embeddings written by [write].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Full precision
+    #[default]
+    Full,
+    /// Components rounded to the given number of decimal places
+    Decimals(u32),
+}
+
+/// Write `embeddings` to `writer`, one JSON object per line, with vectors of the given precision.
+pub fn write(
+    mut writer: impl Write,
+    embeddings: &[Embedding],
+    precision: Precision,
+) -> Result<(), EmbeddingError> {
+    for embedding in embeddings {
+        match precision {
+            Precision::Full => serde_json::to_writer(&mut writer, embedding)?,
+            Precision::Decimals(decimals) => {
+                serde_json::to_writer(&mut writer, &embedding.round(decimals))?
+            }
+        }
+        writer.write_all(b"\n").map_err(serde_json::Error::io)?;
+    }
+
+    writer.flush().map_err(serde_json::Error::io)?;
+    Ok(())
+}
+
+/// Read the embeddings written by [write] from `reader`, skipping blank lines.
+pub fn read(reader: impl BufRead) -> Result<Vec<Embedding>, EmbeddingError> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            let line = line.map_err(serde_json::Error::io)?;
+            Ok(serde_json::from_str(&line)?)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let embeddings = vec![
+            Embedding {
+                document: "hello".to_string(),
+                vec: vec![0.123456789, -0.5],
+            },
+            Embedding {
+                document: "world".to_string(),
+                vec: vec![1.0 / 3.0, 0.25],
+            },
+        ];
+
+        let mut full = vec![];
+        write(&mut full, &embeddings, Precision::Full).unwrap();
+        let loaded = read(full.as_slice()).unwrap();
+        assert_eq!(loaded[0].vec, embeddings[0].vec);
+        assert_eq!(loaded[1].vec, embeddings[1].vec);
+
+        let mut rounded = vec![];
+        write(&mut rounded, &embeddings, Precision::Decimals(3)).unwrap();
+        assert_eq!(
+            String::from_utf8(rounded.clone()).unwrap(),
+            "{\"document\":\"hello\",\"vec\":[0.123,-0.5]}\n{\"document\":\"world\",\"vec\":[0.333,0.25]}\n"
+        );
+        assert!(rounded.len() < full.len());
+
+        let loaded = read(rounded.as_slice()).unwrap();
+        assert_eq!(loaded, embeddings);
+        assert_eq!(loaded[1].vec, vec![0.333, 0.25]);
+    }
+
+    #[test]
+    fn test_read_invalid_line() {
+        let error =
+            read("{\"document\":\"hello\",\"vec\":[]}\n\nnot json\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, EmbeddingError::JsonError(_)));
+    }
+}
@@ -6,10 +6,14 @@
 pub mod builder;
 pub mod embed;
 pub mod embedding;
+pub mod jsonl;
 pub mod tool;
 
 pub mod distance;
-pub use builder::EmbeddingsBuilder;
+pub use builder::{EmbeddingsBuilder, PartialEmbeddings};
 pub use embed::{to_texts, Embed, EmbedError, TextEmbedder};
-pub use embedding::{Embedding, EmbeddingError, EmbeddingModel};
+pub use embedding::{
+    DocumentEmbedder, Embedding, EmbeddingError, EmbeddingF32, EmbeddingModel, EmbeddingModelDyn,
+    InvalidDocumentError, QueryEmbedder,
+};
 pub use tool::ToolSchema;
@@ -30,7 +30,7 @@
 
 use std::marker::PhantomData;
 
-use schemars::{schema_for, JsonSchema};
+use schemars::{gen::SchemaSettings, JsonSchema};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -48,16 +48,45 @@ pub enum ExtractionError {
     #[error("No data extracted")]
     NoData,
 
+    /// No longer returned by the extractor, which returns [ExtractionError::InvalidOutput] with
+    /// the raw output of the model instead.
+    #[deprecated(note = "the extractor returns `ExtractionError::InvalidOutput` instead")]
     #[error("Failed to deserialize the extracted data: {0}")]
     DeserializationError(#[from] serde_json::Error),
 
+    /// The output of the model does not deserialize into the target type.
+    /// `output` is the raw output of the model (i.e.: the arguments of the `submit` call, or the
+    /// text of the response), to help debugging the prompt.
+    #[error("Failed to deserialize the extracted data: {error}\nModel output: {output}")]
+    InvalidOutput {
+        output: String,
+        #[source]
+        error: serde_json::Error,
+    },
+
     #[error("CompletionError: {0}")]
     CompletionError(#[from] CompletionError),
 }
 
+/// JSON schema of `T` as sent to the model by the extractor, with the schemas of nested types
+/// (e.g.: the items of a `Vec<LineItem>` field) inlined instead of referenced through
+/// `definitions`, which many providers do not resolve.
+/// Only recursive types are still referenced.
+///
+/// Useful to build the provider-specific parameters of [ExtractorBuilder::response_format].
+pub fn json_schema<T: JsonSchema>() -> serde_json::Value {
+    let schema = SchemaSettings::draft07()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<T>();
+
+    json!(schema)
+}
+
 /// Extractor for structured data from text
 pub struct Extractor<M: CompletionModel, T: JsonSchema + for<'a> Deserialize<'a> + Send + Sync> {
     agent: Agent<M>,
+    json_response: bool,
     _t: PhantomData<T>,
 }
 
@@ -68,26 +97,24 @@ where
     pub async fn extract(&self, text: impl Into<Message> + Send) -> Result<T, ExtractionError> {
         let response = self.agent.completion(text, vec![]).await?.send().await?;
 
-        let arguments = response
+        let mut arguments = vec![];
+        let mut text_content = String::new();
+        response
             .choice
             .into_iter()
-            // We filter tool calls to look for submit tool calls
-            .filter_map(|content| {
-                if let AssistantContent::ToolCall(ToolCall {
-                    function: ToolFunction { arguments, name },
+            .for_each(|content| match content {
+                // We filter tool calls to look for submit tool calls
+                AssistantContent::ToolCall(ToolCall {
+                    function:
+                        ToolFunction {
+                            arguments: args,
+                            name,
+                        },
                     ..
-                }) = content
-                {
-                    if name == SUBMIT_TOOL_NAME {
-                        Some(arguments)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+                }) if name == SUBMIT_TOOL_NAME => arguments.push(args),
+                AssistantContent::Text(text) => text_content.push_str(&text.text),
+                _ => {}
+            });
 
         if arguments.len() > 1 {
             tracing::warn!(
@@ -95,13 +122,26 @@ where
             );
         }
 
-        let raw_data = if let Some(arg) = arguments.into_iter().next() {
-            arg
-        } else {
-            return Err(ExtractionError::NoData);
-        };
+        if let Some(raw_data) = arguments.into_iter().next() {
+            return serde_json::from_value(raw_data.clone()).map_err(|error| {
+                ExtractionError::InvalidOutput {
+                    output: raw_data.to_string(),
+                    error,
+                }
+            });
+        }
 
-        Ok(serde_json::from_value(raw_data)?)
+        // Without a submit call, the data is expected as JSON text (i.e.: when extracting with a
+        // response format, or when the model answered with JSON instead of calling the tool)
+        let text_content = text_content.trim();
+        if self.json_response {
+            serde_json::from_str(text_content).map_err(|error| ExtractionError::InvalidOutput {
+                output: text_content.to_string(),
+                error,
+            })
+        } else {
+            serde_json::from_str(text_content).map_err(|_| ExtractionError::NoData)
+        }
     }
 }
 
@@ -111,6 +151,8 @@ pub struct ExtractorBuilder<
     M: CompletionModel,
 > {
     agent_builder: AgentBuilder<M>,
+    instructions: Vec<String>,
+    response_format: Option<serde_json::Value>,
     _t: PhantomData<T>,
 }
 
@@ -119,23 +161,16 @@ impl<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync, M: Compl
 {
     pub fn new(model: M) -> Self {
         Self {
-            agent_builder: AgentBuilder::new(model)
-                .preamble("\
-                    You are an AI assistant whose purpose is to extract structured data from the provided text.\n\
-                    You will have access to a `submit` function that defines the structure of the data to extract from the provided text.\n\
-                    Use the `submit` function to submit the structured data.\n\
-                    Be sure to fill out every field and ALWAYS CALL THE `submit` function, event with default values!!!.
-                ")
-                .tool(SubmitTool::<T> {_t: PhantomData}),
+            agent_builder: AgentBuilder::new(model),
+            instructions: vec![],
+            response_format: None,
             _t: PhantomData,
         }
     }
 
     /// Add additional preamble to the extractor
     pub fn preamble(mut self, preamble: &str) -> Self {
-        self.agent_builder = self.agent_builder.append_preamble(&format!(
-            "\n=============== ADDITIONAL INSTRUCTIONS ===============\n{preamble}"
-        ));
+        self.instructions.push(preamble.to_string());
         self
     }
 
@@ -145,10 +180,53 @@ impl<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync, M: Compl
         self
     }
 
+    /// Extract the data using the structured output mode of the provider instead of a `submit` tool.
+    /// `additional_params` are the provider-specific parameters constraining the response to the
+    /// JSON schema of `T` (e.g.: Cohere's `response_format`), and are merged into each request.
+    ///
+    /// The schema of `T` can be obtained with [json_schema].
+    pub fn response_format(mut self, additional_params: serde_json::Value) -> Self {
+        self.response_format = Some(additional_params);
+        self
+    }
+
     /// Build the Extractor
     pub fn build(self) -> Extractor<M, T> {
+        let json_response = self.response_format.is_some();
+
+        let agent_builder = match self.response_format {
+            Some(response_format) => self
+                .agent_builder
+                .preamble("\
+                    You are an AI assistant whose purpose is to extract structured data from the provided text.\n\
+                    Respond ONLY with a JSON object containing the structured data.\n\
+                    Be sure to fill out every field, even with default values!!!.
+                ")
+                .additional_params(response_format),
+            None => self
+                .agent_builder
+                .preamble("\
+                    You are an AI assistant whose purpose is to extract structured data from the provided text.\n\
+                    You will have access to a `submit` function that defines the structure of the data to extract from the provided text.\n\
+                    Use the `submit` function to submit the structured data.\n\
+                    Follow the parameters of the `submit` function exactly, including nested objects and arrays of objects, and include every required field.\n\
+                    Be sure to fill out every field and ALWAYS CALL THE `submit` function, event with default values!!!.
+                ")
+                .tool(SubmitTool::<T> { _t: PhantomData }),
+        };
+
+        let agent_builder =
+            self.instructions
+                .iter()
+                .fold(agent_builder, |agent_builder, preamble| {
+                    agent_builder.append_preamble(&format!(
+                        "\n=============== ADDITIONAL INSTRUCTIONS ===============\n{preamble}"
+                    ))
+                });
+
         Extractor {
-            agent: self.agent_builder.build(),
+            agent: agent_builder.build(),
+            json_response,
             _t: PhantomData,
         }
     }
@@ -174,7 +252,7 @@ impl<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync> Tool for
             name: Self::NAME.to_string(),
             description: "Submit the structured data you extracted from the provided text."
                 .to_string(),
-            parameters: json!(schema_for!(T)),
+            parameters: json_schema::<T>(),
         }
     }
 
@@ -182,3 +260,216 @@ impl<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync> Tool for
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        completion::{self, CompletionError, CompletionModel, CompletionRequest},
+        message::AssistantContent,
+        OneOrMany,
+    };
+
+    use super::{json_schema, ExtractionError, ExtractorBuilder};
+
+    #[derive(Clone)]
+    struct Model {
+        response: AssistantContent,
+    }
+
+    impl CompletionModel for Model {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<completion::CompletionResponse<()>, CompletionError> {
+            // Only the tool based extraction sends the `submit` tool
+            assert_eq!(
+                request.tools.is_empty(),
+                request.additional_params.is_some()
+            );
+
+            Ok(completion::CompletionResponse {
+                choice: OneOrMany::one(self.response.clone()),
+                raw_response: (),
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_submit_tool() {
+        let model = Model {
+            response: AssistantContent::tool_call(
+                "call_0",
+                "submit",
+                serde_json::json!({"name": "John Doe", "age": 30}),
+            ),
+        };
+
+        let person = ExtractorBuilder::<Person, _>::new(model)
+            .build()
+            .extract("John Doe is a 30 year old doctor.")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            person,
+            Person {
+                name: "John Doe".to_string(),
+                age: 30
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_response_format() {
+        let model = Model {
+            response: AssistantContent::text(r#" {"name": "John Doe", "age": 30} "#),
+        };
+
+        let person = ExtractorBuilder::<Person, _>::new(model)
+            .response_format(serde_json::json!({"response_format": {"type": "json_object"}}))
+            .build()
+            .extract("John Doe is a 30 year old doctor.")
+            .await
+            .unwrap();
+
+        assert_eq!(person.age, 30);
+    }
+
+    #[tokio::test]
+    async fn test_extract_without_data() {
+        let model = Model {
+            response: AssistantContent::text("I could not find anyone."),
+        };
+
+        let result = ExtractorBuilder::<Person, _>::new(model)
+            .build()
+            .extract("Nothing to see here.")
+            .await;
+
+        assert!(matches!(result, Err(ExtractionError::NoData)));
+    }
+
+    #[tokio::test]
+    async fn test_extract_invalid_output() {
+        let model = Model {
+            response: AssistantContent::tool_call(
+                "call_0",
+                "submit",
+                serde_json::json!({"name": "John Doe", "age": "thirty"}),
+            ),
+        };
+
+        let result = ExtractorBuilder::<Person, _>::new(model)
+            .build()
+            .extract("John Doe is a 30 year old doctor.")
+            .await;
+
+        match result {
+            Err(ExtractionError::InvalidOutput { output, error }) => {
+                assert_eq!(output, r#"{"age":"thirty","name":"John Doe"}"#);
+                assert!(error.is_data());
+            }
+            _ => panic!("Expected an InvalidOutput error"),
+        }
+
+        let model = Model {
+            response: AssistantContent::text(r#"{"name": "John Doe""#),
+        };
+
+        let result = ExtractorBuilder::<Person, _>::new(model)
+            .response_format(serde_json::json!({"response_format": {"type": "json_object"}}))
+            .build()
+            .extract("John Doe is a 30 year old doctor.")
+            .await;
+
+        match result {
+            Err(ExtractionError::InvalidOutput { output, error }) => {
+                assert_eq!(output, r#"{"name": "John Doe""#);
+                assert!(error.is_eof());
+            }
+            _ => panic!("Expected an InvalidOutput error"),
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+    struct Invoice {
+        number: String,
+        customer: Customer,
+        line_items: Vec<LineItem>,
+        notes: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+    struct Customer {
+        name: String,
+        email: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+    struct LineItem {
+        description: String,
+        quantity: u32,
+        unit_price: f64,
+    }
+
+    #[test]
+    fn test_json_schema_inlines_nested_types() {
+        let schema = json_schema::<Invoice>();
+
+        assert!(!schema.to_string().contains("$ref"));
+        assert!(schema.get("definitions").is_none());
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["customer", "line_items", "number"])
+        );
+
+        let customer = &schema["properties"]["customer"];
+        assert_eq!(customer["type"], "object");
+        assert_eq!(customer["required"], serde_json::json!(["name"]));
+
+        let line_items = &schema["properties"]["line_items"];
+        assert_eq!(line_items["type"], "array");
+        assert_eq!(line_items["items"]["type"], "object");
+        assert_eq!(
+            line_items["items"]["required"],
+            serde_json::json!(["description", "quantity", "unit_price"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_nested_data() {
+        let invoice = serde_json::json!({
+            "number": "INV-001",
+            "customer": {"name": "ACME"},
+            "line_items": [
+                {"description": "Anvil", "quantity": 2, "unit_price": 99.5},
+                {"description": "Rocket skates", "quantity": 1, "unit_price": 250.0}
+            ]
+        });
+        let model = Model {
+            response: AssistantContent::tool_call("call_0", "submit", invoice),
+        };
+
+        let invoice = ExtractorBuilder::<Invoice, _>::new(model)
+            .build()
+            .extract(
+                "Invoice INV-001 for ACME: 2 anvils at $99.50, 1 pair of rocket skates at $250.",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(invoice.line_items.len(), 2);
+        assert_eq!(invoice.line_items[1].description, "Rocket skates");
+        assert_eq!(invoice.customer.email, None);
+    }
+}
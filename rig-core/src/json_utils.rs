@@ -5,6 +5,11 @@ use std::fmt;
 use std::marker::PhantomData;
 use std::str::FromStr;
 
+/// Shallow merge of the JSON object `b` into the JSON object `a`: the keys of `b` are added to
+/// `a`, replacing the values of the keys already in `a` (arrays included, e.g.: `tools`).
+/// If either value is not an object, `a` is returned unchanged.
+///
+/// Use [merge_with] with [ArrayMerge::Append] to append arrays instead.
 pub fn merge(a: serde_json::Value, b: serde_json::Value) -> serde_json::Value {
     match (a, b) {
         (serde_json::Value::Object(mut a_map), serde_json::Value::Object(b_map)) => {
@@ -17,6 +22,43 @@ pub fn merge(a: serde_json::Value, b: serde_json::Value) -> serde_json::Value {
     }
 }
 
+/// How [merge_with] merges an array of `b` into an array of `a` under the same key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The array of `b` replaces the array of `a`, like [merge]
+    #[default]
+    Replace,
+    /// The items of the array of `b` are appended to the array of `a`
+    Append,
+}
+
+/// Shallow merge of the JSON object `b` into the JSON object `a`, like [merge], except that
+/// arrays present in both objects under the same key are merged according to `arrays`.
+pub fn merge_with(
+    a: serde_json::Value,
+    b: serde_json::Value,
+    arrays: ArrayMerge,
+) -> serde_json::Value {
+    match (a, b) {
+        (serde_json::Value::Object(mut a_map), serde_json::Value::Object(b_map)) => {
+            b_map
+                .into_iter()
+                .for_each(|(key, value)| match (arrays, a_map.get_mut(&key), value) {
+                    (
+                        ArrayMerge::Append,
+                        Some(serde_json::Value::Array(a_items)),
+                        serde_json::Value::Array(b_items),
+                    ) => a_items.extend(b_items),
+                    (_, _, value) => {
+                        a_map.insert(key, value);
+                    }
+                });
+            serde_json::Value::Object(a_map)
+        }
+        (a, _) => a,
+    }
+}
+
 pub fn merge_inplace(a: &mut serde_json::Value, b: serde_json::Value) {
     if let (serde_json::Value::Object(a_map), serde_json::Value::Object(b_map)) = (a, b) {
         b_map.into_iter().for_each(|(key, value)| {
@@ -160,6 +202,26 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_merge_with_arrays() {
+        let a = serde_json::json!({"tools": [{"name": "search"}], "stop": ["a"], "k": 1});
+        let b = serde_json::json!({"tools": [{"name": "calculator"}], "stop": "b", "p": 0.5});
+
+        assert_eq!(
+            merge_with(a.clone(), b.clone(), ArrayMerge::Replace),
+            merge(a.clone(), b.clone())
+        );
+        assert_eq!(
+            merge_with(a, b, ArrayMerge::Append),
+            serde_json::json!({
+                "tools": [{"name": "search"}, {"name": "calculator"}],
+                "stop": "b",
+                "k": 1,
+                "p": 0.5
+            })
+        );
+    }
+
     #[test]
     fn test_merge_inplace() {
         let mut a = serde_json::json!({"key1": "value1"});
@@ -83,6 +83,8 @@
 pub mod agent;
 #[cfg(feature = "audio")]
 pub mod audio_generation;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod cli_chatbot;
 pub mod completion;
 pub mod embeddings;
@@ -91,10 +93,12 @@ pub mod extractor;
 pub mod image_generation;
 pub(crate) mod json_utils;
 pub mod loaders;
+pub mod memory;
 pub mod one_or_many;
 pub mod pipeline;
 pub mod providers;
 pub mod streaming;
+pub mod tokens;
 pub mod tool;
 pub mod transcription;
 pub mod vector_store;
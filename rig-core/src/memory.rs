@@ -0,0 +1,452 @@
+//! This module provides the [Memory] trait, used by agents to keep the history of a
+//! conversation between prompts, and [InMemoryMemory], its in-memory implementation.
+//!
+//! It also provides [HistoryTruncation] strategies to fit a chat history in the context window
+//! of a model: [DropOldest] and [Summarize].
+//!
+//! # Example
+//! ```
+//! use rig::{completion::Prompt, memory::InMemoryMemory, providers::openai};
+//!
+//! let openai = openai::Client::from_env();
+//!
+//! let agent = openai
+//!     .agent("gpt-4o")
+//!     .preamble("You are a helpful assistant.")
+//!     // Keep roughly the last 4000 tokens of the conversation
+//!     .memory(InMemoryMemory::new().with_token_budget(4000))
+//!     .build();
+//!
+//! agent.prompt("My name is Ada.").await?;
+//! // The agent remembers the previous turn
+//! let answer = agent.prompt("What is my name?").await?;
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    completion::{CompletionError, CompletionModel, Message},
+    message::{AssistantContent, UserContent},
+    tokens::estimate_tokens as estimate_text_tokens,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryError {
+    /// Error returned by the storage backend of the memory
+    #[error("BackendError: {0}")]
+    BackendError(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+/// Storage of the history of a conversation, to which an agent appends each turn.
+///
+/// Implement this trait to store conversations in a database or a cache shared by several
+/// processes. Implementations are responsible for bounding the history they return, e.g.: to
+/// fit the context window of the model (see [InMemoryMemory::with_token_budget]).
+pub trait Memory: Send + Sync {
+    /// Load the history of the conversation, oldest message first.
+    fn load(&self) -> BoxFuture<'_, Result<Vec<Message>, MemoryError>>;
+
+    /// Append the messages of a turn (i.e.: the prompt, the response of the model and the
+    /// tool calls in between) to the history.
+    fn append(&self, messages: Vec<Message>) -> BoxFuture<'_, Result<(), MemoryError>>;
+
+    /// Forget the history of the conversation.
+    fn clear(&self) -> BoxFuture<'_, Result<(), MemoryError>>;
+}
+
+/// [Memory] keeping the history of the conversation in memory.
+/// Clones share the same history.
+#[derive(Clone, Default)]
+pub struct InMemoryMemory {
+    messages: Arc<Mutex<Vec<Message>>>,
+    token_budget: Option<usize>,
+}
+
+impl InMemoryMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap the history to about `token_budget` tokens (as estimated by [estimate_tokens]),
+    /// dropping the oldest messages when a turn is appended. The last turn is always kept.
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Current history of the conversation.
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages.lock().expect("Memory lock poisoned").clone()
+    }
+}
+
+impl Memory for InMemoryMemory {
+    fn load(&self) -> BoxFuture<'_, Result<Vec<Message>, MemoryError>> {
+        Box::pin(async move { Ok(self.messages()) })
+    }
+
+    fn append(&self, messages: Vec<Message>) -> BoxFuture<'_, Result<(), MemoryError>> {
+        Box::pin(async move {
+            let mut history = self.messages.lock().expect("Memory lock poisoned");
+            let protected = messages.len();
+            history.extend(messages);
+
+            if let Some(token_budget) = self.token_budget {
+                drop_oldest(&mut history, token_budget, protected);
+            }
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> BoxFuture<'_, Result<(), MemoryError>> {
+        Box::pin(async move {
+            self.messages.lock().expect("Memory lock poisoned").clear();
+            Ok(())
+        })
+    }
+}
+
+/// Strategy used to fit the history of a conversation in a token budget (e.g.: the context
+/// window of the model, minus the tokens reserved for the preamble and the response) before
+/// sending it to the model.
+///
+/// # Example
+/// ```
+/// use rig::{completion::CompletionModel, memory::{DropOldest, HistoryTruncation}};
+///
+/// let context_window = model.max_context_tokens().unwrap_or(4_096);
+/// // Reserve tokens for the preamble, the prompt and the response
+/// let history = DropOldest.truncate(history, context_window - 8_000).await?;
+/// ```
+pub trait HistoryTruncation: Send + Sync {
+    /// Fit `history` in about `token_budget` tokens (as estimated by [estimate_tokens]).
+    fn truncate(
+        &self,
+        history: Vec<Message>,
+        token_budget: usize,
+    ) -> BoxFuture<'_, Result<Vec<Message>, CompletionError>>;
+}
+
+/// [HistoryTruncation] dropping the oldest messages of the history until it fits in the budget.
+/// The truncated history always starts with a user prompt.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DropOldest;
+
+impl HistoryTruncation for DropOldest {
+    fn truncate(
+        &self,
+        mut history: Vec<Message>,
+        token_budget: usize,
+    ) -> BoxFuture<'_, Result<Vec<Message>, CompletionError>> {
+        drop_oldest(&mut history, token_budget, 0);
+        Box::pin(async move { Ok(history) })
+    }
+}
+
+/// [HistoryTruncation] replacing the oldest messages of the history by a summary generated by
+/// `model`, when the history does not fit in the budget. The most recent messages are kept
+/// as-is in half of the budget, and the summary is added as a user message before them.
+#[derive(Clone)]
+pub struct Summarize<M: CompletionModel> {
+    model: M,
+}
+
+impl<M: CompletionModel> Summarize<M> {
+    pub fn new(model: M) -> Self {
+        Self { model }
+    }
+}
+
+impl<M: CompletionModel> HistoryTruncation for Summarize<M> {
+    fn truncate(
+        &self,
+        history: Vec<Message>,
+        token_budget: usize,
+    ) -> BoxFuture<'_, Result<Vec<Message>, CompletionError>> {
+        Box::pin(async move {
+            if history.iter().map(estimate_tokens).sum::<usize>() <= token_budget {
+                return Ok(history);
+            }
+
+            let mut recent = history.clone();
+            drop_oldest(&mut recent, token_budget / 2, 0);
+            let older = &history[..history.len() - recent.len()];
+
+            let response = self
+                .model
+                .completion_request(Message::user(transcript(older)))
+                .preamble(
+                    "Summarize the following conversation between a user and an AI assistant. \
+                    Keep the facts, decisions and open questions needed to continue the \
+                    conversation, in at most a few paragraphs."
+                        .to_string(),
+                )
+                .send()
+                .await?;
+            let summary = response.text().unwrap_or_default();
+
+            let mut truncated = vec![Message::user(format!(
+                "Summary of the earlier conversation:\n{summary}"
+            ))];
+            truncated.extend(recent);
+            Ok(truncated)
+        })
+    }
+}
+
+/// Plain text transcript of messages, to be summarized.
+fn transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .flat_map(|message| match message {
+            Message::User { content } => content
+                .iter()
+                .filter_map(|content| match content {
+                    UserContent::Text(text) => Some(format!("User: {}", text.text)),
+                    UserContent::ToolResult(result) => Some(format!(
+                        "Tool result ({}): {}",
+                        result.id,
+                        serde_json::to_string(&result.content).unwrap_or_default()
+                    )),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            Message::Assistant { content } => content
+                .iter()
+                .map(|content| match content {
+                    AssistantContent::Text(text) => format!("Assistant: {}", text.text),
+                    AssistantContent::ToolCall(tool_call) => format!(
+                        "Assistant called the tool `{}` ({}) with {}",
+                        tool_call.function.name, tool_call.id, tool_call.function.arguments
+                    ),
+                })
+                .collect::<Vec<_>>(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Drop the oldest messages of `history` until it fits in `token_budget` tokens, without dropping
+/// its last `protected` messages. The history is then made to start with a user prompt, as a
+/// tool result or an assistant message without the preceding messages is rejected by most
+/// providers.
+pub(crate) fn drop_oldest(history: &mut Vec<Message>, token_budget: usize, protected: usize) {
+    let droppable = history.len().saturating_sub(protected);
+    let mut tokens = history.iter().map(estimate_tokens).sum::<usize>();
+
+    let mut dropped = 0;
+    while dropped < droppable && tokens > token_budget {
+        tokens -= estimate_tokens(&history[dropped]);
+        dropped += 1;
+    }
+    if dropped > 0 {
+        while dropped < droppable && !is_prompt(&history[dropped]) {
+            dropped += 1;
+        }
+    }
+
+    history.drain(..dropped);
+}
+
+/// Whether the message is a user prompt, as opposed to an assistant message or tool results.
+fn is_prompt(message: &Message) -> bool {
+    match message {
+        Message::User { content } => !content
+            .iter()
+            .any(|content| matches!(content, UserContent::ToolResult(_))),
+        Message::Assistant { .. } => false,
+    }
+}
+
+/// Rough estimate of the number of tokens of a message: the tokens of its contents (see
+/// [crate::tokens::estimate_tokens]) plus a few tokens of overhead per message, for budgeting purposes only.
+pub fn estimate_tokens(message: &Message) -> usize {
+    const MESSAGE_OVERHEAD: usize = 4;
+
+    let tokens = match message {
+        Message::User { content } => content
+            .iter()
+            .map(|content| match content {
+                UserContent::Text(text) => estimate_text_tokens(&text.text),
+                UserContent::ToolResult(result) => serde_json::to_string(&result.content)
+                    .map(|content| estimate_text_tokens(&content))
+                    .unwrap_or_default(),
+                UserContent::Image(image) => estimate_text_tokens(&image.data),
+                UserContent::Audio(audio) => estimate_text_tokens(&audio.data),
+                UserContent::Document(document) => estimate_text_tokens(&document.data),
+            })
+            .sum::<usize>(),
+        Message::Assistant { content } => content
+            .iter()
+            .map(|content| match content {
+                AssistantContent::Text(text) => estimate_text_tokens(&text.text),
+                AssistantContent::ToolCall(tool_call) => estimate_text_tokens(&format!(
+                    "{}{}",
+                    tool_call.function.name, tool_call.function.arguments
+                )),
+            })
+            .sum::<usize>(),
+    };
+
+    tokens + MESSAGE_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{message::ToolResultContent, OneOrMany};
+
+    #[tokio::test]
+    async fn test_in_memory_memory() {
+        let memory = InMemoryMemory::new();
+        memory
+            .append(vec![Message::user("Hello"), Message::assistant("Hi!")])
+            .await
+            .unwrap();
+        memory
+            .append(vec![Message::user("How are you?")])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            memory.load().await.unwrap(),
+            vec![
+                Message::user("Hello"),
+                Message::assistant("Hi!"),
+                Message::user("How are you?")
+            ]
+        );
+
+        memory.clear().await.unwrap();
+        assert!(memory.load().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_drops_oldest_turns() {
+        // Each message is about 10 tokens: a turn fits in the budget, but two turns do not
+        let message = |role: &str, i: usize| {
+            let text = format!("{role} message {i:>5}");
+            match role {
+                "user" => Message::user(text),
+                _ => Message::assistant(text),
+            }
+        };
+        let memory = InMemoryMemory::new().with_token_budget(20);
+
+        for i in 0..3 {
+            memory
+                .append(vec![message("user", i), message("assistant", i)])
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            memory.messages(),
+            vec![message("user", 2), message("assistant", 2)]
+        );
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_tool_results_with_their_call() {
+        let mut history = vec![
+            Message::user("What is 2 + 3?"),
+            Message::Assistant {
+                content: OneOrMany::one(AssistantContent::tool_call(
+                    "call_0",
+                    "add",
+                    serde_json::json!({"x": 2, "y": 3}),
+                )),
+            },
+            Message::User {
+                content: OneOrMany::one(UserContent::tool_result(
+                    "call_0",
+                    OneOrMany::one(ToolResultContent::text("5")),
+                )),
+            },
+            Message::assistant("2 + 3 = 5"),
+            Message::user("Thanks!"),
+        ];
+
+        drop_oldest(&mut history, 10, 1);
+        assert_eq!(history, vec![Message::user("Thanks!")]);
+    }
+
+    fn long_history() -> Vec<Message> {
+        (0..4)
+            .flat_map(|i| {
+                [
+                    Message::user(format!("Question {i}: {}", "?".repeat(40))),
+                    Message::assistant(format!("Answer {i}: {}", "!".repeat(40))),
+                ]
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_truncation() {
+        let history = long_history();
+        let truncated = DropOldest.truncate(history.clone(), 40).await.unwrap();
+
+        assert_eq!(truncated, history[6..]);
+        assert_eq!(
+            DropOldest.truncate(history.clone(), 1_000).await.unwrap(),
+            history
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summarize_truncation() {
+        use crate::completion::{CompletionRequest, CompletionResponse};
+
+        /// Model answering with the number of lines of the transcript to summarize
+        #[derive(Clone)]
+        struct SummaryModel;
+
+        impl CompletionModel for SummaryModel {
+            type Response = ();
+
+            async fn completion(
+                &self,
+                request: CompletionRequest,
+            ) -> Result<CompletionResponse<()>, CompletionError> {
+                let Message::User { content } =
+                    request.chat_history.iter().last().cloned().unwrap()
+                else {
+                    panic!("The transcript should be sent as a user message")
+                };
+                let UserContent::Text(transcript) = content.first() else {
+                    panic!("The transcript should be text")
+                };
+
+                Ok(CompletionResponse {
+                    choice: OneOrMany::one(AssistantContent::text(format!(
+                        "{} messages",
+                        transcript.text.lines().count()
+                    ))),
+                    raw_response: (),
+                })
+            }
+        }
+
+        let history = long_history();
+        let truncated = Summarize::new(SummaryModel)
+            .truncate(history.clone(), 80)
+            .await
+            .unwrap();
+
+        let mut expected = vec![Message::user(
+            "Summary of the earlier conversation:\n6 messages",
+        )];
+        expected.extend_from_slice(&history[6..]);
+        assert_eq!(truncated, expected);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(&Message::user("")), 4);
+        assert_eq!(estimate_tokens(&Message::user("12345678")), 6);
+        assert_eq!(estimate_tokens(&Message::assistant("123456789")), 7);
+    }
+}
@@ -40,6 +40,15 @@ pub const ANTHROPIC_VERSION_2023_01_01: &str = "2023-01-01";
 pub const ANTHROPIC_VERSION_2023_06_01: &str = "2023-06-01";
 pub const ANTHROPIC_VERSION_LATEST: &str = ANTHROPIC_VERSION_2023_06_01;
 
+/// Names of the sampling parameters of the Anthropic messages API
+pub(crate) const SAMPLING_PARAMS: completion::SamplingParams = completion::SamplingParams {
+    provider: "Anthropic",
+    top_p: Some("top_p"),
+    top_k: Some("top_k"),
+    stop_sequences: Some("stop_sequences"),
+    ..completion::SamplingParams::NONE
+};
+
 #[derive(Debug, Deserialize)]
 pub struct CompletionResponse {
     pub content: Vec<Content>,
@@ -542,6 +551,8 @@ impl completion::CompletionModel for CompletionModel {
         // specific requirements of each provider. For now, we just manually check while
         // building the request as a raw JSON document.
 
+        let sampling_params = completion_request.sampling_params(&SAMPLING_PARAMS);
+
         // Check if max_tokens is set, required for Anthropic
         let max_tokens = if let Some(tokens) = completion_request.max_tokens {
             tokens
@@ -575,6 +586,8 @@ impl completion::CompletionModel for CompletionModel {
             json_utils::merge_inplace(&mut request, json!({ "temperature": temperature }));
         }
 
+        json_utils::merge_inplace(&mut request, sampling_params);
+
         if !completion_request.tools.is_empty() {
             json_utils::merge_inplace(
                 &mut request,
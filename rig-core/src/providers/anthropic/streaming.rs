@@ -87,6 +87,9 @@ impl StreamingCompletionModel for CompletionModel {
         completion_request: CompletionRequest,
     ) -> Result<streaming::StreamingCompletionResponse<Self::StreamingResponse>, CompletionError>
     {
+        let sampling_params =
+            completion_request.sampling_params(&super::completion::SAMPLING_PARAMS);
+
         let max_tokens = if let Some(tokens) = completion_request.max_tokens {
             tokens
         } else if let Some(tokens) = self.default_max_tokens {
@@ -120,6 +123,8 @@ impl StreamingCompletionModel for CompletionModel {
             merge_inplace(&mut request, json!({ "temperature": temperature }));
         }
 
+        merge_inplace(&mut request, sampling_params);
+
         if !completion_request.tools.is_empty() {
             merge_inplace(
                 &mut request,
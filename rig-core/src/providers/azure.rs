@@ -480,6 +480,11 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<serde_json::Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Azure OpenAI",
+            ..completion::SamplingParams::OPENAI
+        });
+
         let mut full_history: Vec<openai::Message> = match &completion_request.preamble {
             Some(preamble) => vec![openai::Message::system(preamble)],
             None => vec![],
@@ -515,6 +520,8 @@ impl CompletionModel {
             })
         };
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
@@ -819,6 +826,12 @@ mod azure_tests {
                 chat_history: OneOrMany::one("Hello!".into()),
                 documents: vec![],
                 max_tokens: Some(100),
+                top_p: None,
+                top_k: None,
+                stop_sequences: None,
+                seed: None,
+                frequency_penalty: None,
+                presence_penalty: None,
                 temperature: Some(0.0),
                 tools: vec![],
                 additional_params: None,
@@ -0,0 +1,197 @@
+//! Caches of the embeddings of a Cohere [EmbeddingModel](super::EmbeddingModel), so that texts
+//! embedded before (e.g.: when re-running an indexing pipeline) are not embedded again.
+//!
+//! Embeddings are cached under a hash of the model, the input type and the text (see
+//! [cache_key]). Only the texts missing from the cache are sent to the Cohere API.
+//!
+//! # Example
+//! ```
+//! use rig::providers::cohere::{self, cache::DiskEmbeddingCache, EmbeddingModel};
+//!
+//! let client = cohere::Client::new("YOUR_API_KEY");
+//! let model = EmbeddingModel::builder(client, cohere::EMBED_ENGLISH_V3)
+//!     .cache(DiskEmbeddingCache::new(".embeddings")?)
+//!     .build();
+//! ```
+
+use std::{collections::HashMap, sync::RwLock};
+
+use sha2::{Digest, Sha256};
+
+use super::InputType;
+
+/// Storage of embeddings by cache key (see [cache_key]).
+///
+/// Caches are best effort: a cache failing to read or write an embedding should behave as if the
+/// embedding was missing (i.e.: the text is embedded again) rather than fail the request.
+pub trait EmbeddingCache: Send + Sync {
+    /// The embedding cached under `key`, if any.
+    fn get(&self, key: &str) -> Option<Vec<f64>>;
+
+    /// Cache `embedding` under `key`.
+    fn insert(&self, key: &str, embedding: &[f64]);
+}
+
+/// Cache key of the embedding of `text` by `model` with the input type `input_type`: the hex
+/// encoded SHA-256 hash of the three, stable across runs and platforms.
+pub fn cache_key(model: &str, input_type: &InputType, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    for field in [model, &input_type.to_string(), text] {
+        // Length prefixes, so that e.g. ("ab", "c") and ("a", "bc") give different keys
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Cache keeping the embeddings in memory, for the lifetime of the process.
+#[derive(Default)]
+pub struct InMemoryEmbeddingCache {
+    embeddings: RwLock<HashMap<String, Vec<f64>>>,
+}
+
+impl InMemoryEmbeddingCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached embeddings.
+    pub fn len(&self) -> usize {
+        self.embeddings
+            .read()
+            .map_or(0, |embeddings| embeddings.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl EmbeddingCache for InMemoryEmbeddingCache {
+    fn get(&self, key: &str) -> Option<Vec<f64>> {
+        self.embeddings.read().ok()?.get(key).cloned()
+    }
+
+    fn insert(&self, key: &str, embedding: &[f64]) {
+        if let Ok(mut embeddings) = self.embeddings.write() {
+            embeddings.insert(key.to_string(), embedding.to_vec());
+        }
+    }
+}
+
+/// Cache keeping each embedding in a JSON file of a directory, named after its cache key, so that
+/// the embeddings are reused across runs.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct DiskEmbeddingCache {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskEmbeddingCache {
+    /// Cache the embeddings in `directory`, created if it does not exist.
+    pub fn new(directory: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(format!("{key}.json"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl EmbeddingCache for DiskEmbeddingCache {
+    fn get(&self, key: &str) -> Option<Vec<f64>> {
+        let file = std::fs::read(self.path(key)).ok()?;
+
+        serde_json::from_slice(&file)
+            .inspect_err(|error| {
+                tracing::warn!(target: "rig", "Ignoring invalid cached embedding {key}: {error}")
+            })
+            .ok()
+    }
+
+    fn insert(&self, key: &str, embedding: &[f64]) {
+        // Write to a temporary file first, so that concurrent readers never see a partial file
+        let path = self.path(key);
+        let temporary = path.with_extension(format!("{}.tmp", fastrand::u64(..)));
+        let written = serde_json::to_vec(embedding)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&temporary, json))
+            .and_then(|_| std::fs::rename(&temporary, &path));
+
+        if let Err(error) = written {
+            let _ = std::fs::remove_file(&temporary);
+            tracing::warn!(target: "rig", "Failed to cache embedding {key}: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, DiskEmbeddingCache, EmbeddingCache, InMemoryEmbeddingCache};
+    use crate::providers::cohere::InputType;
+
+    #[test]
+    fn test_cache_key() {
+        let key = cache_key("embed-english-v3.0", &InputType::SearchDocument, "Hello");
+        assert_eq!(key.len(), 64);
+        assert_eq!(
+            key,
+            cache_key("embed-english-v3.0", &InputType::SearchDocument, "Hello")
+        );
+
+        assert_ne!(
+            key,
+            cache_key("embed-english-v3.0", &InputType::SearchQuery, "Hello")
+        );
+        assert_ne!(
+            key,
+            cache_key(
+                "embed-multilingual-v3.0",
+                &InputType::SearchDocument,
+                "Hello"
+            )
+        );
+        assert_ne!(
+            cache_key("ab", &InputType::SearchDocument, "c"),
+            cache_key("a", &InputType::SearchDocument, "bc")
+        );
+    }
+
+    #[test]
+    fn test_in_memory_cache() {
+        let cache = InMemoryEmbeddingCache::new();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("key"), None);
+
+        cache.insert("key", &[0.5, -1.0]);
+        assert_eq!(cache.get("key"), Some(vec![0.5, -1.0]));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let temp = assert_fs::TempDir::new().expect("Failed to create temp dir");
+        let directory = temp.path().join("embeddings");
+
+        let cache = DiskEmbeddingCache::new(&directory).unwrap();
+        assert_eq!(cache.get("key"), None);
+        cache.insert("key", &[0.5, -1.0]);
+        assert_eq!(cache.get("key"), Some(vec![0.5, -1.0]));
+
+        // Embeddings persist across instances, invalid files being ignored
+        let cache = DiskEmbeddingCache::new(&directory).unwrap();
+        assert_eq!(cache.get("key"), Some(vec![0.5, -1.0]));
+        std::fs::write(directory.join("other.json"), "not json").unwrap();
+        assert_eq!(cache.get("other"), None);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! Cohere classification API (`/v1/classify`)
+//!
+//! # Example
+//! ```
+//! use rig::providers::cohere::{self, EMBED_ENGLISH_V3};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = cohere::Client::new("YOUR_API_KEY");
+//! let classifier = client.classify_model(EMBED_ENGLISH_V3);
+//!
+//! let classifications = classifier
+//!     .classify(
+//!         vec!["Where is my order?".to_string()],
+//!         vec![
+//!             ("Has my package shipped?".to_string(), "shipping".to_string()),
+//!             ("When will my order arrive?".to_string(), "shipping".to_string()),
+//!             ("I want my money back".to_string(), "refund".to_string()),
+//!             ("Can I get a refund?".to_string(), "refund".to_string()),
+//!         ],
+//!     )
+//!     .await?;
+//!
+//! println!("{:?}", classifications[0].prediction());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    client::{ApiErrorResponse, ApiResponse},
+    embeddings::Meta,
+    usage::BilledUnits,
+    Client,
+};
+
+/// Maximum number of inputs classified per request by the Cohere API
+pub const MAX_INPUTS: usize = 96;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClassifyError {
+    /// Http error (e.g.: connection error, timeout, etc.)
+    #[error("HttpError: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    /// Json error (e.g.: serialization, deserialization)
+    #[error("JsonError: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// Error returned by the Cohere API
+    #[error("ProviderError: {0}")]
+    ProviderError(String),
+}
+
+/// A labeled example used for few-shot classification.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Example {
+    pub text: String,
+    pub label: String,
+}
+
+impl From<(String, String)> for Example {
+    fn from((text, label): (String, String)) -> Self {
+        Self { text, label }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClassifyResponse {
+    pub id: String,
+    pub classifications: Vec<Classification>,
+    #[serde(default)]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Classification {
+    pub id: String,
+    #[serde(default)]
+    pub input: Option<String>,
+    /// Predicted labels (a single one for single-label classifiers)
+    #[serde(default)]
+    pub predictions: Vec<String>,
+    /// Confidence of each predicted label
+    #[serde(default)]
+    pub confidences: Vec<f64>,
+    /// Confidence distribution over all the labels
+    #[serde(default)]
+    pub labels: HashMap<String, LabelConfidence>,
+    #[serde(default)]
+    pub classification_type: Option<ClassificationType>,
+}
+
+impl Classification {
+    /// The most likely label, if any.
+    pub fn prediction(&self) -> Option<&str> {
+        self.predictions.first().map(String::as_str)
+    }
+
+    /// Confidence of the most likely label, if any.
+    pub fn confidence(&self) -> Option<f64> {
+        self.confidences.first().copied()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LabelConfidence {
+    pub confidence: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClassificationType {
+    SingleLabel,
+    MultiLabel,
+}
+
+#[derive(Clone)]
+pub struct ClassifyModel {
+    client: Client,
+    /// Name of the embedding model or fine-tuned classification model used to classify inputs
+    pub model: String,
+}
+
+impl ClassifyModel {
+    pub fn new(client: Client, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+        }
+    }
+
+    /// Classify each of the `inputs` using the labeled `examples` as `(text, label)` pairs.
+    /// Fine-tuned classification models can be used without examples.
+    ///
+    /// Inputs are sent in batches of [MAX_INPUTS], and the classifications are returned in the
+    /// same order as the inputs.
+    pub async fn classify(
+        &self,
+        inputs: Vec<String>,
+        examples: Vec<(String, String)>,
+    ) -> Result<Vec<Classification>, ClassifyError> {
+        let (classifications, _) = self.classify_with_usage(inputs, examples).await?;
+        Ok(classifications)
+    }
+
+    /// Classify `inputs` like [classify](Self::classify), also returning the units billed for
+    /// all the requests sent.
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_classify",
+        skip_all,
+        fields(
+            model = %self.model,
+            inputs = inputs.len(),
+            examples = examples.len(),
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
+    pub async fn classify_with_usage(
+        &self,
+        inputs: Vec<String>,
+        examples: Vec<(String, String)>,
+    ) -> Result<(Vec<Classification>, BilledUnits), ClassifyError> {
+        let examples = examples.into_iter().map(Example::from).collect::<Vec<_>>();
+
+        let mut classifications = Vec::with_capacity(inputs.len());
+        let mut billed_units = BilledUnits::default();
+        for inputs in inputs.chunks(MAX_INPUTS) {
+            let mut request = json!({
+                "model": self.model,
+                "inputs": inputs,
+            });
+            if !examples.is_empty() {
+                request["examples"] = json!(examples);
+            }
+
+            let response = self
+                .client
+                .send(self.client.post("/v1/classify").json(&request))
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let message = ApiErrorResponse::message_from_body(response.text().await?);
+                return Err(ClassifyError::ProviderError(format!("{status}: {message}")));
+            }
+
+            match serde_json::from_str::<ApiResponse<ClassifyResponse>>(&response.text().await?)? {
+                ApiResponse::Ok(response) => {
+                    if let Some(meta) = &response.meta {
+                        tracing::info!(target: "rig",
+                            "Cohere classify billed units: {}",
+                            meta.billed_units,
+                        );
+                        billed_units += meta.billed_units;
+                    }
+
+                    classifications.extend(response.classifications);
+                }
+                ApiResponse::Err(error) => return Err(ClassifyError::ProviderError(error.message)),
+            }
+        }
+
+        Ok((classifications, billed_units))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClassificationType, ClassifyResponse};
+
+    #[test]
+    fn test_deserialize_classify_response() {
+        let json_data = r#"
+        {
+            "id": "86b4a3b3-2c2f-4a1f-9c4c-2a3d1b6b6f0e",
+            "classifications": [
+                {
+                    "id": "842d12eb-d9bc-4b4a-8c4e-6b5b3c3f2e1a",
+                    "input": "Where is my order?",
+                    "prediction": "shipping",
+                    "predictions": ["shipping"],
+                    "confidence": 0.92,
+                    "confidences": [0.92],
+                    "labels": {
+                        "refund": { "confidence": 0.08 },
+                        "shipping": { "confidence": 0.92 }
+                    },
+                    "classification_type": "single-label"
+                }
+            ],
+            "meta": {
+                "api_version": { "version": "1" },
+                "billed_units": { "classifications": 1 }
+            }
+        }
+        "#;
+
+        let response: ClassifyResponse = serde_json::from_str(json_data).unwrap();
+        let classification = &response.classifications[0];
+
+        assert_eq!(classification.prediction(), Some("shipping"));
+        assert_eq!(classification.confidence(), Some(0.92));
+        assert_eq!(classification.labels["refund"].confidence, 0.08);
+        assert_eq!(
+            classification.classification_type,
+            Some(ClassificationType::SingleLabel)
+        );
+        assert_eq!(response.meta.unwrap().billed_units.classifications, 1);
+    }
+}
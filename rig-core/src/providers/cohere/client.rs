@@ -2,16 +2,34 @@ use crate::{
     agent::AgentBuilder, embeddings::EmbeddingsBuilder, extractor::ExtractorBuilder, Embed,
 };
 
+use std::{io::Write, sync::Arc, time::Duration};
+
+use flate2::{write::GzEncoder, Compression};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use super::{CompletionModel, EmbeddingModel};
+use super::{
+    inspect, ClassifyModel, CompletionModel, DocumentEmbeddingModel, EmbeddingModel, InputType,
+    QueryEmbeddingModel, RequestInspector, RetryPolicy,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct ApiErrorResponse {
     pub message: String,
 }
 
+impl ApiErrorResponse {
+    /// Extract the `message` of an error response body of the Cohere API, falling back to the
+    /// raw body if it is not a JSON error.
+    pub(crate) fn message_from_body(body: String) -> String {
+        serde_json::from_str::<ApiErrorResponse>(&body)
+            .map(|error| error.message)
+            .unwrap_or(body)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum ApiResponse<T> {
@@ -19,73 +37,647 @@ pub enum ApiResponse<T> {
     Err(ApiErrorResponse),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum CohereError {
+    /// Http error (e.g.: connection error, timeout, etc.)
+    #[error("HttpError: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    /// The response of the Cohere API could not be deserialized (e.g.: after a change of its
+    /// schema). Unlike [CohereError::HttpError], retrying the request will not help.
+    #[error("JsonError: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// The API key is missing, invalid or lacks the permissions for the request
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// A required environment variable is not set (or is empty)
+    #[error("Environment variable {0} is not set")]
+    MissingEnvVar(&'static str),
+
+    /// Error returned by the Cohere API
+    #[error("ProviderError ({status}): {message}")]
+    ProviderError {
+        status: reqwest::StatusCode,
+        message: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckApiKeyResponse {
+    valid: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListModelsResponse {
+    models: Vec<ModelInfo>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// A model available through the Cohere API
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ModelInfo {
+    pub name: String,
+    /// API endpoints the model can be used with (e.g.: `chat`, `embed`, `classify`)
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub finetuned: bool,
+    #[serde(default)]
+    pub context_length: Option<f64>,
+    #[serde(default)]
+    pub default_endpoints: Vec<String>,
+}
+
+impl ModelInfo {
+    /// Whether the model can be used with the given endpoint (e.g.: `chat`)
+    pub fn supports(&self, endpoint: &str) -> bool {
+        self.endpoints.iter().any(|e| e == endpoint)
+    }
+}
+
 // ================================================================
 // Main Cohere Client
 // ================================================================
 const COHERE_API_BASE_URL: &str = "https://api.cohere.ai";
+const COHERE_CHAT_PATH: &str = "/v2/chat";
+const COHERE_EMBED_PATH: &str = "/v1/embed";
+const COHERE_FEEDBACK_PATH: &str = "/v1/feedback/generate";
+/// User-Agent sent by default, identifying the version of rig
+const DEFAULT_USER_AGENT: &str = concat!("rig/", env!("CARGO_PKG_VERSION"));
+/// Minimum size of the bodies compressed by a client with [ClientBuilder::gzip_requests], as
+/// compressing smaller bodies saves little
+const GZIP_MIN_BODY_SIZE: usize = 1024;
 
+#[derive(Clone)]
+pub struct ClientBuilder<'a> {
+    api_key: &'a str,
+    base_url: &'a str,
+    headers: HeaderMap,
+    http_client: Option<reqwest::Client>,
+    chat_path: &'a str,
+    embed_path: &'a str,
+    feedback_path: &'a str,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    gzip_requests: bool,
+    max_response_size: Option<usize>,
+}
+
+/// Create a new Cohere client using the builder
+///
+/// # Example
+/// ```
+/// use rig::providers::cohere::ClientBuilder;
+///
+/// // Initialize the Cohere client
+/// let cohere_client = ClientBuilder::new("your-cohere-api-key")
+///     .header("X-Proxy-Authorization", "your-proxy-token")
+///     .build();
+/// ```
+impl<'a> ClientBuilder<'a> {
+    pub fn new(api_key: &'a str) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::USER_AGENT,
+            HeaderValue::from_static(DEFAULT_USER_AGENT),
+        );
+
+        Self {
+            api_key,
+            base_url: COHERE_API_BASE_URL,
+            headers,
+            http_client: None,
+            chat_path: COHERE_CHAT_PATH,
+            embed_path: COHERE_EMBED_PATH,
+            feedback_path: COHERE_FEEDBACK_PATH,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            gzip_requests: false,
+            max_response_size: None,
+        }
+    }
+
+    pub fn base_url(mut self, base_url: &'a str) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Add a header sent with every request.
+    /// Panics if the header name or value is invalid.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).expect("Header name should be valid"),
+            HeaderValue::from_str(value).expect("Header value should be valid"),
+        );
+        self
+    }
+
+    /// Set the User-Agent sent with every request, e.g.: to identify the application in the
+    /// Cohere logs (default: `rig/<version>`). Also applies to a client given with
+    /// [ClientBuilder::http_client].
+    /// Panics if the User-Agent is not a valid header value.
+    pub fn user_agent(self, user_agent: &str) -> Self {
+        self.header(reqwest::header::USER_AGENT.as_str(), user_agent)
+    }
+
+    /// Add headers sent with every request.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Use a pre-configured `reqwest::Client` (e.g.: with a proxy or custom timeouts).
+    /// The API key is added to each request, so the client does not need to set it.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Path of the chat endpoint, relative to the base URL (default: `/v2/chat`).
+    /// The endpoint must accept the requests and return the responses of the Cohere v2 chat API
+    /// (e.g.: a compatible proxy).
+    pub fn chat_path(mut self, chat_path: &'a str) -> Self {
+        self.chat_path = chat_path;
+        self
+    }
+
+    /// Path of the embed endpoint, relative to the base URL (default: `/v1/embed`).
+    /// The endpoint must accept the requests and return the responses of the Cohere v1 embed API
+    /// (e.g.: a compatible proxy).
+    pub fn embed_path(mut self, embed_path: &'a str) -> Self {
+        self.embed_path = embed_path;
+        self
+    }
+
+    /// Path of the generation feedback endpoint, relative to the base URL
+    /// (default: `/v1/feedback/generate`), used by [Client::submit_feedback].
+    pub fn feedback_path(mut self, feedback_path: &'a str) -> Self {
+        self.feedback_path = feedback_path;
+        self
+    }
+
+    /// Maximum number of idle connections kept open per host for reuse, avoiding a new TCP and
+    /// TLS handshake for each request (default: no limit, as in `reqwest`).
+    /// Ignored if a pre-configured client is given with [ClientBuilder::http_client].
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max_idle);
+        self
+    }
+
+    /// How long an idle connection is kept open for reuse before being closed
+    /// (default: 90 seconds, as in `reqwest`).
+    /// Ignored if a pre-configured client is given with [ClientBuilder::http_client].
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Compress the bodies of the requests (of at least 1 KiB) with gzip, sending them with the
+    /// `Content-Encoding: gzip` header, e.g.: to speed up the upload of large embedding batches
+    /// over a slow network. Disabled by default.
+    ///
+    /// Note: a [RequestInspector] sees the compressed body of the requests.
+    pub fn gzip_requests(mut self, gzip_requests: bool) -> Self {
+        self.gzip_requests = gzip_requests;
+        self
+    }
+
+    /// Maximum size (in bytes) of the body of the completion responses, failing the completions
+    /// with larger responses (e.g.: unusually large tool call arguments) instead of buffering
+    /// them (default: no limit).
+    ///
+    /// Non-streaming completion responses are buffered before being parsed, so a completion
+    /// takes about 3 times the size of its response body in memory at its peak (the body, its
+    /// parsed response and the converted response). Use streaming completions to process long
+    /// outputs incrementally.
+    pub fn max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default_http_client(&self) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder();
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        builder.build().expect("Cohere reqwest client should build")
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn default_http_client(&self) -> reqwest::Client {
+        reqwest::Client::default()
+    }
+
+    pub fn build(self) -> Client {
+        let http_client = match self.http_client.clone() {
+            Some(http_client) => http_client,
+            None => self.default_http_client(),
+        };
+
+        Client::from_inner(ClientInner {
+            base_url: self.base_url.to_string(),
+            api_key: self.api_key.to_string(),
+            headers: self.headers,
+            http_client,
+            retry_policy: RetryPolicy::default(),
+            inspector: None,
+            chat_path: self.chat_path.to_string(),
+            embed_path: self.embed_path.to_string(),
+            feedback_path: self.feedback_path.to_string(),
+            gzip_requests: self.gzip_requests,
+            max_response_size: self.max_response_size,
+            middlewares: vec![],
+        })
+    }
+}
+
+/// Cohere API client.
+///
+/// Cloning a client is cheap: its configuration is shared behind an `Arc` (and the inner
+/// `reqwest::Client`, with its connection pool, is itself reference counted), so a clone per
+/// task or per request (e.g.: the clone held by each model created from the client) only costs
+/// a reference count increment.
 #[derive(Clone)]
 pub struct Client {
+    inner: Arc<ClientInner>,
+}
+
+#[derive(Clone)]
+struct ClientInner {
     base_url: String,
+    api_key: String,
+    headers: HeaderMap,
     http_client: reqwest::Client,
+    retry_policy: RetryPolicy,
+    inspector: Option<Arc<dyn RequestInspector>>,
+    chat_path: String,
+    embed_path: String,
+    feedback_path: String,
+    gzip_requests: bool,
+    max_response_size: Option<usize>,
+    middlewares: Vec<Middleware>,
 }
 
+/// Function applied to every request of a [Client] just before it is sent (see
+/// [Client::with_middleware]).
+pub type Middleware = Arc<dyn Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder + Send + Sync>;
+
 impl Client {
     pub fn new(api_key: &str) -> Self {
-        Self::from_url(api_key, COHERE_API_BASE_URL)
+        ClientBuilder::new(api_key).build()
     }
 
-    pub fn from_url(api_key: &str, base_url: &str) -> Self {
+    fn from_inner(inner: ClientInner) -> Self {
         Self {
-            base_url: base_url.to_string(),
-            http_client: reqwest::Client::builder()
-                .default_headers({
-                    let mut headers = reqwest::header::HeaderMap::new();
-                    headers.insert(
-                        "Authorization",
-                        format!("Bearer {}", api_key)
-                            .parse()
-                            .expect("Bearer token should parse"),
-                    );
-                    headers
-                })
-                .build()
-                .expect("Cohere reqwest client should build"),
+            inner: Arc::new(inner),
         }
     }
 
+    /// Create a new Cohere client sending its requests to `base_url` instead of the Cohere API.
+    /// The base URL may include a path prefix (e.g.: `https://gateway.example.com/cohere` for
+    /// an API gateway), which is preserved in the URL of each request.
+    pub fn from_url(api_key: &str, base_url: &str) -> Self {
+        ClientBuilder::new(api_key).base_url(base_url).build()
+    }
+
+    /// Create a new Cohere client sending its requests with the given `reqwest::Client`.
+    pub fn with_http_client(api_key: &str, http_client: reqwest::Client) -> Self {
+        ClientBuilder::new(api_key).http_client(http_client).build()
+    }
+
+    /// Set the policy used to retry requests that were rate limited (429) or failed
+    /// with a transient server error (502, 503, 504).
+    /// Use [RetryPolicy::none] to disable retries.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        Arc::make_mut(&mut self.inner).retry_policy = retry_policy;
+        self
+    }
+
+    /// Clone of this client authenticating its requests with `api_key` instead (e.g.: the API key
+    /// of a tenant in a multi-tenant service). The clone shares the connection pool, headers,
+    /// retry policy and request inspector of this client.
+    pub fn with_api_key(&self, api_key: &str) -> Self {
+        Self::from_inner(ClientInner {
+            api_key: api_key.to_string(),
+            ..self.inner.as_ref().clone()
+        })
+    }
+
+    /// Set a hook receiving every request sent to the Cohere API (without its `Authorization`
+    /// header) and the response it got, e.g.: to log them after redacting sensitive data.
+    pub fn with_request_inspector(mut self, inspector: Arc<dyn RequestInspector>) -> Self {
+        Arc::make_mut(&mut self.inner).inspector = Some(inspector);
+        self
+    }
+
+    /// Add a middleware modifying every request just before it is sent, e.g.: to inject tracing
+    /// context, signing headers or a tenant id. Middlewares are applied in the order they were
+    /// added, after the compression of the body (see [ClientBuilder::gzip_requests]) and before
+    /// the [RequestInspector]. A middleware is applied once per request, even if it is retried.
+    ///
+    /// # Example
+    /// ```
+    /// use rig::providers::cohere;
+    /// # fn current_tenant() -> String { "acme".to_string() }
+    /// # fn current_traceparent() -> String {
+    /// #     "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string()
+    /// # }
+    ///
+    /// let client = cohere::Client::new("YOUR_API_KEY")
+    ///     .with_middleware(|request| request.header("X-Tenant-Id", current_tenant()))
+    ///     .with_middleware(|request| request.header("traceparent", current_traceparent()));
+    /// ```
+    pub fn with_middleware(
+        mut self,
+        middleware: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.inner)
+            .middlewares
+            .push(Arc::new(middleware));
+        self
+    }
+
     /// Create a new Cohere client from the `COHERE_API_KEY` environment variable.
     /// Panics if the environment variable is not set.
     pub fn from_env() -> Self {
-        let api_key = std::env::var("COHERE_API_KEY").expect("COHERE_API_KEY not set");
-        Self::new(&api_key)
+        Self::try_from_env().expect("COHERE_API_KEY not set")
+    }
+
+    /// Create a new Cohere client from the `COHERE_API_KEY` environment variable, and the
+    /// optional `COHERE_API_BASE_URL` environment variable.
+    /// Leading and trailing whitespace is trimmed from both values.
+    pub fn try_from_env() -> Result<Self, CohereError> {
+        Self::from_env_vars(
+            std::env::var("COHERE_API_KEY").ok(),
+            std::env::var("COHERE_API_BASE_URL").ok(),
+        )
+    }
+
+    fn from_env_vars(
+        api_key: Option<String>,
+        base_url: Option<String>,
+    ) -> Result<Self, CohereError> {
+        let api_key = api_key
+            .as_deref()
+            .map(str::trim)
+            .filter(|api_key| !api_key.is_empty())
+            .ok_or(CohereError::MissingEnvVar("COHERE_API_KEY"))?;
+
+        let mut builder = ClientBuilder::new(api_key);
+        if let Some(base_url) = base_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|base_url| !base_url.is_empty())
+        {
+            builder = builder.base_url(base_url);
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Path of the chat endpoint (see [ClientBuilder::chat_path]).
+    pub fn chat_path(&self) -> &str {
+        &self.inner.chat_path
+    }
+
+    /// Maximum size of the body of the completion responses (see
+    /// [ClientBuilder::max_response_size]).
+    pub fn max_response_size(&self) -> Option<usize> {
+        self.inner.max_response_size
+    }
+
+    /// Path of the embed endpoint (see [ClientBuilder::embed_path]).
+    pub fn embed_path(&self) -> &str {
+        &self.inner.embed_path
+    }
+
+    /// Path of the generation feedback endpoint (see [ClientBuilder::feedback_path]).
+    pub fn feedback_path(&self) -> &str {
+        &self.inner.feedback_path
     }
 
     pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
-        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
-        self.http_client.post(url)
+        self.request(reqwest::Method::POST, path)
+    }
+
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.request(reqwest::Method::GET, path)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let inner = &self.inner;
+        inner
+            .http_client
+            .request(method, build_url(&inner.base_url, path))
+            .bearer_auth(&inner.api_key)
+            .headers(inner.headers.clone())
+    }
+
+    /// Check that the API key is valid.
+    /// Returns [CohereError::Unauthorized] if the API key was rejected.
+    pub async fn ping(&self) -> Result<(), CohereError> {
+        let response: CheckApiKeyResponse = self.send_json(self.post("/v1/check-api-key")).await?;
+
+        if response.valid {
+            Ok(())
+        } else {
+            Err(CohereError::Unauthorized("Invalid API key".to_string()))
+        }
+    }
+
+    /// Report the quality of a generation (see
+    /// [CompletionResponse::generation_id](super::completion::CompletionResponse::generation_id)),
+    /// e.g.: from a thumbs-up/down button, with an optional comment.
+    ///
+    /// Returns [CohereError::Unauthorized] if the API key was rejected, and
+    /// [CohereError::ProviderError] if the feedback was rejected (e.g.: unknown generation id).
+    pub async fn submit_feedback(
+        &self,
+        generation_id: &str,
+        rating: Rating,
+        comment: Option<&str>,
+    ) -> Result<(), CohereError> {
+        let response = self
+            .send(self.post(self.feedback_path()).json(&feedback_request(
+                generation_id,
+                rating,
+                comment,
+            )))
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let message = ApiErrorResponse::message_from_body(response.text().await?);
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(CohereError::Unauthorized(message))
+            }
+            _ => Err(CohereError::ProviderError { status, message }),
+        }
     }
 
+    /// List the models available to the API key.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, CohereError> {
+        let mut models = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self.get("/v1/models").query(&[("page_size", "1000")]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("page_token", page_token)]);
+            }
+
+            let response: ListModelsResponse = self.send_json(request).await?;
+            models.extend(response.models);
+
+            match response.next_page_token {
+                Some(next_page_token) if !next_page_token.is_empty() => {
+                    page_token = Some(next_page_token)
+                }
+                _ => break,
+            }
+        }
+
+        Ok(models)
+    }
+
+    async fn send_json<T: for<'a> Deserialize<'a>>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, CohereError> {
+        let response = self.send(request).await?;
+        let status = response.status();
+
+        if status.is_success() {
+            // Read the body first, so that deserialization errors are not reported as http errors
+            Ok(serde_json::from_str(&response.text().await?)?)
+        } else {
+            let message = ApiErrorResponse::message_from_body(response.text().await?);
+
+            match status {
+                reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                    Err(CohereError::Unauthorized(message))
+                }
+                _ => Err(CohereError::ProviderError { status, message }),
+            }
+        }
+    }
+
+    /// Send a request built with this client, retrying it according to the client's [RetryPolicy].
+    ///
+    /// The HTTP status and the elapsed time (retries included) are recorded on the current
+    /// tracing span, as its `http.status` and `elapsed_ms` fields.
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        // `Instant` is not available on `wasm32-unknown-unknown`
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = std::time::Instant::now();
+
+        let request = if self.inner.gzip_requests {
+            self.gzip(request)
+        } else {
+            request
+        };
+        let request = self
+            .inner
+            .middlewares
+            .iter()
+            .fold(request, |request, middleware| middleware(request));
+
+        let retry_policy = &self.inner.retry_policy;
+        let response = match &self.inner.inspector {
+            Some(inspector) => inspect::send(inspector.as_ref(), retry_policy, request).await,
+            None => retry_policy.send(request).await,
+        };
+
+        let span = tracing::Span::current();
+        if let Ok(response) = &response {
+            span.record("http.status", response.status().as_u16());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+
+        response
+    }
+
+    /// Compress the body of `request` with gzip, if it is large enough. Requests whose body cannot
+    /// be read (e.g.: streamed bodies) are sent uncompressed.
+    fn gzip(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let Some(Ok(mut built)) = request.try_clone().map(reqwest::RequestBuilder::build) else {
+            return request;
+        };
+        let Some(body) = built.body().and_then(reqwest::Body::as_bytes) else {
+            return request;
+        };
+        if body.len() < GZIP_MIN_BODY_SIZE {
+            return request;
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let Ok(compressed) = encoder.write_all(body).and_then(|_| encoder.finish()) else {
+            return request;
+        };
+
+        *built.body_mut() = Some(compressed.into());
+        built.headers_mut().insert(
+            reqwest::header::CONTENT_ENCODING,
+            HeaderValue::from_static("gzip"),
+        );
+        reqwest::RequestBuilder::from_parts(self.inner.http_client.clone(), built)
+    }
+
+    /// Create an embedding model with the given name and input type.
+    /// `input_type` is either an [InputType] or its string representation (e.g.: `"search_document"`).
+    ///
     /// Note: default embedding dimension of 0 will be used if model is not known.
     /// If this is the case, it's better to use function `embedding_model_with_ndims`
-    pub fn embedding_model(&self, model: &str, input_type: &str) -> EmbeddingModel {
-        let ndims = match model {
-            super::EMBED_ENGLISH_V3
-            | super::EMBED_MULTILINGUAL_V3
-            | super::EMBED_ENGLISH_LIGHT_V2 => 1024,
-            super::EMBED_ENGLISH_LIGHT_V3 | super::EMBED_MULTILINGUAL_LIGHT_V3 => 384,
-            super::EMBED_ENGLISH_V2 => 4096,
-            super::EMBED_MULTILINGUAL_V2 => 768,
-            _ => 0,
-        };
+    pub fn embedding_model(&self, model: &str, input_type: impl Into<InputType>) -> EmbeddingModel {
+        let ndims = super::embeddings::known_ndims(model);
         EmbeddingModel::new(self.clone(), model, input_type, ndims)
     }
 
+    /// Create an embedding model for the documents searched over in a RAG setup
+    /// (i.e.: with the [InputType::SearchDocument] input type).
+    pub fn embedding_model_for_documents(&self, model: &str) -> EmbeddingModel {
+        self.embedding_model(model, InputType::SearchDocument)
+    }
+
+    /// Create an embedding model for the queries run against documents embedded with
+    /// [Client::embedding_model_for_documents] (i.e.: with the [InputType::SearchQuery] input type).
+    pub fn embedding_model_for_search(&self, model: &str) -> EmbeddingModel {
+        self.embedding_model(model, InputType::SearchQuery)
+    }
+
+    /// Create an embedding model for the documents searched over in a RAG setup, with its input
+    /// type checked at compile time (see [DocumentEmbeddingModel]).
+    pub fn document_embedding_model(&self, model: &str) -> DocumentEmbeddingModel {
+        DocumentEmbeddingModel::from_model(self.embedding_model_for_documents(model))
+    }
+
+    /// Create an embedding model for the queries run against documents embedded with
+    /// [Client::document_embedding_model], with its input type checked at compile time
+    /// (see [QueryEmbeddingModel]).
+    pub fn query_embedding_model(&self, model: &str) -> QueryEmbeddingModel {
+        QueryEmbeddingModel::from_model(self.embedding_model_for_search(model))
+    }
+
     /// Create an embedding model with the given name and the number of dimensions in the embedding generated by the model.
     pub fn embedding_model_with_ndims(
         &self,
         model: &str,
-        input_type: &str,
+        input_type: impl Into<InputType>,
         ndims: usize,
     ) -> EmbeddingModel {
         EmbeddingModel::new(self.clone(), model, input_type, ndims)
@@ -94,11 +686,18 @@ impl Client {
     pub fn embeddings<D: Embed>(
         &self,
         model: &str,
-        input_type: &str,
+        input_type: impl Into<InputType>,
     ) -> EmbeddingsBuilder<EmbeddingModel, D> {
         EmbeddingsBuilder::new(self.embedding_model(model, input_type))
     }
 
+    /// Create a classification model with the given name.
+    /// `model` is either an embedding model used for few-shot classification,
+    /// or a fine-tuned classification model.
+    pub fn classify_model(&self, model: &str) -> ClassifyModel {
+        ClassifyModel::new(self.clone(), model)
+    }
+
     pub fn completion_model(&self, model: &str) -> CompletionModel {
         CompletionModel::new(self.clone(), model)
     }
@@ -107,10 +706,502 @@ impl Client {
         AgentBuilder::new(self.completion_model(model))
     }
 
+    /// Create an extractor builder with the given completion model.
+    /// Models supporting structured outputs (the `command-r` and `command-a` families) are
+    /// constrained to the JSON schema of `T` with `response_format`, while older models extract
+    /// the data by calling a `submit` tool.
     pub fn extractor<T: JsonSchema + for<'a> Deserialize<'a> + Serialize + Send + Sync>(
         &self,
         model: &str,
     ) -> ExtractorBuilder<T, CompletionModel> {
-        ExtractorBuilder::new(self.completion_model(model))
+        let extractor = ExtractorBuilder::new(self.completion_model(model));
+
+        if supports_response_format(model) {
+            extractor.response_format(json!({
+                "response_format": {
+                    "type": "json_object",
+                    "json_schema": crate::extractor::json_schema::<T>(),
+                }
+            }))
+        } else {
+            extractor
+        }
+    }
+}
+
+/// Rating of a generation submitted with [Client::submit_feedback].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rating {
+    Good,
+    Bad,
+}
+
+impl From<bool> for Rating {
+    fn from(good: bool) -> Self {
+        if good {
+            Rating::Good
+        } else {
+            Rating::Bad
+        }
+    }
+}
+
+fn feedback_request(
+    generation_id: &str,
+    rating: Rating,
+    comment: Option<&str>,
+) -> serde_json::Value {
+    let mut request = serde_json::json!({
+        "request_id": generation_id,
+        "good_response": rating == Rating::Good,
+    });
+    if let Some(comment) = comment {
+        request["comment"] = comment.into();
+    }
+    request
+}
+
+fn supports_response_format(model: &str) -> bool {
+    model.starts_with("command-r") || model.starts_with("command-a")
+}
+
+/// Join the base URL of the client and the path of an endpoint with exactly one slash.
+///
+/// Only the slashes between the base URL and the path are collapsed, so that the scheme
+/// separator (`https://`) and any path prefix of the base URL are preserved.
+fn build_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        build_url, feedback_request, ApiErrorResponse, Client, ClientBuilder, Rating, RetryPolicy,
+    };
+
+    #[test]
+    fn test_request_headers() {
+        let client = ClientBuilder::new("test-key")
+            .header("X-Proxy-Authorization", "proxy-token")
+            .http_client(reqwest::Client::new())
+            .build();
+
+        let request = client.post("/v2/chat").build().unwrap();
+
+        assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+        assert_eq!(request.headers()["X-Proxy-Authorization"], "proxy-token");
+    }
+
+    #[test]
+    fn test_clone_shares_configuration() {
+        let client = ClientBuilder::new("test-key")
+            .header("X-Proxy-Authorization", "proxy-token")
+            .build();
+
+        let model = client.completion_model(super::super::COMMAND_R);
+        assert!(Arc::ptr_eq(&client.inner, &model.client.inner));
+        assert!(Arc::ptr_eq(&client.inner, &model.clone().client.inner));
+
+        // Configuring a clone does not change the configuration of the other clones
+        let no_retry = client.clone().with_retry_policy(RetryPolicy::none());
+        assert!(!Arc::ptr_eq(&client.inner, &no_retry.inner));
+        assert_eq!(
+            no_retry.post("/v2/chat").build().unwrap().headers()["X-Proxy-Authorization"],
+            "proxy-token"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        use super::super::testing::{TestResponse, TestServer};
+
+        let server = TestServer::serve(vec![TestResponse::new(200, "")]).await;
+
+        let client = Client::from_url("test-key", &server.base_url)
+            .with_middleware(|request| request.header("X-Tenant-Id", "tenant-1"))
+            .with_middleware(|request| request.header("X-Tenant-Id", "tenant-2"))
+            .with_middleware(|request| request.header("X-Signature", "signed"));
+        client.send(client.post("/v2/chat")).await.unwrap();
+        let request = server.requests()[0].to_lowercase();
+
+        // Middlewares are applied in order
+        assert!(
+            request.contains("x-tenant-id: tenant-1\r\nx-tenant-id: tenant-2\r\n"),
+            "{request}"
+        );
+        assert!(request.contains("x-signature: signed\r\n"), "{request}");
+        assert!(request.contains("authorization: bearer test-key\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_inlines_nested_schema() {
+        use super::super::testing::{TestResponse, TestServer};
+
+        #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+        struct Invoice {
+            number: String,
+            line_items: Vec<LineItem>,
+        }
+
+        #[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+        struct LineItem {
+            description: String,
+            quantity: u32,
+        }
+
+        let server = TestServer::serve(vec![TestResponse::json(serde_json::json!({
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "content": [{
+                    "type": "text",
+                    "text": r#"{"number": "INV-1", "line_items": [{"description": "Pen", "quantity": 2}]}"#
+                }]
+            },
+            "finish_reason": "COMPLETE"
+        }))])
+        .await;
+
+        let extractor = Client::from_url("test-key", &server.base_url)
+            .extractor::<Invoice>(super::super::COMMAND_R)
+            .build();
+        let invoice = extractor.extract("Invoice INV-1: 2 pens").await.unwrap();
+        assert_eq!(invoice.line_items[0].quantity, 2);
+
+        let request = &server.requests()[0];
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let schema = &body["response_format"]["json_schema"];
+        assert_eq!(
+            schema["properties"]["line_items"]["items"]["properties"]["quantity"]["type"],
+            "integer"
+        );
+        assert!(!schema.to_string().contains("$ref"), "{schema}");
+    }
+
+    #[test]
+    fn test_gzip_requests() {
+        use serde_json::json;
+        use std::io::Read;
+
+        let client = ClientBuilder::new("test-key").gzip_requests(true).build();
+        let texts = vec!["The quick brown fox jumps over the lazy dog"; 100];
+        let request = client
+            .gzip(client.post("/v1/embed").json(&json!({ "texts": texts })))
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers()["Content-Encoding"], "gzip");
+        assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+        let compressed = request.body().unwrap().as_bytes().unwrap();
+        let mut body = String::new();
+        flate2::read::GzDecoder::new(compressed)
+            .read_to_string(&mut body)
+            .unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({ "texts": texts })
+        );
+
+        // Small bodies are sent as is
+        let request = client
+            .gzip(
+                client
+                    .post("/v1/embed")
+                    .json(&json!({ "texts": ["hello"] })),
+            )
+            .build()
+            .unwrap();
+        assert!(request.headers().get("Content-Encoding").is_none());
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let request = ClientBuilder::new("test-key")
+            .build()
+            .post("/v2/chat")
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.headers()["User-Agent"],
+            format!("rig/{}", env!("CARGO_PKG_VERSION"))
+        );
+
+        let request = ClientBuilder::new("test-key")
+            .user_agent("my-app/1.2.3")
+            .http_client(reqwest::Client::new())
+            .build()
+            .post("/v2/chat")
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["User-Agent"], "my-app/1.2.3");
+    }
+
+    #[test]
+    fn test_feedback_request() {
+        assert_eq!(
+            feedback_request("gen_0", Rating::Good, None),
+            serde_json::json!({"request_id": "gen_0", "good_response": true})
+        );
+        assert_eq!(
+            feedback_request("gen_0", false.into(), Some("Wrong answer")),
+            serde_json::json!({
+                "request_id": "gen_0",
+                "good_response": false,
+                "comment": "Wrong answer"
+            })
+        );
+    }
+
+    #[test]
+    fn test_api_key_override() {
+        let client = ClientBuilder::new("default-key")
+            .header("X-Proxy-Authorization", "proxy-token")
+            .build();
+        let tenant_client = client.with_api_key("tenant-key");
+
+        let request = tenant_client.post("/v2/chat").build().unwrap();
+        assert_eq!(request.headers()["Authorization"], "Bearer tenant-key");
+        assert_eq!(request.headers()["X-Proxy-Authorization"], "proxy-token");
+
+        let request = client.post("/v2/chat").build().unwrap();
+        assert_eq!(request.headers()["Authorization"], "Bearer default-key");
+
+        let model = client
+            .completion_model(super::super::COMMAND_R)
+            .with_api_key("tenant-key");
+        let request = model.client.post("/v2/chat").build().unwrap();
+        assert_eq!(request.headers()["Authorization"], "Bearer tenant-key");
+    }
+
+    #[test]
+    fn test_deserialize_list_models_response() {
+        let json_data = r#"
+        {
+            "models": [
+                {
+                    "name": "command-r-plus",
+                    "endpoints": ["generate", "chat", "summarize"],
+                    "finetuned": false,
+                    "context_length": 128000,
+                    "tokenizer_url": "https://storage.googleapis.com/cohere-public/tokenizers/command-r-plus.json",
+                    "default_endpoints": ["chat"]
+                },
+                {
+                    "name": "embed-english-v3.0",
+                    "endpoints": ["embed", "classify"],
+                    "finetuned": false,
+                    "context_length": 512,
+                    "default_endpoints": ["embed"]
+                }
+            ],
+            "next_page_token": ""
+        }
+        "#;
+
+        let response: super::ListModelsResponse = serde_json::from_str(json_data).unwrap();
+
+        assert_eq!(response.models.len(), 2);
+        assert_eq!(response.models[0].context_length, Some(128000.0));
+        assert!(response.models[0].supports("chat"));
+        assert!(!response.models[1].supports("chat"));
+    }
+
+    #[test]
+    fn test_client_from_env_vars() {
+        let client = super::Client::from_env_vars(
+            Some(" test-key\n".to_string()),
+            Some("https://cohere.example.com ".to_string()),
+        )
+        .unwrap();
+        let request = client.post("/v2/chat").build().unwrap();
+
+        assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+        assert_eq!(request.url().host_str(), Some("cohere.example.com"));
+
+        let client = super::Client::from_env_vars(Some("test-key".to_string()), None).unwrap();
+        let request = client.post("/v2/chat").build().unwrap();
+        assert_eq!(request.url().host_str(), Some("api.cohere.ai"));
+
+        assert!(matches!(
+            super::Client::from_env_vars(Some("  ".to_string()), None),
+            Err(super::CohereError::MissingEnvVar("COHERE_API_KEY"))
+        ));
+    }
+
+    #[test]
+    fn test_connection_pool_settings() {
+        let builder = ClientBuilder::new("test-key")
+            .pool_max_idle_per_host(32)
+            .pool_idle_timeout(std::time::Duration::from_secs(30));
+        assert_eq!(builder.pool_max_idle_per_host, Some(32));
+        assert_eq!(
+            builder.pool_idle_timeout,
+            Some(std::time::Duration::from_secs(30))
+        );
+
+        let client = builder.build();
+        assert!(client.post("/v2/chat").build().is_ok());
+    }
+
+    #[test]
+    fn test_error_message_from_body() {
+        assert_eq!(
+            ApiErrorResponse::message_from_body(
+                r#"{"message": "invalid request: temperature must be between 0 and 1"}"#
+                    .to_string()
+            ),
+            "invalid request: temperature must be between 0 and 1"
+        );
+        assert_eq!(
+            ApiErrorResponse::message_from_body("Bad Gateway".to_string()),
+            "Bad Gateway"
+        );
+    }
+
+    #[test]
+    fn test_build_url() {
+        for (base_url, path, expected) in [
+            // Default base URL
+            (
+                "https://api.cohere.ai",
+                "/v2/chat",
+                "https://api.cohere.ai/v2/chat",
+            ),
+            // Trailing and leading slashes
+            (
+                "https://api.cohere.ai/",
+                "/v2/chat",
+                "https://api.cohere.ai/v2/chat",
+            ),
+            (
+                "https://api.cohere.ai//",
+                "//v2/chat",
+                "https://api.cohere.ai/v2/chat",
+            ),
+            (
+                "https://api.cohere.ai",
+                "v2/chat",
+                "https://api.cohere.ai/v2/chat",
+            ),
+            // Path prefixes
+            (
+                "https://gw.example.com/cohere",
+                "/v1/embed",
+                "https://gw.example.com/cohere/v1/embed",
+            ),
+            (
+                "https://gw.example.com/a/b/",
+                "v1/embed",
+                "https://gw.example.com/a/b/v1/embed",
+            ),
+            // Schemes and ports
+            (
+                "http://localhost:8080",
+                "/v1/models",
+                "http://localhost:8080/v1/models",
+            ),
+            // Slashes inside the path are preserved
+            (
+                "https://api.cohere.ai",
+                "/v1/models//x",
+                "https://api.cohere.ai/v1/models//x",
+            ),
+        ] {
+            assert_eq!(build_url(base_url, path), expected, "{base_url} + {path}");
+        }
+    }
+
+    #[test]
+    fn test_request_url() {
+        let url = |base_url: &str, path: &str| {
+            super::Client::from_url("test-key", base_url)
+                .post(path)
+                .build()
+                .unwrap()
+                .url()
+                .to_string()
+        };
+
+        assert_eq!(
+            url("https://api.cohere.ai", "/v2/chat"),
+            "https://api.cohere.ai/v2/chat"
+        );
+        assert_eq!(
+            url("https://api.cohere.ai/", "v2/chat"),
+            "https://api.cohere.ai/v2/chat"
+        );
+        assert_eq!(
+            url("https://gateway.example.com/cohere/", "/v2/chat"),
+            "https://gateway.example.com/cohere/v2/chat"
+        );
+        assert_eq!(
+            url("http://localhost:8080/proxy", "/v1/embed"),
+            "http://localhost:8080/proxy/v1/embed"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_paths() {
+        let client = super::Client::new("test-key");
+        assert_eq!(client.chat_path(), "/v2/chat");
+        assert_eq!(client.embed_path(), "/v1/embed");
+        assert_eq!(client.feedback_path(), "/v1/feedback/generate");
+
+        let client = super::ClientBuilder::new("test-key")
+            .base_url("https://gateway.example.com")
+            .chat_path("/cohere/chat")
+            .embed_path("/cohere/embed")
+            .feedback_path("/cohere/feedback")
+            .build();
+        let request = client.post(client.chat_path()).build().unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://gateway.example.com/cohere/chat"
+        );
+        assert_eq!(client.embed_path(), "/cohere/embed");
+        assert_eq!(client.feedback_path(), "/cohere/feedback");
+    }
+
+    #[tokio::test]
+    async fn test_submit_feedback_path() {
+        use super::super::testing::{TestResponse, TestServer};
+
+        let server = TestServer::serve(vec![TestResponse::new(200, "")]).await;
+
+        ClientBuilder::new("test-key")
+            .base_url(&server.base_url)
+            .feedback_path("/gateway/feedback")
+            .build()
+            .submit_feedback("gen_0", Rating::Good, None)
+            .await
+            .unwrap();
+        assert!(server.requests()[0].starts_with("POST /gateway/feedback HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_path_prefixed_base_url() {
+        let client = super::Client::from_url("test-key", "https://gw.internal/cohere");
+
+        for (path, expected) in [
+            (client.chat_path(), "https://gw.internal/cohere/v2/chat"),
+            (client.embed_path(), "https://gw.internal/cohere/v1/embed"),
+            (
+                client.feedback_path(),
+                "https://gw.internal/cohere/v1/feedback/generate",
+            ),
+            ("/v1/models", "https://gw.internal/cohere/v1/models"),
+        ] {
+            let request = client.post(path).build().unwrap();
+            assert_eq!(request.url().as_str(), expected);
+            assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+        }
     }
 }
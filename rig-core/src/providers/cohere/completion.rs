@@ -2,21 +2,53 @@ use std::collections::HashMap;
 
 use crate::{
     completion::{self, CompletionError},
-    json_utils, message, OneOrMany,
+    json_utils,
+    message::{self, MimeType},
+    OneOrMany,
 };
 
-use super::client::Client;
+use super::{
+    client::{ApiErrorResponse, Client},
+    usage,
+};
 use crate::completion::CompletionRequest;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-#[derive(Debug, Deserialize)]
+/// Response of the Cohere chat API.
+///
+/// Responses are deserialized leniently, so that additions to the Cohere API do not break
+/// existing code: unknown fields are kept in [CompletionResponse::extra], unknown finish reasons
+/// are mapped to [FinishReason::Other], and content, tool call, citation and source types
+/// unknown to this version of the crate are mapped to their `Other` variant (and ignored when
+/// converting the response).
+#[derive(Debug, Deserialize, Serialize)]
 pub struct CompletionResponse {
     pub id: String,
     pub finish_reason: FinishReason,
     message: Message,
     #[serde(default)]
     pub usage: Option<Usage>,
+    /// Log probabilities of the generated tokens, if requested with
+    /// [CompletionModel::with_logprobs]
+    #[serde(default)]
+    pub logprobs: Option<Vec<LogProbs>>,
+    /// Fields of the response unknown to this version of the crate
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+/// Log probabilities of a chunk of generated text.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LogProbs {
+    /// Token ids of the chunk
+    pub token_ids: Vec<u64>,
+    /// Text of the chunk
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Log probability of each token of the chunk
+    #[serde(default)]
+    pub logprobs: Vec<f64>,
 }
 
 impl CompletionResponse {
@@ -34,19 +66,284 @@ impl CompletionResponse {
 
         (content, citations, tool_calls)
     }
+
+    /// Units billed for the response (zero if the response has no usage).
+    pub fn billed_units(&self) -> usage::BilledUnits {
+        self.usage
+            .as_ref()
+            .map(Usage::billed_units)
+            .unwrap_or_default()
+    }
+
+    /// Id of the generation (i.e.: the `id` of the response), to log it or to reference the
+    /// response when reporting it to Cohere.
+    pub fn generation_id(&self) -> &str {
+        &self.id
+    }
+
+    /// Reasoning of the model before calling tools (e.g.: "I will search for flurbos."), if any.
+    pub fn tool_plan(&self) -> Option<&str> {
+        match &self.message {
+            Message::Assistant { tool_plan, .. } => tool_plan.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Tool calls of the response.
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        match &self.message {
+            Message::Assistant { tool_calls, .. } => tool_calls,
+            _ => &[],
+        }
+    }
+
+    /// Whether the model needs to search before answering, i.e.: whether it calls the search
+    /// tool `tool_name`.
+    ///
+    /// Unlike the v1 chat API, the v2 chat API has no dedicated `is_search_required` and
+    /// `search_queries` fields: to let the model generate search queries, give it a search tool
+    /// and read the queries with [CompletionResponse::search_queries].
+    pub fn is_search_required(&self, tool_name: &str) -> bool {
+        self.tool_calls().iter().any(|tool_call| {
+            tool_call
+                .function
+                .as_ref()
+                .is_some_and(|function| function.name == tool_name)
+        })
+    }
+
+    /// Search queries generated by the model, i.e.: the `query` argument of its calls to the
+    /// tool `tool_name`, in order. The queries belong to the generation
+    /// [CompletionResponse::generation_id].
+    pub fn search_queries(&self, tool_name: &str) -> Vec<String> {
+        self.tool_calls()
+            .iter()
+            .filter_map(|tool_call| tool_call.function.as_ref())
+            .filter(|function| function.name == tool_name)
+            .filter_map(|function| function.arguments.get("query")?.as_str())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Citations grounding the response text in the documents or tool results used to generate it.
+    pub fn citations(&self) -> &[Citation] {
+        match &self.message {
+            Message::Assistant { citations, .. } => citations,
+            _ => &[],
+        }
+    }
+
+    /// Documents cited by the response as `(id, document)` pairs, in order of first citation.
+    /// Each document is returned once, even when cited by several citations.
+    pub fn cited_documents(&self) -> Vec<(&str, &serde_json::Map<String, Value>)> {
+        let mut documents: Vec<(&str, &serde_json::Map<String, Value>)> = vec![];
+
+        self.citations()
+            .iter()
+            .flat_map(|citation| citation.sources.iter())
+            .for_each(|source| {
+                if let Source::Document {
+                    id: Some(id),
+                    document: Some(document),
+                } = source
+                {
+                    if !documents.iter().any(|(cited_id, _)| cited_id == id) {
+                        documents.push((id, document));
+                    }
+                }
+            });
+
+        documents
+    }
+
+    /// Citation density of each document cited by the response, as `(id, density)` pairs in
+    /// order of first citation. The density of a document is the share of the response text
+    /// (between 0.0 and 1.0) covered by the citations grounded in it.
+    ///
+    /// The Cohere API returns no relevance score for the cited documents, so the density serves
+    /// as a proxy: a document grounding most of the answer is more relevant than a document
+    /// grounding a single word. Citations without a span (`start` and `end`) are not counted.
+    pub fn citation_density(&self) -> Vec<(&str, f64)> {
+        let mut spans: Vec<(&str, Vec<(u32, u32)>)> = vec![];
+        for citation in self.citations() {
+            for source in &citation.sources {
+                let Source::Document { id: Some(id), .. } = source else {
+                    continue;
+                };
+                let position = match spans.iter().position(|(cited_id, _)| cited_id == id) {
+                    Some(position) => position,
+                    None => {
+                        spans.push((id, vec![]));
+                        spans.len() - 1
+                    }
+                };
+                if let (Some(start), Some(end)) = (citation.start, citation.end) {
+                    spans[position].1.push((start, end.max(start)));
+                }
+            }
+        }
+
+        let content = match &self.message {
+            Message::Assistant { content, .. } => &content[..],
+            _ => &[],
+        };
+        let text_len = content
+            .iter()
+            .map(|content| match content {
+                AssistantContent::Text { text } => text.chars().count(),
+                AssistantContent::Other => 0,
+            })
+            .sum::<usize>();
+
+        spans
+            .into_iter()
+            .map(|(id, mut spans)| {
+                // Merge the overlapping spans, so that a character cited twice counts once
+                spans.sort_unstable();
+                let mut covered = 0;
+                let mut covered_until = 0;
+                for (start, end) in spans {
+                    let start = start.max(covered_until);
+                    if end > start {
+                        covered += end - start;
+                        covered_until = end;
+                    }
+                }
+
+                let density = match text_len {
+                    0 => 0.0,
+                    text_len => (covered as f64 / text_len as f64).min(1.0),
+                };
+                (id, density)
+            })
+            .collect()
+    }
+
+    /// Citations grounded in at least one document with a
+    /// [citation density](CompletionResponse::citation_density) of at least `threshold`, in
+    /// order, e.g.: to only show the main sources of an answer. Citations grounded only in tool
+    /// results are dropped.
+    pub fn citations_above_density(&self, threshold: f64) -> Vec<&Citation> {
+        let density = self.citation_density();
+
+        self.citations()
+            .iter()
+            .filter(|citation| {
+                citation.sources.iter().any(|source| match source {
+                    Source::Document { id: Some(id), .. } => density
+                        .iter()
+                        .any(|(cited_id, density)| cited_id == id && *density >= threshold),
+                    _ => false,
+                })
+            })
+            .collect()
+    }
+
+    /// Map the citations of the response back to the `documents` sent with the request, as
+    /// `(citation, document)` pairs in order of citation. The citation carries the cited span of
+    /// the response text (`start`, `end` and `text`), and a citation grounded in several
+    /// documents produces one pair per document. Sources that are not one of `documents` (e.g.:
+    /// tool results) are skipped.
+    ///
+    /// This makes it easy to render footnotes with the properties of the original documents
+    /// (e.g.: a URL or title stored in [completion::Document::additional_props]).
+    pub fn cited_spans<'a>(
+        &self,
+        documents: &'a [completion::Document],
+    ) -> Vec<(&Citation, &'a completion::Document)> {
+        self.citations()
+            .iter()
+            .flat_map(|citation| {
+                citation
+                    .sources
+                    .iter()
+                    .filter_map(move |source| match source {
+                        Source::Document { id: Some(id), .. } => documents
+                            .iter()
+                            .find(|document| &document.id == id)
+                            .map(|document| (citation, document)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+impl completion::CompletionResponse<CompletionResponse> {
+    /// Id of the Cohere generation of the response (see [CompletionResponse::generation_id]).
+    pub fn generation_id(&self) -> &str {
+        self.raw_response.generation_id()
+    }
+}
+
+/// Reason why the model stopped generating.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FinishReason {
+    /// The response was truncated after reaching the maximum number of tokens
     MaxTokens,
+    /// The model generated one of the stop sequences
     StopSequence,
+    /// The model finished its response
     Complete,
+    /// The generation failed
     Error,
+    /// The model called a tool
     ToolCall,
+    /// Any other finish reason returned by the Cohere API
+    Other(String),
+}
+
+impl FinishReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::MaxTokens => "MAX_TOKENS",
+            FinishReason::StopSequence => "STOP_SEQUENCE",
+            FinishReason::Complete => "COMPLETE",
+            FinishReason::Error => "ERROR",
+            FinishReason::ToolCall => "TOOL_CALL",
+            FinishReason::Other(reason) => reason,
+        }
+    }
+}
+
+impl std::fmt::Display for FinishReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "MAX_TOKENS" => FinishReason::MaxTokens,
+            "STOP_SEQUENCE" => FinishReason::StopSequence,
+            "COMPLETE" => FinishReason::Complete,
+            "ERROR" => FinishReason::Error,
+            "TOOL_CALL" => FinishReason::ToolCall,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+impl Serialize for FinishReason {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl<'de> Deserialize<'de> for FinishReason {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(String::deserialize(deserializer)?.as_str().into())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Usage {
     #[serde(default)]
     pub billed_units: Option<BilledUnits>,
@@ -54,7 +351,7 @@ pub struct Usage {
     pub tokens: Option<Tokens>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BilledUnits {
     #[serde(default)]
     pub output_tokens: Option<f64>,
@@ -66,7 +363,25 @@ pub struct BilledUnits {
     pub input_tokens: Option<f64>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl Usage {
+    /// Billed units of the response, in the shape shared by all the Cohere endpoints (see
+    /// [usage::BilledUnits]), missing units being zero.
+    pub fn billed_units(&self) -> usage::BilledUnits {
+        let Some(billed_units) = &self.billed_units else {
+            return usage::BilledUnits::default();
+        };
+        let units = |units: Option<f64>| units.map_or(0, |units| units.round() as u32);
+
+        usage::BilledUnits {
+            input_tokens: units(billed_units.input_tokens),
+            output_tokens: units(billed_units.output_tokens),
+            search_units: units(billed_units.search_units),
+            classifications: units(billed_units.classifications),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Tokens {
     #[serde(default)]
     pub input_tokens: Option<f64>,
@@ -81,42 +396,119 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
         let (content, _, tool_calls) = response.message();
 
         let model_response = if !tool_calls.is_empty() {
-            OneOrMany::many(
-                tool_calls
-                    .into_iter()
-                    .filter_map(|tool_call| {
-                        let ToolCallFunction { name, arguments } = tool_call.function?;
-                        let id = tool_call.id.unwrap_or_else(|| name.clone());
+            let count = tool_calls.len();
+            let mut tool_calls = tool_calls
+                .into_iter()
+                .filter_map(|tool_call| {
+                    let ToolCallFunction { name, arguments } = tool_call.function?;
+                    let id = tool_call.id.unwrap_or_else(|| name.clone());
 
-                        Some(completion::AssistantContent::tool_call(id, name, arguments))
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .expect("We have atleast 1 tool call in this if block")
+                    Some(completion::AssistantContent::tool_call(id, name, arguments))
+                })
+                .collect::<Vec<_>>();
+
+            if tool_calls.len() < count {
+                tracing::warn!(
+                    target: "rig",
+                    "Dropped {} of the {count} tool calls of the response without a function",
+                    count - tool_calls.len()
+                );
+            }
+
+            if tool_calls.is_empty() {
+                return Err(CompletionError::ResponseError(
+                    "Response contained no valid tool call".to_owned(),
+                ));
+            }
+
+            // The tool plan (i.e.: the reasoning of the model before calling the tools) comes
+            // first, so that it is kept in the chat history and sent back as is (see the
+            // conversion of assistant messages)
+            if let Some(tool_plan) = response.tool_plan().filter(|plan| !plan.is_empty()) {
+                tool_calls.insert(0, completion::AssistantContent::text(tool_plan));
+            }
+
+            OneOrMany::many(tool_calls).expect("There is at least one tool call")
         } else {
-            OneOrMany::many(content.into_iter().map(|content| match content {
-                AssistantContent::Text { text } => completion::AssistantContent::text(text),
+            // Empty texts are dropped, so that a response without any text (e.g.: blocked by a
+            // content filter) is reported as such instead of as an empty message
+            OneOrMany::many(content.into_iter().filter_map(|content| match content {
+                AssistantContent::Text { text } if !text.is_empty() => {
+                    Some(completion::AssistantContent::text(text))
+                }
+                _ => None,
             }))
             .map_err(|_| {
-                CompletionError::ResponseError(
-                    "Response contained no message or tool call (empty)".to_owned(),
-                )
+                CompletionError::EmptyResponse(format!(
+                    "Response has no text or tool call (finish reason: {})",
+                    response.finish_reason.as_str()
+                ))
             })?
         };
 
         Ok(completion::CompletionResponse {
-            choice: OneOrMany::many(model_response).expect("There is atleast one content"),
+            choice: model_response,
             raw_response: response,
         })
     }
 }
 
+/// Document used by the model to ground its response, in the shape expected by the Cohere chat
+/// API: an id and arbitrary fields (e.g.: `title`, `snippet`, `url`). Citations of the response
+/// reference the document by id and return its fields.
+///
+/// [completion::Document]s are converted to this shape, their `text` and `additional_props`
+/// becoming the fields of the document.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Document {
     pub id: String,
     pub data: HashMap<String, serde_json::Value>,
 }
 
+impl Document {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            data: HashMap::new(),
+        }
+    }
+
+    /// Set a field of the document (e.g.: `title`, `snippet`, `url`).
+    pub fn with_field(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.data.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// Convert the document into a generic document (e.g.: to add it to an agent's context).
+/// The `text` field becomes the text of the document, and the other fields its
+/// `additional_props` (non-string values are serialized to JSON).
+impl From<Document> for completion::Document {
+    fn from(document: Document) -> Self {
+        let mut text = String::new();
+        let mut additional_props = HashMap::new();
+
+        document.data.into_iter().for_each(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+
+            if key == "text" {
+                text = value;
+            } else {
+                additional_props.insert(key, value);
+            }
+        });
+
+        Self {
+            id: document.id,
+            text,
+            additional_props,
+        }
+    }
+}
+
 impl From<completion::Document> for Document {
     fn from(document: completion::Document) -> Self {
         let mut data: HashMap<String, serde_json::Value> = HashMap::new();
@@ -130,7 +522,9 @@ impl From<completion::Document> for Document {
                 data.insert(key, value.into());
             });
 
-        data.insert("text".to_string(), document.text.into());
+        if !document.text.is_empty() {
+            data.insert("text".to_string(), document.text.into());
+        }
 
         Self {
             id: document.id,
@@ -161,6 +555,9 @@ pub struct ToolCallFunction {
 pub enum ToolType {
     #[default]
     Function,
+    /// Any other tool type returned by the Cohere API
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -177,16 +574,37 @@ pub struct Function {
     pub parameters: serde_json::Value,
 }
 
-impl From<completion::ToolDefinition> for Tool {
-    fn from(tool: completion::ToolDefinition) -> Self {
-        Self {
+impl TryFrom<completion::ToolDefinition> for Tool {
+    type Error = CompletionError;
+
+    fn try_from(tool: completion::ToolDefinition) -> Result<Self, Self::Error> {
+        let parameters = match tool.parameters {
+            // Tools without parameters are sent with an empty object schema
+            Value::Null => json!({ "type": "object", "properties": {} }),
+            Value::Object(mut parameters) => {
+                // Parameterless tools may omit `properties` altogether (e.g.: `{"type": "object"}`)
+                parameters.entry("properties").or_insert_with(|| json!({}));
+                Value::Object(parameters)
+            }
+            parameters => {
+                return Err(CompletionError::RequestError(
+                    format!(
+                        "Tool `{}` parameters must be a JSON schema object, got: {}",
+                        tool.name, parameters
+                    )
+                    .into(),
+                ))
+            }
+        };
+
+        Ok(Self {
             r#type: ToolType::default(),
             function: Function {
                 name: tool.name,
-                description: Some(tool.description),
-                parameters: tool.parameters,
+                description: Some(tool.description).filter(|description| !description.is_empty()),
+                parameters,
             },
-        }
+        })
     }
 }
 
@@ -198,13 +616,13 @@ pub enum Message {
     },
 
     Assistant {
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         content: Vec<AssistantContent>,
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         citations: Vec<Citation>,
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         tool_calls: Vec<ToolCall>,
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         tool_plan: Option<String>,
     },
 
@@ -221,14 +639,24 @@ pub enum Message {
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum UserContent {
-    Text { text: String },
-    ImageUrl { image_url: ImageUrl },
+    Text {
+        text: String,
+    },
+    #[serde(rename = "image_url")]
+    ImageUrl {
+        image_url: ImageUrl,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum AssistantContent {
-    Text { text: String },
+    Text {
+        text: String,
+    },
+    /// Any other content type returned by the Cohere API
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
@@ -236,7 +664,44 @@ pub struct ImageUrl {
     pub url: String,
 }
 
+impl From<message::Image> for ImageUrl {
+    fn from(image: message::Image) -> Self {
+        match (image.format, image.media_type) {
+            // Base64 encoded images are sent as data URIs
+            (Some(message::ContentFormat::Base64), Some(media_type)) => Self {
+                url: format!("data:{};base64,{}", media_type.to_mime_type(), image.data),
+            },
+            _ => Self { url: image.data },
+        }
+    }
+}
+
+impl From<ImageUrl> for message::Image {
+    fn from(image_url: ImageUrl) -> Self {
+        let data_uri = image_url
+            .url
+            .strip_prefix("data:")
+            .and_then(|data_uri| data_uri.split_once(";base64,"));
+
+        match data_uri {
+            Some((mime_type, data)) => message::Image {
+                data: data.to_string(),
+                format: Some(message::ContentFormat::Base64),
+                media_type: message::ImageMediaType::from_mime_type(mime_type),
+                detail: None,
+            },
+            None => message::Image {
+                data: image_url.url,
+                format: Some(message::ContentFormat::String),
+                media_type: None,
+                detail: None,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum ToolResultContent {
     Text { text: String },
     Document { document: Document },
@@ -267,6 +732,9 @@ pub enum Source {
         id: Option<String>,
         tool_output: Option<serde_json::Map<String, serde_json::Value>>,
     },
+    /// Any other source type returned by the Cohere API
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -274,6 +742,9 @@ pub enum Source {
 pub enum CitationType {
     TextContent,
     Plan,
+    /// Any other citation type returned by the Cohere API
+    #[serde(other)]
+    Other,
 }
 
 impl TryFrom<message::Message> for Vec<Message> {
@@ -281,31 +752,55 @@ impl TryFrom<message::Message> for Vec<Message> {
 
     fn try_from(message: message::Message) -> Result<Self, Self::Error> {
         Ok(match message {
-            message::Message::User { content } => content
-                .into_iter()
-                .map(|content| match content {
-                    message::UserContent::Text(message::Text { text }) => Ok(Message::User {
-                        content: OneOrMany::one(UserContent::Text { text }),
-                    }),
-                    message::UserContent::ToolResult(message::ToolResult { id, content }) => {
-                        Ok(Message::Tool {
-                            tool_call_id: id,
-                            content: content.try_map(|content| match content {
-                                message::ToolResultContent::Text(text) => {
-                                    Ok(ToolResultContent::Text { text: text.text })
-                                }
-                                _ => Err(message::MessageError::ConversionError(
-                                    "Only text tool result content is supported by Cohere"
-                                        .to_owned(),
-                                )),
-                            })?,
-                        })
+            message::Message::User { content } => {
+                let mut messages = vec![];
+                let mut user_content = vec![];
+
+                for content in content.into_iter() {
+                    match content {
+                        message::UserContent::Text(message::Text { text }) => {
+                            user_content.push(UserContent::Text { text });
+                        }
+                        message::UserContent::Image(image) => {
+                            user_content.push(UserContent::ImageUrl {
+                                image_url: image.into(),
+                            });
+                        }
+                        message::UserContent::ToolResult(message::ToolResult { id, content }) => {
+                            // Tool results are sent as separate messages, so flush the user content
+                            // preceding them to keep the conversation in order
+                            if let Ok(content) = OneOrMany::many(std::mem::take(&mut user_content))
+                            {
+                                messages.push(Message::User { content });
+                            }
+
+                            messages.push(Message::Tool {
+                                tool_call_id: id,
+                                content: content.try_map(|content| match content {
+                                    message::ToolResultContent::Text(text) => {
+                                        Ok(ToolResultContent::Text { text: text.text })
+                                    }
+                                    _ => Err(message::MessageError::ConversionError(
+                                        "Only text tool result content is supported by Cohere"
+                                            .to_owned(),
+                                    )),
+                                })?,
+                            });
+                        }
+                        _ => {
+                            return Err(message::MessageError::ConversionError(
+                                "Only text and image content is supported by Cohere".to_owned(),
+                            ))
+                        }
                     }
-                    _ => Err(message::MessageError::ConversionError(
-                        "Only text content is supported by Cohere".to_owned(),
-                    )),
-                })
-                .collect::<Result<Vec<_>, _>>()?,
+                }
+
+                if let Ok(content) = OneOrMany::many(user_content) {
+                    messages.push(Message::User { content });
+                }
+
+                messages
+            }
             message::Message::Assistant { content } => {
                 let mut text_content = vec![];
                 let mut tool_calls = vec![];
@@ -328,12 +823,32 @@ impl TryFrom<message::Message> for Vec<Message> {
                     }
                 });
 
-                vec![Message::Assistant {
-                    content: text_content,
-                    citations: vec![],
-                    tool_calls,
-                    tool_plan: None,
-                }]
+                // The Cohere API expects the text of an assistant message with tool calls as its
+                // tool plan
+                if tool_calls.is_empty() {
+                    vec![Message::Assistant {
+                        content: text_content,
+                        citations: vec![],
+                        tool_calls,
+                        tool_plan: None,
+                    }]
+                } else {
+                    let tool_plan = text_content
+                        .into_iter()
+                        .filter_map(|content| match content {
+                            AssistantContent::Text { text } => Some(text),
+                            AssistantContent::Other => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    vec![Message::Assistant {
+                        content: vec![],
+                        citations: vec![],
+                        tool_calls,
+                        tool_plan: (!tool_plan.is_empty()).then_some(tool_plan),
+                    }]
+                }
             }
         })
     }
@@ -349,24 +864,27 @@ impl TryFrom<Message> for message::Message {
                     UserContent::Text { text } => {
                         message::UserContent::Text(message::Text { text })
                     }
-                    UserContent::ImageUrl { image_url } => message::UserContent::image(
-                        image_url.url,
-                        Some(message::ContentFormat::String),
-                        None,
-                        None,
-                    ),
+                    UserContent::ImageUrl { image_url } => {
+                        message::UserContent::Image(image_url.into())
+                    }
                 }),
             }),
             Message::Assistant {
                 content,
                 tool_calls,
+                tool_plan,
                 ..
             } => {
-                let mut content = content
+                let mut content = tool_plan
+                    .filter(|tool_plan| !tool_plan.is_empty())
+                    .map(message::AssistantContent::text)
                     .into_iter()
-                    .map(|content| match content {
-                        AssistantContent::Text { text } => message::AssistantContent::text(text),
-                    })
+                    .chain(content.into_iter().filter_map(|content| match content {
+                        AssistantContent::Text { text } => {
+                            Some(message::AssistantContent::text(text))
+                        }
+                        AssistantContent::Other => None,
+                    }))
                     .collect::<Vec<_>>();
 
                 content.extend(tool_calls.into_iter().filter_map(|tool_call| {
@@ -413,15 +931,196 @@ impl TryFrom<Message> for message::Message {
                     )),
                 })
             }
-            Message::System { content } => Ok(message::Message::user(content)),
+            Message::System { .. } => Err(message::MessageError::ConversionError(
+                "A Cohere system message has no message equivalent, use restore_chat_history to \
+                 restore it as the preamble"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+/// Convert a stored Cohere chat history (e.g.: the `messages` of a chat request) back to a
+/// preamble and a chat history, to resume the conversation with a
+/// [CompletionRequest](completion::CompletionRequest) or an agent.
+/// The `system` messages make up the preamble (joined with blank lines if there are several),
+/// and the other messages the chat history, `chatbot`/`assistant` messages becoming
+/// assistant messages.
+pub fn restore_chat_history(
+    messages: Vec<Message>,
+) -> Result<(Option<String>, Vec<message::Message>), message::MessageError> {
+    let mut preamble: Option<String> = None;
+    let mut chat_history = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        match message {
+            Message::System { content } => match &mut preamble {
+                Some(preamble) => {
+                    preamble.push_str("\n\n");
+                    preamble.push_str(&content);
+                }
+                None => preamble = Some(content),
+            },
+            message => chat_history.push(message.try_into()?),
         }
     }
+
+    Ok((preamble, chat_history))
 }
 
 #[derive(Clone)]
 pub struct CompletionModel {
     pub(crate) client: Client,
     pub model: String,
+    temperature: Option<f64>,
+    max_tokens: Option<u32>,
+    p: Option<f64>,
+    k: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+    logprobs: bool,
+    raw_prompting: bool,
+    safety_mode: Option<SafetyMode>,
+    validate_tool_calls: bool,
+    array_merge: json_utils::ArrayMerge,
+    document_limits: Option<DocumentLimits>,
+    idempotency_keys: bool,
+    batch_concurrency: usize,
+}
+
+/// What to do with the documents of a request exceeding its [DocumentLimits].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DocumentOverflow {
+    /// Fail the request with a [CompletionError::RequestError] naming the exceeded limit
+    #[default]
+    Error,
+    /// Drop the lowest-ranked documents (i.e.: the last ones, as documents are expected in
+    /// decreasing order of relevance) and truncate the oversized ones, logging a warning
+    Fit,
+}
+
+/// Limits on the RAG documents sent with a chat request, checked before sending it.
+///
+/// # Example
+/// ```
+/// use rig::providers::cohere::{self, DocumentLimits, DocumentOverflow};
+///
+/// let model = cohere::Client::new("YOUR_API_KEY")
+///     .completion_model(cohere::COMMAND_R)
+///     .with_document_limits(
+///         DocumentLimits::new(20)
+///             .max_document_tokens(1_000)
+///             .overflow(DocumentOverflow::Fit),
+///     );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DocumentLimits {
+    /// Maximum number of documents per request
+    pub max_documents: usize,
+    /// Maximum size of each document, in tokens (as estimated by
+    /// [estimate_tokens](crate::tokens::estimate_tokens))
+    pub max_document_tokens: Option<usize>,
+    pub overflow: DocumentOverflow,
+}
+
+impl DocumentLimits {
+    pub fn new(max_documents: usize) -> Self {
+        Self {
+            max_documents,
+            max_document_tokens: None,
+            overflow: DocumentOverflow::Error,
+        }
+    }
+
+    /// Set the maximum size of each document, in tokens.
+    pub fn max_document_tokens(mut self, max_document_tokens: usize) -> Self {
+        self.max_document_tokens = Some(max_document_tokens);
+        self
+    }
+
+    /// Set what to do with the documents exceeding the limits.
+    pub fn overflow(mut self, overflow: DocumentOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Check `documents` against the limits, dropping or truncating them with
+    /// [DocumentOverflow::Fit].
+    fn apply(
+        &self,
+        mut documents: Vec<completion::Document>,
+    ) -> Result<Vec<completion::Document>, CompletionError> {
+        if documents.len() > self.max_documents {
+            match self.overflow {
+                DocumentOverflow::Error => {
+                    return Err(CompletionError::RequestError(
+                        format!(
+                            "The request has {} documents, more than the limit of {} documents",
+                            documents.len(),
+                            self.max_documents
+                        )
+                        .into(),
+                    ))
+                }
+                DocumentOverflow::Fit => {
+                    tracing::warn!(target: "rig",
+                        "Dropping the {} lowest-ranked documents, over the limit of {} documents",
+                        documents.len() - self.max_documents,
+                        self.max_documents,
+                    );
+                    documents.truncate(self.max_documents);
+                }
+            }
+        }
+
+        let Some(max_tokens) = self.max_document_tokens else {
+            return Ok(documents);
+        };
+        for document in documents.iter_mut() {
+            let tokens = crate::tokens::estimate_tokens(&document.text);
+            if tokens <= max_tokens {
+                continue;
+            }
+
+            match self.overflow {
+                DocumentOverflow::Error => {
+                    return Err(CompletionError::RequestError(
+                        format!(
+                            "Document {} is about {tokens} tokens, more than the limit of {max_tokens} tokens per document",
+                            document.id
+                        )
+                        .into(),
+                    ))
+                }
+                DocumentOverflow::Fit => {
+                    tracing::warn!(target: "rig",
+                        "Truncating document {} from about {} to {} tokens",
+                        document.id,
+                        tokens,
+                        max_tokens,
+                    );
+                    let mut end = max_tokens * 4;
+                    while !document.text.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    document.text.truncate(end);
+                }
+            }
+        }
+
+        Ok(documents)
+    }
+}
+
+/// Safety instructions applied by the model to its responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SafetyMode {
+    /// Safety instructions suited to wide-ranging interactions, with fewer constraints on output
+    Contextual,
+    /// Strict guardrails avoiding all sensitive topics
+    Strict,
+    /// No safety instructions
+    None,
 }
 
 impl CompletionModel {
@@ -429,21 +1128,207 @@ impl CompletionModel {
         Self {
             client,
             model: model.to_string(),
+            temperature: None,
+            max_tokens: None,
+            p: None,
+            k: None,
+            stop_sequences: None,
+            logprobs: false,
+            raw_prompting: false,
+            safety_mode: None,
+            validate_tool_calls: false,
+            array_merge: json_utils::ArrayMerge::Replace,
+            document_limits: None,
+            idempotency_keys: false,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+        }
+    }
+
+    /// Set the default temperature of the model.
+    /// The temperature of a completion request takes precedence over this value.
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the default maximum number of tokens generated by the model.
+    /// The `max_tokens` of a completion request takes precedence over this value.
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set the nucleus sampling (top-p) probability mass, between 0.01 and 0.99.
+    /// The `top_p` of a completion request takes precedence over this value.
+    pub fn with_p(mut self, p: f64) -> Self {
+        self.p = Some(p);
+        self
+    }
+
+    /// Set the number of most likely tokens considered at each generation step (top-k), between 0 and 500.
+    /// The `top_k` of a completion request takes precedence over this value.
+    pub fn with_k(mut self, k: u32) -> Self {
+        self.k = Some(k);
+        self
+    }
+
+    /// Set the sequences that stop the generation when produced by the model.
+    /// The `stop_sequences` of a completion request take precedence over this value.
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = Some(stop_sequences);
+        self
+    }
+
+    /// Request the log probabilities of the generated tokens, returned in
+    /// [CompletionResponse::logprobs].
+    pub fn with_logprobs(mut self, logprobs: bool) -> Self {
+        self.logprobs = logprobs;
+        self
+    }
+
+    /// Authenticate the requests of this model with `api_key` instead of the client's API key,
+    /// sharing the client's connection pool (see [Client::with_api_key]).
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.client = self.client.with_api_key(api_key);
+        self
+    }
+
+    /// Send the messages and preamble to the model verbatim, without Cohere's default prompt
+    /// template (e.g.: to reproduce the exact prompts of a paper). Disabled by default.
+    pub fn with_raw_prompting(mut self, raw_prompting: bool) -> Self {
+        self.raw_prompting = raw_prompting;
+        self
+    }
+
+    /// Set the safety mode of the model. If not set, the default safety mode of the
+    /// Cohere account is used.
+    pub fn with_safety_mode(mut self, safety_mode: SafetyMode) -> Self {
+        self.safety_mode = Some(safety_mode);
+        self
+    }
+
+    /// Set how the arrays of the `additional_params` of a completion request are merged into the
+    /// request body. By default ([ArrayMerge::Replace](super::ArrayMerge::Replace)) an array replaces the array
+    /// of the request under the same key, e.g.: a `tools` array replaces the tools of the agent.
+    /// With [ArrayMerge::Append](super::ArrayMerge::Append), its items are appended instead, e.g.: to add tools
+    /// defined as raw JSON to those of the agent.
+    pub fn with_array_merge(mut self, array_merge: json_utils::ArrayMerge) -> Self {
+        self.array_merge = array_merge;
+        self
+    }
+
+    /// Check the RAG documents of each request against `limits` before sending it, instead of
+    /// letting the Cohere API reject requests with too many or too large documents.
+    pub fn with_document_limits(mut self, limits: DocumentLimits) -> Self {
+        self.document_limits = Some(limits);
+        self
+    }
+
+    /// Validate the arguments of the tool calls returned by the model against the definitions of
+    /// the tools of the request. When enabled, a tool call to an unknown tool or with arguments
+    /// that do not match the tool's parameters fails the completion with
+    /// [CompletionError::InvalidToolCall]. Disabled by default.
+    pub fn with_tool_call_validation(mut self, validate: bool) -> Self {
+        self.validate_tool_calls = validate;
+        self
+    }
+
+    /// Send an idempotency key (in the [IDEMPOTENCY_KEY_HEADER] header) with each chat request.
+    /// A new key is generated for each completion and sent again with the retries of its
+    /// request, so that a proxy or gateway in front of the API can deduplicate them. Disabled
+    /// by default.
+    pub fn with_idempotency_keys(mut self, idempotency_keys: bool) -> Self {
+        self.idempotency_keys = idempotency_keys;
+        self
+    }
+
+    /// Set the maximum number of requests of a [completion_batch](Self::completion_batch) (or of
+    /// the candidates of [completions](Self::completions)) sent concurrently (at least 1). Defaults to [DEFAULT_BATCH_CONCURRENCY].
+    pub fn with_batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency.max(1);
+        self
+    }
+
+    /// Request to the chat endpoint with the body `request`, with a new idempotency key (if
+    /// enabled) shared by the retries of the request.
+    pub(crate) fn chat_request(&self, request: &Value) -> reqwest::RequestBuilder {
+        let builder = self.client.post(self.client.chat_path()).json(request);
+
+        if self.idempotency_keys {
+            builder.header(IDEMPOTENCY_KEY_HEADER, new_idempotency_key())
+        } else {
+            builder
         }
     }
 
+    /// Generate `num_generations` candidate completions of `completion_request`, e.g.: to rerank
+    /// them in a best-of-n flow. [completion](completion::CompletionModel::completion) still
+    /// returns a single candidate.
+    ///
+    /// The Cohere v2 chat API has no `num_generations` parameter, so the candidates are generated
+    /// by as many requests, each billed separately, sent as a
+    /// [completion_batch](Self::completion_batch) (i.e.: with at most
+    /// [with_batch_concurrency](Self::with_batch_concurrency) requests in flight at once). When
+    /// the request has a seed, the seed of the i-th candidate is `seed + i`, so that the
+    /// candidates differ.
+    pub async fn completions(
+        &self,
+        completion_request: CompletionRequest,
+        num_generations: usize,
+    ) -> Result<Vec<completion::CompletionResponse<CompletionResponse>>, CompletionError> {
+        self.completion_batch(candidate_requests(&completion_request, num_generations))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Complete each of `requests` (e.g.: the prompts of an offline evaluation), returning the
+    /// results in the order of the requests.
+    ///
+    /// At most [with_batch_concurrency](Self::with_batch_concurrency) requests are in flight at
+    /// once, sharing the connection pool of the client, and each of them is retried according to
+    /// the [RetryPolicy](super::RetryPolicy) of the client. A failed request does not stop the
+    /// batch: its error is returned in place of its response.
+    pub async fn completion_batch(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Vec<Result<completion::CompletionResponse<CompletionResponse>, CompletionError>> {
+        use completion::CompletionModel as _;
+        use futures::StreamExt;
+
+        futures::stream::iter(requests)
+            .map(|request| self.completion(request))
+            .buffered(self.batch_concurrency)
+            .collect()
+            .await
+    }
+
+    /// Build the JSON body that [completion](completion::CompletionModel::completion) would send
+    /// to the Cohere chat API for the given request, without sending it
+    /// (e.g.: to debug an agent, or to snapshot the request in tests).
+    pub fn build_request(
+        &self,
+        completion_request: &CompletionRequest,
+    ) -> Result<Value, CompletionError> {
+        self.create_completion_request(completion_request.clone())
+    }
+
     pub(crate) fn create_completion_request(
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
-        // Build up the order of messages (context, chat_history)
-        let mut partial_history = vec![];
-        if let Some(docs) = completion_request.normalized_documents() {
-            partial_history.push(docs);
-        }
-        partial_history.extend(completion_request.chat_history);
+        // Documents are sent in the dedicated `documents` field of the request (instead of being
+        // added to the chat history) so that the model can cite them.
+        let partial_history = completion_request.chat_history;
+        let mut additional_params = completion_request.additional_params;
+        let raw_history = take_raw_chat_history(&mut additional_params)?;
+        let documents = match &self.document_limits {
+            Some(limits) => limits.apply(completion_request.documents)?,
+            None => completion_request.documents,
+        };
 
         // Initialize full history with preamble (or empty if non-existent)
+        let has_preamble = completion_request.preamble.is_some();
         let mut full_history: Vec<Message> = completion_request
             .preamble
             .map_or_else(Vec::new, |preamble| {
@@ -461,51 +1346,340 @@ impl CompletionModel {
                 .collect::<Vec<_>>(),
         );
 
-        let request = json!({
+        let mut request = json!({
             "model": self.model,
             "messages": full_history,
-            "documents": completion_request.documents,
-            "temperature": completion_request.temperature,
-            "tools": completion_request.tools.into_iter().map(Tool::from).collect::<Vec<_>>(),
+            "documents": documents
+                .into_iter()
+                .map(Document::from)
+                .collect::<Vec<_>>(),
+            "temperature": completion_request.temperature.or(self.temperature),
+            "tools": completion_request
+                .tools
+                .into_iter()
+                .map(Tool::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
         });
 
-        if let Some(ref params) = completion_request.additional_params {
-            Ok(json_utils::merge(request.clone(), params.clone()))
-        } else {
-            Ok(request)
+        // Only send the optional parameters that were set, either on the request or on the model
+        let max_tokens = completion_request
+            .max_tokens
+            .or(self.max_tokens.map(u64::from));
+        let p = completion_request.top_p.or(self.p);
+        let k = completion_request.top_k.or(self.k.map(u64::from));
+        let stop_sequences = check_stop_sequences(
+            completion_request
+                .stop_sequences
+                .or_else(|| self.stop_sequences.clone()),
+        )?;
+        let params = [
+            ("max_tokens", max_tokens.map(Value::from)),
+            ("p", p.map(Value::from)),
+            ("k", k.map(Value::from)),
+            ("stop_sequences", stop_sequences.map(Value::from)),
+            ("seed", completion_request.seed.map(Value::from)),
+            (
+                "frequency_penalty",
+                penalty("frequency_penalty", completion_request.frequency_penalty)?
+                    .map(Value::from),
+            ),
+            (
+                "presence_penalty",
+                penalty("presence_penalty", completion_request.presence_penalty)?.map(Value::from),
+            ),
+            ("logprobs", self.logprobs.then_some(Value::Bool(true))),
+            (
+                "raw_prompting",
+                self.raw_prompting.then_some(Value::Bool(true)),
+            ),
+            ("safety_mode", self.safety_mode.map(|mode| json!(mode))),
+        ];
+        for (key, value) in params {
+            if let Some(value) = value {
+                request[key] = value;
+            }
+        }
+
+        // Raw turns go between the preamble and the converted chat history
+        if let Some(raw_history) = raw_history {
+            if let Some(messages) = request["messages"].as_array_mut() {
+                let position = usize::from(has_preamble);
+                messages.splice(position..position, raw_history);
+            }
+        }
+
+        if let Some(ref params) = additional_params {
+            Ok(json_utils::merge_with(
+                request.clone(),
+                params.clone(),
+                self.array_merge,
+            ))
+        } else {
+            Ok(request)
         }
     }
 }
 
+/// Maximum number of stop sequences of a chat request accepted by the Cohere API.
+pub const MAX_STOP_SEQUENCES: usize = 5;
+
+/// Default maximum number of concurrent requests of [CompletionModel::completion_batch].
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// Header of the idempotency key of the chat requests (see
+/// [CompletionModel::with_idempotency_keys]).
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Random idempotency key, formatted as a UUID v4.
+fn new_idempotency_key() -> String {
+    // Version 4 (random), variant 1
+    const VERSION: u128 = 0x4 << 76;
+    const VARIANT: u128 = 0x8 << 60;
+    let bits = (fastrand::u128(..) & !(0xf << 76) & !(0xc << 60)) | VERSION | VARIANT;
+    let hex = format!("{bits:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Key of the additional parameters of a completion request holding raw chat history turns
+/// (see [raw_chat_history]).
+pub const RAW_CHAT_HISTORY: &str = "raw_chat_history";
+
+/// Additional parameters passing pre-formatted Cohere chat turns (e.g.: `tool` role turns of a
+/// resumed conversation) to a completion request, bypassing the conversion of
+/// [message::Message]. The turns are sent as is, between the preamble and the chat history of
+/// the request, and must follow the Cohere v2 chat message format.
+///
+/// # Example
+/// ```
+/// use rig::{completion::Completion, providers::cohere};
+/// use serde_json::json;
+///
+/// let response = agent
+///     .completion("What did the tool return?", vec![])
+///     .await?
+///     .additional_params(cohere::raw_chat_history(vec![
+///         json!({"role": "user", "content": "What's the weather in Paris?"}),
+///         json!({
+///             "role": "assistant",
+///             "tool_calls": [{
+///                 "id": "call_0",
+///                 "type": "function",
+///                 "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}
+///             }]
+///         }),
+///         json!({
+///             "role": "tool",
+///             "tool_call_id": "call_0",
+///             "content": [{"type": "document", "document": {"data": "Sunny"}}]
+///         }),
+///     ]))
+///     .send()
+///     .await?;
+/// ```
+pub fn raw_chat_history(turns: Vec<Value>) -> Value {
+    json!({ RAW_CHAT_HISTORY: turns })
+}
+
+/// Remove the raw chat history turns from the additional parameters of a request, checking that
+/// each turn has a role.
+fn take_raw_chat_history(
+    additional_params: &mut Option<Value>,
+) -> Result<Option<Vec<Value>>, CompletionError> {
+    let Some(raw_history) = additional_params
+        .as_mut()
+        .and_then(Value::as_object_mut)
+        .and_then(|params| params.remove(RAW_CHAT_HISTORY))
+    else {
+        return Ok(None);
+    };
+
+    let Value::Array(turns) = raw_history else {
+        return Err(CompletionError::RequestError(
+            format!("{RAW_CHAT_HISTORY} must be an array of chat turns").into(),
+        ));
+    };
+    if let Some(i) = turns.iter().position(|turn| !turn["role"].is_string()) {
+        return Err(CompletionError::RequestError(
+            format!("{RAW_CHAT_HISTORY} turn at index {i} has no role").into(),
+        ));
+    }
+
+    Ok(Some(turns))
+}
+
+/// Requests of the candidates generated by [CompletionModel::completions].
+fn candidate_requests(
+    completion_request: &CompletionRequest,
+    num_generations: usize,
+) -> Vec<CompletionRequest> {
+    (0..num_generations as u64)
+        .map(|i| CompletionRequest {
+            seed: completion_request.seed.map(|seed| seed.wrapping_add(i)),
+            ..completion_request.clone()
+        })
+        .collect()
+}
+
+/// Check that there are no more stop sequences than accepted by the Cohere API.
+fn check_stop_sequences(
+    stop_sequences: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, CompletionError> {
+    match stop_sequences {
+        Some(stop_sequences) if stop_sequences.len() > MAX_STOP_SEQUENCES => {
+            Err(CompletionError::RequestError(
+                format!(
+                    "Cohere accepts at most {MAX_STOP_SEQUENCES} stop sequences, got {}",
+                    stop_sequences.len()
+                )
+                .into(),
+            ))
+        }
+        stop_sequences => Ok(stop_sequences),
+    }
+}
+
+/// Check that a penalty is within the range accepted by the Cohere API (0.0 to 1.0).
+fn penalty(name: &str, value: Option<f64>) -> Result<Option<f64>, CompletionError> {
+    match value {
+        Some(value) if !(0.0..=1.0).contains(&value) => Err(CompletionError::RequestError(
+            format!("Cohere accepts a {name} between 0.0 and 1.0, got {value}").into(),
+        )),
+        value => Ok(value),
+    }
+}
+
 impl completion::CompletionModel for CompletionModel {
     type Response = CompletionResponse;
 
+    /// Context window of the model (see [super::CONTEXT_WINDOWS]), or
+    /// [DEFAULT_CONTEXT_WINDOW](super::DEFAULT_CONTEXT_WINDOW) if the model is unknown.
+    fn max_context_tokens(&self) -> Option<usize> {
+        Some(super::context_window(&self.model).unwrap_or(super::DEFAULT_CONTEXT_WINDOW))
+    }
+
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_completion",
+        skip_all,
+        fields(
+            model = %self.model,
+            documents = completion_request.documents.len(),
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
     #[cfg_attr(feature = "worker", worker::send)]
     async fn completion(
         &self,
         completion_request: completion::CompletionRequest,
     ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let tools = self
+            .validate_tool_calls
+            .then(|| completion_request.tools.clone());
         let request = self.create_completion_request(completion_request)?;
         tracing::debug!(
             "Cohere request: {}",
             serde_json::to_string_pretty(&request)?
         );
 
-        let response = self.client.post("/v2/chat").json(&request).send().await?;
+        let response = self.client.send(self.chat_request(&request)).await?;
 
         if response.status().is_success() {
-            let text_response = response.text().await?;
-            tracing::debug!("Cohere response text: {}", text_response);
+            let body = read_body(response, self.client.max_response_size()).await?;
+            tracing::debug!("Cohere response text: {}", String::from_utf8_lossy(&body));
 
-            let json_response: CompletionResponse = serde_json::from_str(&text_response)?;
+            let json_response: CompletionResponse = serde_json::from_slice(&body)?;
+            drop(body);
             let completion: completion::CompletionResponse<CompletionResponse> =
                 json_response.try_into()?;
+            if let Some(tools) = tools {
+                completion.validate_tool_calls(&tools)?;
+            }
             Ok(completion)
         } else {
-            Err(CompletionError::ProviderError(response.text().await?))
+            Err(completion_error(response).await)
+        }
+    }
+}
+
+/// Read the body of a response, failing with a [CompletionError::ResponseError] as soon as it
+/// exceeds `max_size` bytes (if set).
+async fn read_body(
+    mut response: reqwest::Response,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, CompletionError> {
+    let too_large = |max_size| {
+        CompletionError::ResponseError(format!(
+            "Response body exceeds the maximum response size of {max_size} bytes"
+        ))
+    };
+
+    let Some(max_size) = max_size else {
+        return Ok(response.bytes().await?.into());
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_size as u64)
+    {
+        return Err(too_large(max_size));
+    }
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Err(too_large(max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Map an unsuccessful response of the Cohere chat API to a [CompletionError] according to
+/// its HTTP status.
+pub(crate) async fn completion_error(response: reqwest::Response) -> CompletionError {
+    let status = response.status();
+    let retry_after = super::retry::retry_after(&response);
+
+    match response.text().await {
+        Ok(text) => completion_error_from_parts(status, retry_after, text),
+        Err(error) => error.into(),
+    }
+}
+
+fn completion_error_from_parts(
+    status: reqwest::StatusCode,
+    retry_after: Option<std::time::Duration>,
+    text: String,
+) -> CompletionError {
+    use reqwest::StatusCode;
+
+    let message = ApiErrorResponse::message_from_body(text);
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => CompletionError::Unauthorized(message),
+        StatusCode::TOO_MANY_REQUESTS => CompletionError::RateLimited {
+            retry_after,
+            message,
+        },
+        StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => {
+            CompletionError::InvalidRequest(message)
         }
+        status if status.is_server_error() => CompletionError::ServerError {
+            status: status.as_u16(),
+            message,
+        },
+        status => CompletionError::ProviderError(format!("{status}: {message}")),
     }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -559,6 +1733,15 @@ mod tests {
         assert_eq!(id, "abc123");
         assert_eq!(finish_reason, FinishReason::ToolCall);
 
+        assert_eq!(
+            usage.as_ref().unwrap().billed_units(),
+            usage::BilledUnits {
+                input_tokens: 78,
+                output_tokens: 27,
+                search_units: 0,
+                classifications: 0,
+            }
+        );
         let Usage {
             billed_units,
             tokens,
@@ -615,4 +1798,1248 @@ mod tests {
         let completion_message: completion::Message = message.clone().try_into().unwrap();
         let _converted_back: Vec<Message> = completion_message.try_into().unwrap();
     }
+
+    #[test]
+    fn test_model_parameters_in_request() {
+        let model = Client::new("dummy-key")
+            .completion_model(super::super::COMMAND_R)
+            .with_temperature(0.3)
+            .with_max_tokens(100)
+            .with_p(0.9)
+            .with_k(40)
+            .with_stop_sequences(vec!["END".to_string()]);
+
+        let request = completion::CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(completion::Message::user("Hello")),
+            documents: vec![],
+            tools: vec![],
+            temperature: Some(0.7),
+            max_tokens: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            additional_params: None,
+        };
+
+        let request = model.create_completion_request(request).unwrap();
+
+        assert_eq!(request["temperature"], 0.7);
+        assert_eq!(request["max_tokens"], 100);
+        assert_eq!(request["p"], 0.9);
+        assert_eq!(request["k"], 40);
+        assert_eq!(request["stop_sequences"], json!(["END"]));
+    }
+
+    #[test]
+    fn test_search_queries() {
+        let json_data = r#"
+        {
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "tool_plan": "I will search for flurbos.",
+                "tool_calls": [
+                    {
+                        "id": "call_0",
+                        "type": "function",
+                        "function": { "name": "search", "arguments": "{\"query\": \"flurbo color\"}" }
+                    },
+                    {
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "calculator", "arguments": "{\"x\": 1}" }
+                    },
+                    {
+                        "id": "call_2",
+                        "type": "function",
+                        "function": { "name": "search", "arguments": "{\"query\": \"flurbo habitat\"}" }
+                    }
+                ]
+            },
+            "finish_reason": "TOOL_CALL"
+        }
+        "#;
+
+        let response: CompletionResponse = serde_json::from_str(json_data).unwrap();
+        assert!(response.is_search_required("search"));
+        assert!(!response.is_search_required("web_search"));
+        assert_eq!(response.tool_calls().len(), 3);
+        assert_eq!(
+            response.search_queries("search"),
+            vec!["flurbo color".to_string(), "flurbo habitat".to_string()]
+        );
+        assert!(response.search_queries("web_search").is_empty());
+        assert_eq!(response.tool_plan(), Some("I will search for flurbos."));
+
+        // The tool plan is kept in the chat history, before the tool calls
+        let completion: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(completion.choice.len(), 4);
+        assert_eq!(
+            completion.choice.first(),
+            completion::AssistantContent::text("I will search for flurbos.")
+        );
+
+        let response: CompletionResponse = serde_json::from_str(
+            r#"{"id": "abc123", "message": {"role": "assistant", "content": [{"type": "text", "text": "Hi"}]}, "finish_reason": "COMPLETE"}"#,
+        )
+        .unwrap();
+        assert!(!response.is_search_required("search"));
+    }
+
+    #[test]
+    fn test_tool_calls_without_function() {
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "tool_calls": [
+                    {"id": "call_0", "type": "function", "function": {"name": "search", "arguments": "{}"}},
+                    {"id": "call_1", "type": "function"}
+                ]
+            },
+            "finish_reason": "TOOL_CALL"
+        }))
+        .unwrap();
+        let completion: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(completion.tool_call_count(), 1);
+
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "message": {"role": "assistant", "tool_calls": [{"id": "call_0"}]},
+            "finish_reason": "TOOL_CALL"
+        }))
+        .unwrap();
+        let error =
+            completion::CompletionResponse::<CompletionResponse>::try_from(response).unwrap_err();
+        assert!(matches!(error, CompletionError::ResponseError(_)));
+    }
+
+    #[test]
+    fn test_empty_response() {
+        for message in [
+            json!({"role": "assistant"}),
+            json!({"role": "assistant", "content": [], "tool_calls": []}),
+            json!({"role": "assistant", "content": [{"type": "text", "text": ""}]}),
+        ] {
+            let response: CompletionResponse = serde_json::from_value(json!({
+                "id": "abc123",
+                "message": message,
+                "finish_reason": "ERROR"
+            }))
+            .unwrap();
+            let error = completion::CompletionResponse::<CompletionResponse>::try_from(response)
+                .unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "EmptyResponse: Response has no text or tool call (finish reason: ERROR)"
+            );
+        }
+
+        // Tool calls are kept when the text is empty
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "content": [{"type": "text", "text": ""}],
+                "tool_calls": [{"id": "call_0", "type": "function", "function": {"name": "search", "arguments": "{}"}}]
+            },
+            "finish_reason": "TOOL_CALL"
+        }))
+        .unwrap();
+        let completion: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(completion.tool_call_count(), 1);
+        assert!(matches!(
+            completion.choice.first(),
+            completion::AssistantContent::ToolCall(_)
+        ));
+    }
+
+    #[test]
+    fn test_cited_spans() {
+        let json_data = r#"
+        {
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Flurbos are green aliens." }],
+                "citations": [
+                    {
+                        "start": 0,
+                        "end": 7,
+                        "text": "Flurbos",
+                        "sources": [
+                            { "type": "document", "id": "doc1", "document": { "id": "doc1" } },
+                            { "type": "tool", "id": "call_0:0", "tool_output": { "result": "flurbo" } }
+                        ]
+                    },
+                    {
+                        "start": 12,
+                        "end": 24,
+                        "text": "green aliens",
+                        "sources": [
+                            { "type": "document", "id": "doc0", "document": { "id": "doc0" } },
+                            { "type": "document", "id": "doc1", "document": { "id": "doc1" } },
+                            { "type": "document", "id": "unknown", "document": { "id": "unknown" } }
+                        ]
+                    }
+                ]
+            },
+            "finish_reason": "COMPLETE"
+        }
+        "#;
+        let response: CompletionResponse = serde_json::from_str(json_data).unwrap();
+
+        let documents = vec![
+            completion::Document {
+                id: "doc0".to_string(),
+                text: "A flurbo is a green alien.".to_string(),
+                additional_props: HashMap::from([(
+                    "url".to_string(),
+                    "https://flurbo.wiki/aliens".to_string(),
+                )]),
+            },
+            completion::Document {
+                id: "doc1".to_string(),
+                text: "Flurbos live on cold planets.".to_string(),
+                additional_props: HashMap::new(),
+            },
+        ];
+
+        let spans = response
+            .cited_spans(&documents)
+            .into_iter()
+            .map(|(citation, document)| (citation.text.as_deref().unwrap(), document.id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("Flurbos", "doc1"),
+                ("green aliens", "doc0"),
+                ("green aliens", "doc1")
+            ]
+        );
+        assert_eq!(
+            response.cited_spans(&documents)[1].1.additional_props["url"],
+            "https://flurbo.wiki/aliens"
+        );
+    }
+
+    #[test]
+    fn test_citations_and_cited_documents() {
+        let json_data = r#"
+        {
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Flurbos are green aliens from cold planets." }],
+                "citations": [
+                    {
+                        "start": 0,
+                        "end": 7,
+                        "text": "Flurbos",
+                        "type": "TEXT_CONTENT",
+                        "sources": [{ "type": "document", "id": "doc0", "document": { "id": "doc0", "text": "A flurbo is a green alien." } }]
+                    },
+                    {
+                        "start": 12,
+                        "end": 24,
+                        "text": "green aliens",
+                        "type": "TEXT_CONTENT",
+                        "sources": [
+                            { "type": "document", "id": "doc0", "document": { "id": "doc0", "text": "A flurbo is a green alien." } },
+                            { "type": "document", "id": "doc1", "document": { "id": "doc1", "text": "Flurbos live on cold planets." } }
+                        ]
+                    }
+                ]
+            },
+            "finish_reason": "COMPLETE"
+        }
+        "#;
+
+        let response: CompletionResponse = serde_json::from_str(json_data).unwrap();
+
+        let citations = response.citations();
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[1].start, Some(12));
+        assert_eq!(citations[1].end, Some(24));
+
+        let documents = response.cited_documents();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].0, "doc0");
+        assert_eq!(documents[1].0, "doc1");
+        assert_eq!(documents[1].1["text"], "Flurbos live on cold planets.");
+    }
+
+    #[test]
+    fn test_citation_density() {
+        let json_data = r#"
+        {
+            "id": "abc123",
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Flurbos are green aliens from cold planets." }],
+                "citations": [
+                    {
+                        "start": 0,
+                        "end": 24,
+                        "text": "Flurbos are green aliens",
+                        "sources": [{ "type": "document", "id": "doc0" }]
+                    },
+                    {
+                        "start": 12,
+                        "end": 24,
+                        "text": "green aliens",
+                        "sources": [{ "type": "document", "id": "doc0" }, { "type": "document", "id": "doc1" }]
+                    },
+                    {
+                        "start": 30,
+                        "end": 34,
+                        "text": "cold",
+                        "sources": [{ "type": "tool", "id": "search_0" }]
+                    }
+                ]
+            },
+            "finish_reason": "COMPLETE"
+        }
+        "#;
+
+        let response: CompletionResponse = serde_json::from_str(json_data).unwrap();
+
+        // The response text has 43 characters, 24 of which are cited from doc0 (the spans of its
+        // citations overlap) and 12 from doc1
+        let density = response.citation_density();
+        assert_eq!(density.len(), 2);
+        assert_eq!(density[0].0, "doc0");
+        assert!((density[0].1 - 24.0 / 43.0).abs() < 1e-9);
+        assert_eq!(density[1].0, "doc1");
+        assert!((density[1].1 - 12.0 / 43.0).abs() < 1e-9);
+
+        let spans = |citations: Vec<&Citation>| {
+            citations
+                .iter()
+                .map(|citation| citation.start)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans(response.citations_above_density(0.0)),
+            vec![Some(0), Some(12)]
+        );
+        assert_eq!(
+            spans(response.citations_above_density(0.5)),
+            vec![Some(0), Some(12)]
+        );
+        assert!(response.citations_above_density(0.6).is_empty());
+    }
+
+    #[test]
+    fn test_tool_result_round_trip_request() {
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+
+        let request = completion::CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::many(vec![
+                completion::Message::user("What is 5 - 2?"),
+                completion::Message::Assistant {
+                    content: OneOrMany::one(completion::AssistantContent::tool_call(
+                        "subtract_sm6ps6fb6y9f",
+                        "subtract",
+                        json!({"x": 5, "y": 2}),
+                    )),
+                },
+                completion::Message::User {
+                    content: OneOrMany::one(completion::message::UserContent::tool_result(
+                        "subtract_sm6ps6fb6y9f",
+                        OneOrMany::one(completion::message::ToolResultContent::text("3")),
+                    )),
+                },
+            ])
+            .unwrap(),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            additional_params: None,
+        };
+
+        let request = model.create_completion_request(request).unwrap();
+
+        assert_eq!(
+            request["messages"],
+            json!([
+                {
+                    "role": "user",
+                    "content": [{ "type": "text", "text": "What is 5 - 2?" }]
+                },
+                {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "subtract_sm6ps6fb6y9f",
+                        "type": "function",
+                        "function": { "name": "subtract", "arguments": "{\"x\":5,\"y\":2}" }
+                    }]
+                },
+                {
+                    "role": "tool",
+                    "tool_call_id": "subtract_sm6ps6fb6y9f",
+                    "content": [{ "type": "text", "text": "3" }]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_sampling_parameters() {
+        let model = Client::new("dummy-key")
+            .completion_model(super::super::COMMAND_R)
+            .with_k(40);
+
+        let request = model
+            .create_completion_request(completion::CompletionRequest {
+                preamble: None,
+                chat_history: OneOrMany::one(completion::Message::user("Hello")),
+                documents: vec![],
+                tools: vec![],
+                temperature: None,
+                max_tokens: Some(50),
+                top_p: Some(0.75),
+                top_k: Some(10),
+                stop_sequences: Some(vec!["\n\n".to_string()]),
+                seed: Some(42),
+                frequency_penalty: None,
+                presence_penalty: None,
+                additional_params: None,
+            })
+            .unwrap();
+
+        assert_eq!(request["max_tokens"], 50);
+        assert_eq!(request["p"], 0.75);
+        assert_eq!(request["k"], 10);
+        assert_eq!(request["stop_sequences"], json!(["\n\n"]));
+        assert_eq!(request["seed"], 42);
+
+        let request = model
+            .create_completion_request(completion::CompletionRequest {
+                preamble: None,
+                chat_history: OneOrMany::one(completion::Message::user("Hello")),
+                documents: vec![],
+                tools: vec![],
+                temperature: None,
+                max_tokens: None,
+                top_p: None,
+                top_k: None,
+                stop_sequences: None,
+                seed: None,
+                frequency_penalty: None,
+                presence_penalty: None,
+                additional_params: None,
+            })
+            .unwrap();
+
+        assert_eq!(request["k"], 40);
+        assert!(request.get("max_tokens").is_none());
+        assert!(request.get("p").is_none());
+        assert!(request.get("stop_sequences").is_none());
+        assert!(request.get("seed").is_none());
+
+        let request = completion::CompletionModel::completion_request(&model, "Hello")
+            .stop_sequences((0..6).map(|i| format!("STOP{i}")).collect())
+            .build();
+        let error = model.create_completion_request(request).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "RequestError: Cohere accepts at most 5 stop sequences, got 6"
+        );
+    }
+
+    #[test]
+    fn test_tool_definition_conversion() {
+        let tool = Tool::try_from(completion::ToolDefinition {
+            name: "get_current_time".to_string(),
+            description: "".to_string(),
+            parameters: Value::Null,
+        })
+        .unwrap();
+        assert_eq!(tool.function.description, None);
+        assert_eq!(
+            tool.function.parameters,
+            json!({ "type": "object", "properties": {} })
+        );
+
+        let error = Tool::try_from(completion::ToolDefinition {
+            name: "broken".to_string(),
+            description: "A tool with an invalid schema".to_string(),
+            parameters: json!("not a schema"),
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("broken"));
+    }
+
+    #[test]
+    fn test_parameterless_tool_definition() {
+        let tool = Tool::try_from(completion::ToolDefinition {
+            name: "get_current_time".to_string(),
+            description: "Get the current time".to_string(),
+            parameters: json!({ "type": "object" }),
+        })
+        .unwrap();
+
+        assert_eq!(
+            tool.function.parameters,
+            json!({ "type": "object", "properties": {} })
+        );
+    }
+
+    #[test]
+    fn test_user_message_round_trip() {
+        let user_message = completion::Message::User {
+            content: OneOrMany::many(vec![
+                completion::message::UserContent::text("What is in this image?"),
+                completion::message::UserContent::image(
+                    "iVBORw0KGgo=",
+                    Some(completion::message::ContentFormat::Base64),
+                    Some(completion::message::ImageMediaType::PNG),
+                    None,
+                ),
+                completion::message::UserContent::image(
+                    "https://example.com/image.png",
+                    Some(completion::message::ContentFormat::String),
+                    None,
+                    None,
+                ),
+            ])
+            .unwrap(),
+        };
+
+        let messages: Vec<Message> = user_message.clone().try_into().unwrap();
+        assert_eq!(messages.len(), 1);
+        let json = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(json["content"][1]["type"], "image_url");
+        assert_eq!(
+            json["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+        assert_eq!(json["content"][2]["type"], "image_url");
+
+        let converted_back: completion::Message = messages[0].clone().try_into().unwrap();
+        assert_eq!(converted_back, user_message);
+    }
+
+    #[test]
+    fn test_assistant_message_round_trip() {
+        let assistant_message = completion::Message::Assistant {
+            content: OneOrMany::many(vec![
+                completion::AssistantContent::text("Let me compute that."),
+                completion::AssistantContent::tool_call(
+                    "subtract_sm6ps6fb6y9f",
+                    "subtract",
+                    json!({"x": 5, "y": 2}),
+                ),
+            ])
+            .unwrap(),
+        };
+
+        let messages: Vec<Message> = assistant_message.clone().try_into().unwrap();
+        assert_eq!(messages.len(), 1);
+
+        // The text of a message with tool calls is sent back as its tool plan
+        match &messages[0] {
+            Message::Assistant {
+                content, tool_plan, ..
+            } => {
+                assert!(content.is_empty());
+                assert_eq!(tool_plan.as_deref(), Some("Let me compute that."));
+            }
+            message => panic!("Expected an assistant message, got {message:?}"),
+        }
+
+        let converted_back: completion::Message = messages[0].clone().try_into().unwrap();
+        assert_eq!(converted_back, assistant_message);
+    }
+
+    #[test]
+    fn test_system_message_round_trip() {
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let chat_history = vec![
+            completion::Message::user("What is 5 - 2?"),
+            completion::Message::assistant("3"),
+        ];
+        let request = completion::CompletionRequestBuilder::new(model.clone(), "And 3 - 1?")
+            .preamble("You are a calculator.".to_string())
+            .messages(chat_history.clone())
+            .build();
+
+        let body = model.build_request(&request).unwrap();
+        let messages: Vec<Message> = serde_json::from_value(body["messages"].clone()).unwrap();
+        assert_eq!(
+            messages[0],
+            Message::System {
+                content: "You are a calculator.".to_string()
+            }
+        );
+
+        // A system message alone has no equivalent
+        let error = completion::Message::try_from(messages[0].clone()).unwrap_err();
+        assert!(
+            error.to_string().contains("restore_chat_history"),
+            "{error}"
+        );
+
+        let (preamble, restored) = super::restore_chat_history(messages).unwrap();
+        assert_eq!(preamble.as_deref(), Some("You are a calculator."));
+        assert_eq!(
+            restored,
+            [chat_history, vec![completion::Message::user("And 3 - 1?")]].concat()
+        );
+    }
+
+    #[test]
+    fn test_tool_results_keep_message_order() {
+        let user_message = completion::Message::User {
+            content: OneOrMany::many(vec![
+                completion::message::UserContent::tool_result(
+                    "call_0",
+                    OneOrMany::one(completion::message::ToolResultContent::text("3")),
+                ),
+                completion::message::UserContent::text("Now double it."),
+            ])
+            .unwrap(),
+        };
+
+        let messages: Vec<Message> = user_message.try_into().unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], Message::Tool { .. }));
+        assert!(matches!(messages[1], Message::User { .. }));
+    }
+
+    #[test]
+    fn test_deserialize_unknown_shapes() {
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "finish_reason": "NEW_REASON",
+            "message": {
+                "role": "assistant",
+                "content": [
+                    {"type": "thinking", "thinking": "Let me think..."},
+                    {"type": "text", "text": "Penguins are birds."}
+                ],
+                "citations": [{
+                    "start": 0,
+                    "end": 8,
+                    "text": "Penguins",
+                    "type": "NEW_CITATION_TYPE",
+                    "sources": [{"type": "web", "id": "web_0"}]
+                }]
+            },
+            "model_version": "2025-01-01"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            response.finish_reason,
+            FinishReason::Other("NEW_REASON".to_string())
+        );
+        assert_eq!(response.extra["model_version"], "2025-01-01");
+
+        let (content, citations, _) = response.message();
+        assert_eq!(content[0], AssistantContent::Other);
+        assert_eq!(citations[0].citation_type, Some(CitationType::Other));
+        assert_eq!(citations[0].sources, vec![Source::Other]);
+
+        let response: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(response.generation_id(), "abc123");
+        assert_eq!(
+            response.choice,
+            OneOrMany::one(completion::AssistantContent::text("Penguins are birds."))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_finish_reason() {
+        let reason: FinishReason = serde_json::from_value(json!("MAX_TOKENS")).unwrap();
+        assert_eq!(reason, FinishReason::MaxTokens);
+
+        let reason: FinishReason = serde_json::from_value(json!("CONTENT_FILTERED")).unwrap();
+        assert_eq!(reason, FinishReason::Other("CONTENT_FILTERED".to_string()));
+        assert_eq!(reason.to_string(), "CONTENT_FILTERED");
+    }
+
+    #[test]
+    fn test_logprobs() {
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let request = |model: &CompletionModel| {
+            model
+                .create_completion_request(completion::CompletionRequest {
+                    preamble: None,
+                    chat_history: OneOrMany::one(completion::Message::user("Hello")),
+                    documents: vec![],
+                    tools: vec![],
+                    temperature: None,
+                    max_tokens: None,
+                    top_p: None,
+                    top_k: None,
+                    stop_sequences: None,
+                    seed: None,
+                    frequency_penalty: None,
+                    presence_penalty: None,
+                    additional_params: None,
+                })
+                .unwrap()
+        };
+
+        assert!(request(&model).get("logprobs").is_none());
+        assert_eq!(
+            request(&model.clone().with_logprobs(true))["logprobs"],
+            true
+        );
+
+        assert!(request(&model).get("raw_prompting").is_none());
+        assert_eq!(
+            request(&model.clone().with_raw_prompting(true))["raw_prompting"],
+            true
+        );
+
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "finish_reason": "COMPLETE",
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "Hi there" }]
+            },
+            "logprobs": [
+                { "token_ids": [28339], "text": "Hi", "logprobs": [-0.0123] },
+                { "token_ids": [2155], "text": " there", "logprobs": [-1.5] }
+            ]
+        }))
+        .unwrap();
+
+        let logprobs = response.logprobs.unwrap();
+        assert_eq!(logprobs.len(), 2);
+        assert_eq!(logprobs[1].text.as_deref(), Some(" there"));
+        assert_eq!(logprobs[1].logprobs, vec![-1.5]);
+    }
+
+    #[test]
+    fn test_build_request() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let request = model
+            .completion_request("What is 2 + 3?")
+            .preamble("You are a calculator.".to_string())
+            .temperature(0.0)
+            .tool(completion::ToolDefinition {
+                name: "add".to_string(),
+                description: "Add two numbers".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" }
+                    }
+                }),
+            })
+            .build();
+
+        assert_eq!(
+            model.build_request(&request).unwrap(),
+            json!({
+                "model": "command-r",
+                "messages": [
+                    { "role": "system", "content": "You are a calculator." },
+                    { "role": "user", "content": [{ "type": "text", "text": "What is 2 + 3?" }] }
+                ],
+                "documents": [],
+                "temperature": 0.0,
+                "tools": [{
+                    "type": "function",
+                    "function": {
+                        "name": "add",
+                        "description": "Add two numbers",
+                        "parameters": {
+                            "type": "object",
+                            "properties": {
+                                "x": { "type": "number" },
+                                "y": { "type": "number" }
+                            }
+                        }
+                    }
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_request_penalties() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+
+        let request = model
+            .completion_request("Hello")
+            .frequency_penalty(0.5)
+            .presence_penalty(0.0)
+            .build();
+        let request = model.build_request(&request).unwrap();
+        assert_eq!(request["frequency_penalty"], 0.5);
+        assert_eq!(request["presence_penalty"], 0.0);
+
+        let request = model.completion_request("Hello").build();
+        let request = model.build_request(&request).unwrap();
+        assert!(request.get("frequency_penalty").is_none());
+        assert!(request.get("presence_penalty").is_none());
+
+        let request = model
+            .completion_request("Hello")
+            .presence_penalty(1.5)
+            .build();
+        assert_eq!(
+            model.build_request(&request).unwrap_err().to_string(),
+            "RequestError: Cohere accepts a presence_penalty between 0.0 and 1.0, got 1.5"
+        );
+    }
+
+    #[test]
+    fn test_completion_error_from_status() {
+        use reqwest::StatusCode;
+        use std::time::Duration;
+
+        let error = |status, text: &str| {
+            completion_error_from_parts(status, Some(Duration::from_secs(5)), text.to_string())
+        };
+
+        assert!(matches!(
+            error(StatusCode::UNAUTHORIZED, r#"{"message":"invalid api token"}"#),
+            CompletionError::Unauthorized(message) if message == "invalid api token"
+        ));
+        assert!(matches!(
+            error(StatusCode::TOO_MANY_REQUESTS, "too many requests"),
+            CompletionError::RateLimited { retry_after: Some(retry_after), .. }
+                if retry_after == Duration::from_secs(5)
+        ));
+        assert!(matches!(
+            error(StatusCode::BAD_REQUEST, r#"{"message":"invalid model"}"#),
+            CompletionError::InvalidRequest(message) if message == "invalid model"
+        ));
+        assert!(matches!(
+            error(StatusCode::SERVICE_UNAVAILABLE, "unavailable"),
+            CompletionError::ServerError { status: 503, .. }
+        ));
+        assert!(matches!(
+            error(StatusCode::PAYMENT_REQUIRED, "payment required"),
+            CompletionError::ProviderError(_)
+        ));
+    }
+
+    #[test]
+    fn test_safety_mode() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let request = model.completion_request("Hello").build();
+
+        assert!(model
+            .build_request(&request)
+            .unwrap()
+            .get("safety_mode")
+            .is_none());
+        assert_eq!(
+            model
+                .with_safety_mode(SafetyMode::Strict)
+                .build_request(&request)
+                .unwrap()["safety_mode"],
+            "STRICT"
+        );
+    }
+
+    #[test]
+    fn test_additional_params_array_merge() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let request = model
+            .completion_request("Hello")
+            .tool(completion::ToolDefinition {
+                name: "search".to_string(),
+                description: "Search the web".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            })
+            .additional_params(json!({
+                "tools": [{
+                    "type": "function",
+                    "function": {"name": "calculator", "parameters": {"type": "object"}}
+                }]
+            }))
+            .build();
+
+        let tool_names = |request: Value| {
+            request["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|tool| tool["function"]["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            tool_names(model.build_request(&request).unwrap()),
+            vec!["calculator"]
+        );
+        assert_eq!(
+            tool_names(
+                model
+                    .with_array_merge(super::super::ArrayMerge::Append)
+                    .build_request(&request)
+                    .unwrap()
+            ),
+            vec!["search", "calculator"]
+        );
+    }
+
+    #[test]
+    fn test_idempotency_keys() {
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let disabled = model.clone();
+        let model = model.with_idempotency_keys(true);
+
+        let key = |model: &CompletionModel| {
+            model
+                .chat_request(&json!({}))
+                .build()
+                .unwrap()
+                .headers()
+                .get(super::IDEMPOTENCY_KEY_HEADER)
+                .map(|key| key.to_str().unwrap().to_string())
+        };
+
+        let first = key(&model).unwrap();
+        assert_eq!(first.len(), 36);
+        assert_eq!(&first[14..15], "4");
+        assert!(matches!(&first[19..20], "8" | "9" | "a" | "b"));
+        // One key per logical request
+        assert_ne!(key(&model).unwrap(), first);
+
+        assert_eq!(key(&disabled), None);
+        assert_eq!(key(&model.with_idempotency_keys(false)), None);
+    }
+
+    #[tokio::test]
+    async fn test_max_response_size() {
+        use super::super::testing::{TestResponse, TestServer};
+        use completion::CompletionModel as _;
+
+        let body = json!({
+            "id": "abc123",
+            "message": {"role": "assistant", "content": [{"type": "text", "text": "x".repeat(1000)}]},
+            "finish_reason": "COMPLETE"
+        })
+        .to_string();
+
+        // Server answering with and without a content length
+        let server = TestServer::serve(vec![
+            TestResponse::json(&body),
+            TestResponse::json(&body).without_content_length(),
+            TestResponse::json(&body),
+        ])
+        .await;
+        let base_url = server.base_url;
+
+        let client = |max_response_size| {
+            super::super::ClientBuilder::new("dummy-key")
+                .base_url(&base_url)
+                .max_response_size(max_response_size)
+                .build()
+                .with_retry_policy(super::super::RetryPolicy::none())
+        };
+
+        for _ in 0..2 {
+            let model = client(body.len() - 1).completion_model(super::super::COMMAND_R);
+            let request = model.completion_request("Hello").build();
+            let error = model.completion(request).await.unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("exceeds the maximum response size"),
+                "{error}"
+            );
+        }
+
+        let model = client(body.len()).completion_model(super::super::COMMAND_R);
+        let request = model.completion_request("Hello").build();
+        let response = model.completion(request).await.unwrap();
+        assert_eq!(response.text().unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn test_raw_chat_history() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let tool_turn = json!({
+            "role": "tool",
+            "tool_call_id": "call_0",
+            "content": [{"type": "document", "document": {"data": "Sunny"}}]
+        });
+        let request = model
+            .completion_request("Hello")
+            .preamble("Be brief".to_string())
+            .additional_params(super::raw_chat_history(vec![tool_turn.clone()]))
+            .build();
+
+        let request = model.build_request(&request).unwrap();
+        assert_eq!(
+            request["messages"],
+            json!([
+                {"role": "system", "content": "Be brief"},
+                tool_turn,
+                {"role": "user", "content": [{"type": "text", "text": "Hello"}]},
+            ])
+        );
+        assert!(request.get(super::RAW_CHAT_HISTORY).is_none());
+
+        let request = model
+            .completion_request("Hello")
+            .additional_params(super::raw_chat_history(vec![json!({"content": "Hi"})]))
+            .build();
+        assert_eq!(
+            model.build_request(&request).unwrap_err().to_string(),
+            "RequestError: raw_chat_history turn at index 0 has no role"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_batch() {
+        use super::super::testing::{TestResponse, TestServer};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        // Server answering "answer-i" to "prompt-i", the first prompts being the slowest
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+            TestServer::serve_with(move |request| {
+                let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+                async move {
+                    let start = request.find("prompt-").unwrap() + 7;
+                    let prompt = request[start..]
+                        .chars()
+                        .take_while(char::is_ascii_digit)
+                        .collect::<String>()
+                        .parse::<u64>()
+                        .unwrap();
+
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50 - 5 * prompt)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    TestResponse::json(json!({
+                        "id": "abc123",
+                        "message": {"role": "assistant", "content": [{"type": "text", "text": format!("answer-{prompt}")}]},
+                        "finish_reason": "COMPLETE"
+                    }))
+                }
+            })
+            .await
+        };
+
+        let model = super::super::ClientBuilder::new("dummy-key")
+            .base_url(&server.base_url)
+            .build()
+            .completion_model(super::super::COMMAND_R)
+            .with_batch_concurrency(3);
+        let requests = (0..10)
+            .map(|i| {
+                completion::CompletionModel::completion_request(&model, format!("prompt-{i}"))
+                    .build()
+            })
+            .collect();
+
+        let answers = model
+            .completion_batch(requests)
+            .await
+            .into_iter()
+            .map(|response| match response.unwrap().choice.first() {
+                completion::AssistantContent::Text(text) => text.text,
+                _ => panic!("Expected a text response"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            answers,
+            (0..10).map(|i| format!("answer-{i}")).collect::<Vec<_>>()
+        );
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 3);
+        assert!(max_in_flight.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    async fn test_completions_concurrency() {
+        use super::super::testing::{TestResponse, TestServer};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+            TestServer::serve_with(move |_| {
+                let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+                async move {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    TestResponse::json(json!({
+                        "id": "abc123",
+                        "message": {"role": "assistant", "content": [{"type": "text", "text": "haiku"}]},
+                        "finish_reason": "COMPLETE"
+                    }))
+                }
+            })
+            .await
+        };
+
+        let model = super::super::ClientBuilder::new("dummy-key")
+            .base_url(&server.base_url)
+            .build()
+            .completion_model(super::super::COMMAND_R)
+            .with_batch_concurrency(2);
+        let request =
+            completion::CompletionModel::completion_request(&model, "Write a haiku").build();
+
+        let candidates = model.completions(request, 8).await.unwrap();
+
+        assert_eq!(candidates.len(), 8);
+        assert_eq!(server.requests().len(), 8);
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn test_candidate_requests() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+
+        let request = model.completion_request("Write a haiku").build();
+        let requests = candidate_requests(&request, 3);
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|candidate| candidate.seed.is_none()));
+
+        let request = model.completion_request("Write a haiku").seed(42).build();
+        assert_eq!(
+            candidate_requests(&request, 3)
+                .iter()
+                .map(|candidate| candidate.seed)
+                .collect::<Vec<_>>(),
+            vec![Some(42), Some(43), Some(44)]
+        );
+    }
+
+    #[test]
+    fn test_document_limits() {
+        use completion::CompletionModel as _;
+
+        let documents = (0..3)
+            .map(|i| completion::Document {
+                id: format!("doc_{i}"),
+                text: "penguin ".repeat(i + 1),
+                additional_props: HashMap::new(),
+            })
+            .collect::<Vec<_>>();
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let request = model
+            .completion_request("Which penguins are the tallest?")
+            .documents(documents)
+            .build();
+
+        let limits = DocumentLimits::new(2).max_document_tokens(3);
+        let error = model
+            .clone()
+            .with_document_limits(limits)
+            .build_request(&request)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "RequestError: The request has 3 documents, more than the limit of 2 documents"
+        );
+
+        let error = model
+            .clone()
+            .with_document_limits(DocumentLimits::new(3).max_document_tokens(3))
+            .build_request(&request)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "RequestError: Document doc_1 is about 4 tokens, more than the limit of 3 tokens per document"
+        );
+
+        let body = model
+            .with_document_limits(limits.overflow(DocumentOverflow::Fit))
+            .build_request(&request)
+            .unwrap();
+        assert_eq!(
+            body["documents"],
+            json!([
+                {"id": "doc_0", "data": {"text": "penguin "}},
+                {"id": "doc_1", "data": {"text": "penguin peng"}},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_documents_in_request() {
+        use completion::CompletionModel as _;
+
+        let model = Client::new("dummy-key").completion_model(super::super::COMMAND_R);
+        let document = Document::new("doc_0")
+            .with_field("title", "Tall penguins")
+            .with_field("snippet", "Emperor penguins are the tallest.")
+            .with_field("url", "https://example.com/penguins");
+
+        let request = model
+            .completion_request("Which penguins are the tallest?")
+            .document(document.clone().into())
+            .build();
+        let request = model.build_request(&request).unwrap();
+
+        assert_eq!(
+            request["documents"],
+            json!([{
+                "id": "doc_0",
+                "data": {
+                    "title": "Tall penguins",
+                    "snippet": "Emperor penguins are the tallest.",
+                    "url": "https://example.com/penguins"
+                }
+            }])
+        );
+        assert_eq!(
+            Document::from(completion::Document::from(
+                document.with_field("text", "Emperor penguins are the tallest.")
+            ))
+            .data["text"],
+            "Emperor penguins are the tallest."
+        );
+    }
 }
@@ -1,8 +1,18 @@
-use super::{client::ApiResponse, Client};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
-use crate::embeddings::{self, EmbeddingError};
+pub use super::usage::BilledUnits;
+use super::{
+    cache::{cache_key, EmbeddingCache},
+    client::{ApiErrorResponse, ApiResponse},
+    Client, RetryPolicy,
+};
 
-use serde::Deserialize;
+use crate::{
+    embeddings::{self, DocumentEmbedder, EmbeddingError, QueryEmbedder},
+    message::{ImageMediaType, MimeType},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
 
 #[derive(Deserialize)]
@@ -16,7 +26,7 @@ pub struct EmbeddingResponse {
     pub meta: Option<Meta>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug)]
 pub struct Meta {
     pub api_version: ApiVersion,
     pub billed_units: BilledUnits,
@@ -24,7 +34,7 @@ pub struct Meta {
     pub warnings: Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug)]
 pub struct ApiVersion {
     pub version: String,
     #[serde(default)]
@@ -33,34 +43,504 @@ pub struct ApiVersion {
     pub is_experimental: Option<bool>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct BilledUnits {
-    #[serde(default)]
-    pub input_tokens: u32,
-    #[serde(default)]
-    pub output_tokens: u32,
-    #[serde(default)]
-    pub search_units: u32,
-    #[serde(default)]
-    pub classifications: u32,
+/// Type of input passed to the Cohere embedding models.
+///
+/// Documents that will be searched over should be embedded with [InputType::SearchDocument],
+/// while the queries used to search them should be embedded with [InputType::SearchQuery].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputType {
+    /// `search_document`: documents stored in a vector database for search use-cases
+    SearchDocument,
+    /// `search_query`: search queries run against a vector database
+    SearchQuery,
+    /// `classification`: embeddings passed through a text classifier
+    Classification,
+    /// `clustering`: embeddings run through a clustering algorithm
+    Clustering,
+    /// Any other input type supported by the Cohere API
+    Other(String),
 }
 
-impl std::fmt::Display for BilledUnits {
+/// Alias of [InputType]. The enum is named like the other types of the provider (e.g.:
+/// [EmbeddingModel] rather than `CohereEmbeddingModel`), the `cohere` module already naming
+/// the provider.
+pub type CohereInputType = InputType;
+
+impl InputType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            InputType::SearchDocument => "search_document",
+            InputType::SearchQuery => "search_query",
+            InputType::Classification => "classification",
+            InputType::Clustering => "clustering",
+            InputType::Other(input_type) => input_type,
+        }
+    }
+}
+
+impl std::fmt::Display for InputType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Input tokens: {}\nOutput tokens: {}\nSearch units: {}\nClassifications: {}",
-            self.input_tokens, self.output_tokens, self.search_units, self.classifications
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for InputType {
+    fn from(input_type: &str) -> Self {
+        match input_type {
+            "search_document" => InputType::SearchDocument,
+            "search_query" => InputType::SearchQuery,
+            "classification" => InputType::Classification,
+            "clustering" => InputType::Clustering,
+            other => InputType::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for InputType {
+    fn from(input_type: String) -> Self {
+        input_type.as_str().into()
+    }
+}
+
+impl Serialize for InputType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Response to a request with the `embedding_types` parameter set
+#[derive(Deserialize)]
+struct EmbeddingsByTypeResponse {
+    embeddings: EmbeddingsByType,
+    #[serde(default)]
+    meta: Option<Meta>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsByType {
+    float: Vec<Vec<f32>>,
+}
+
+/// Check that no document is empty or whitespace-only, as the Cohere API may reject or drop them.
+fn check_documents(documents: &[String]) -> Result<(), EmbeddingError> {
+    match documents
+        .iter()
+        .position(|document| document.trim().is_empty())
+    {
+        Some(index) => Err(EmbeddingError::DocumentError(Box::new(
+            embeddings::InvalidDocumentError {
+                index,
+                reason: "is empty or whitespace-only".to_string(),
+            },
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Maximum size (in bytes, once decoded) of an image embedded by the Cohere API
+pub const MAX_IMAGE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Image embedded by [EmbeddingModel::embed_images].
+/// The Cohere API supports JPEG, PNG, WEBP and GIF images of up to [MAX_IMAGE_SIZE] bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageInput {
+    /// Base64 encoded image
+    pub data: String,
+    pub media_type: ImageMediaType,
+}
+
+impl ImageInput {
+    /// Create an image input from base64 encoded data.
+    pub fn new(data: impl Into<String>, media_type: ImageMediaType) -> Self {
+        Self {
+            data: data.into(),
+            media_type,
+        }
+    }
+
+    /// Create an image input from raw bytes, e.g.: the content of an image file.
+    pub fn from_bytes(bytes: &[u8], media_type: ImageMediaType) -> Self {
+        use base64::Engine;
+
+        Self::new(
+            base64::engine::general_purpose::STANDARD.encode(bytes),
+            media_type,
+        )
+    }
+
+    /// Size of the decoded image, in bytes.
+    pub fn size(&self) -> usize {
+        let padding = self.data.bytes().rev().take_while(|b| *b == b'=').count();
+        (self.data.len() * 3 / 4).saturating_sub(padding)
+    }
+
+    /// Data URI of the image, as sent to the Cohere API.
+    pub fn data_uri(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.media_type.to_mime_type(),
+            self.data
         )
     }
 }
 
+/// Check that every image has a media type supported by the Cohere API, and fits in its size limit.
+fn check_images(images: &[ImageInput]) -> Result<(), EmbeddingError> {
+    images.iter().enumerate().try_for_each(|(i, image)| {
+        if !matches!(
+            image.media_type,
+            ImageMediaType::JPEG | ImageMediaType::PNG | ImageMediaType::WEBP | ImageMediaType::GIF
+        ) {
+            return Err(EmbeddingError::DocumentError(
+                format!(
+                    "Image at index {i} has an unsupported media type: {}",
+                    image.media_type.to_mime_type()
+                )
+                .into(),
+            ));
+        }
+
+        let size = image.size();
+        if size > MAX_IMAGE_SIZE {
+            return Err(EmbeddingError::DocumentError(
+                format!(
+                    "Image at index {i} is {size} bytes, larger than the limit of {MAX_IMAGE_SIZE} bytes"
+                )
+                .into(),
+            ));
+        }
+
+        Ok(())
+    })
+}
+
+/// Log the billed units of an embed response, and its warnings (e.g.: deprecated model or
+/// truncated inputs) at the warn level.
+fn log_meta(meta: Option<&Meta>) {
+    match meta {
+        Some(meta) => {
+            tracing::info!(target: "rig",
+                "Cohere embeddings billed units: {}",
+                meta.billed_units,
+            );
+            if meta.api_version.is_deprecated == Some(true) {
+                tracing::warn!(target: "rig",
+                    "Cohere API version {} is deprecated",
+                    meta.api_version.version,
+                );
+            }
+            meta.warnings.iter().for_each(
+                |warning| tracing::warn!(target: "rig", "Cohere embeddings warning: {}", warning),
+            );
+        }
+        None => tracing::info!(target: "rig",
+            "Cohere embeddings billed units: n/a",
+        ),
+    };
+}
+
+/// How the Cohere API handles inputs longer than the maximum token length of the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Truncate {
+    /// Return an error when an input exceeds the maximum token length
+    None,
+    /// Discard the start of the input until it fits
+    Start,
+    /// Discard the end of the input until it fits
+    End,
+}
+
 #[derive(Clone)]
 pub struct EmbeddingModel {
     client: Client,
     pub model: String,
-    pub input_type: String,
+    pub input_type: InputType,
+    /// Truncation strategy. If not set, the Cohere API default is used (i.e.: `END`).
+    pub truncate: Option<Truncate>,
     ndims: usize,
+    batch_size: Option<usize>,
+    deduplicate: bool,
+    cache: Option<Arc<dyn EmbeddingCache>>,
+}
+
+/// Builder for an [EmbeddingModel], configuring it without changing the configuration of the
+/// [Client] it was created from.
+/// Single precision embeddings can be requested from the built model with
+/// [EmbeddingModel::embed_texts_f32].
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use rig::providers::cohere::{self, EmbeddingModel, RetryPolicy, Truncate};
+///
+/// let client = cohere::Client::new("YOUR_API_KEY");
+/// let model = EmbeddingModel::builder(client, cohere::EMBED_ENGLISH_V3)
+///     .input_type("search_document")
+///     .truncate(Truncate::End)
+///     .batch_size(16)
+///     .deduplicate(true)
+///     .retry_policy(RetryPolicy::default().max_attempts(5))
+///     .build();
+/// ```
+pub struct EmbeddingModelBuilder {
+    client: Client,
+    model: String,
+    input_type: InputType,
+    truncate: Option<Truncate>,
+    ndims: Option<usize>,
+    batch_size: Option<usize>,
+    deduplicate: bool,
+    cache: Option<Arc<dyn EmbeddingCache>>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl EmbeddingModelBuilder {
+    /// Create a builder for the embedding model `model`, embedding documents with the
+    /// [InputType::SearchDocument] input type by default.
+    pub fn new(client: Client, model: &str) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            input_type: InputType::SearchDocument,
+            truncate: None,
+            ndims: None,
+            batch_size: None,
+            deduplicate: false,
+            cache: None,
+            retry_policy: None,
+        }
+    }
+
+    pub fn input_type(mut self, input_type: impl Into<InputType>) -> Self {
+        self.input_type = input_type.into();
+        self
+    }
+
+    /// Set how inputs longer than the maximum token length of the model are handled.
+    pub fn truncate(mut self, truncate: Truncate) -> Self {
+        self.truncate = Some(truncate);
+        self
+    }
+
+    /// Set the number of dimensions of the embeddings.
+    /// Defaults to the dimensions of the known Cohere models, 0 otherwise.
+    pub fn ndims(mut self, ndims: usize) -> Self {
+        self.ndims = Some(ndims);
+        self
+    }
+
+    /// Set the maximum number of documents embedded per request, between 1 and the limit of
+    /// the Cohere API (96). Larger inputs are split into several requests.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size.clamp(
+            1,
+            <EmbeddingModel as embeddings::EmbeddingModel>::MAX_DOCUMENTS,
+        ));
+        self
+    }
+
+    /// Embed identical documents only once, copying their embedding to every position of the
+    /// duplicates (e.g.: to save the cost of boilerplate in scraped data). Disabled by default.
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Look up the embeddings of the texts in `cache` before embedding them, only sending the
+    /// texts missing from the cache to the Cohere API, and cache the new embeddings
+    /// (see [super::cache]).
+    pub fn cache(mut self, cache: impl EmbeddingCache + 'static) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Set the policy used to retry the requests of this model, instead of the policy of the client.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn build(self) -> EmbeddingModel {
+        let client = match self.retry_policy {
+            Some(retry_policy) => self.client.with_retry_policy(retry_policy),
+            None => self.client,
+        };
+        let ndims = self.ndims.unwrap_or_else(|| known_ndims(&self.model));
+
+        EmbeddingModel {
+            client,
+            model: self.model,
+            input_type: self.input_type,
+            truncate: self.truncate,
+            ndims,
+            batch_size: self.batch_size,
+            deduplicate: self.deduplicate,
+            cache: self.cache,
+        }
+    }
+}
+
+/// Unique documents of a list of documents, with the position of each document of the list in the
+/// unique documents.
+struct UniqueDocuments {
+    documents: Vec<String>,
+    positions: Vec<usize>,
+}
+
+impl UniqueDocuments {
+    fn new(documents: &[String]) -> Self {
+        let mut indices = HashMap::new();
+        let mut unique = vec![];
+        let positions = documents
+            .iter()
+            .map(|document| {
+                *indices.entry(document.as_str()).or_insert_with(|| {
+                    unique.push(document.clone());
+                    unique.len() - 1
+                })
+            })
+            .collect();
+
+        Self {
+            documents: unique,
+            positions,
+        }
+    }
+
+    /// Embeddings of the documents of the list, from the embeddings of the unique documents.
+    fn fan_out<T: Clone>(&self, embeddings: Vec<T>) -> Vec<T> {
+        self.positions
+            .iter()
+            .map(|&position| embeddings[position].clone())
+            .collect()
+    }
+}
+
+/// Embedding model for the documents searched over in a RAG setup, i.e.: embedding with the
+/// [InputType::SearchDocument] input type.
+///
+/// Unlike an [EmbeddingModel], whose input type is only known at runtime, the input type is part
+/// of the type, so that documents and queries cannot be mixed up: the model only implements
+/// [DocumentEmbedder], so it is accepted by [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder)
+/// but not by the vector store indexes, which are searched with the matching
+/// [QueryEmbeddingModel] given by [DocumentEmbeddingModel::query_model].
+///
+/// # Example
+/// ```
+/// use rig::{
+///     embeddings::EmbeddingsBuilder,
+///     providers::cohere,
+///     vector_store::in_memory_store::{InMemoryVectorIndex, InMemoryVectorStore},
+/// };
+///
+/// # async fn build_index() -> Result<InMemoryVectorIndex<cohere::EmbeddingModel, String>, Box<dyn std::error::Error>> {
+/// let client = cohere::Client::new("YOUR_API_KEY");
+/// let documents_model = client.document_embedding_model(cohere::EMBED_ENGLISH_V3);
+///
+/// let embeddings = EmbeddingsBuilder::new(documents_model.clone())
+///     .document("A flurbo is a green alien".to_string())?
+///     .build()
+///     .await?;
+/// let index = InMemoryVectorStore::from_documents(embeddings).index(documents_model.query_model());
+/// # Ok(index)
+/// # }
+/// ```
+///
+/// Searching the index with the model embedding the documents does not compile:
+/// ```compile_fail
+/// use rig::{providers::cohere, vector_store::in_memory_store::InMemoryVectorStore};
+///
+/// let client = cohere::Client::new("YOUR_API_KEY");
+/// let documents_model = client.document_embedding_model(cohere::EMBED_ENGLISH_V3);
+///
+/// let index = InMemoryVectorStore::<String>::default().index(documents_model);
+/// ```
+#[derive(Clone)]
+pub struct DocumentEmbeddingModel(EmbeddingModel);
+
+/// Embedding model for the queries run against documents embedded with a
+/// [DocumentEmbeddingModel], i.e.: embedding with the [InputType::SearchQuery] input type.
+///
+/// The model only implements [QueryEmbedder], so it is accepted by the vector store indexes but
+/// not by [EmbeddingsBuilder](crate::embeddings::EmbeddingsBuilder).
+#[derive(Clone)]
+pub struct QueryEmbeddingModel(EmbeddingModel);
+
+impl DocumentEmbeddingModel {
+    /// Embed documents with `model` (e.g.: configured with [EmbeddingModel::builder]), whatever
+    /// its input type.
+    pub fn from_model(model: EmbeddingModel) -> Self {
+        Self(EmbeddingModel {
+            input_type: InputType::SearchDocument,
+            ..model
+        })
+    }
+
+    /// Model embedding the queries run against the documents embedded by this model, with the
+    /// same Cohere model and configuration.
+    pub fn query_model(&self) -> QueryEmbeddingModel {
+        QueryEmbeddingModel::from_model(self.0.clone())
+    }
+
+    /// The underlying embedding model.
+    pub fn inner(&self) -> &EmbeddingModel {
+        &self.0
+    }
+}
+
+impl QueryEmbeddingModel {
+    /// Embed queries with `model` (e.g.: configured with [EmbeddingModel::builder]), whatever
+    /// its input type.
+    pub fn from_model(model: EmbeddingModel) -> Self {
+        Self(EmbeddingModel {
+            input_type: InputType::SearchQuery,
+            ..model
+        })
+    }
+
+    /// Model embedding the documents searched by the queries embedded by this model, with the
+    /// same Cohere model and configuration.
+    pub fn document_model(&self) -> DocumentEmbeddingModel {
+        DocumentEmbeddingModel::from_model(self.0.clone())
+    }
+
+    /// The underlying embedding model.
+    pub fn inner(&self) -> &EmbeddingModel {
+        &self.0
+    }
+}
+
+impl DocumentEmbedder for DocumentEmbeddingModel {
+    type Model = EmbeddingModel;
+
+    fn into_document_model(self) -> EmbeddingModel {
+        self.0
+    }
+}
+
+impl QueryEmbedder for QueryEmbeddingModel {
+    type Model = EmbeddingModel;
+
+    fn into_query_model(self) -> EmbeddingModel {
+        self.0
+    }
+}
+
+/// Number of dimensions of the embeddings of the known Cohere models, 0 otherwise.
+pub(crate) fn known_ndims(model: &str) -> usize {
+    match model {
+        super::EMBED_ENGLISH_V3 | super::EMBED_MULTILINGUAL_V3 | super::EMBED_ENGLISH_LIGHT_V2 => {
+            1024
+        }
+        super::EMBED_ENGLISH_LIGHT_V3 | super::EMBED_MULTILINGUAL_LIGHT_V3 => 384,
+        super::EMBED_ENGLISH_V2 => 4096,
+        super::EMBED_MULTILINGUAL_V2 => 768,
+        _ => 0,
+    }
 }
 
 impl embeddings::EmbeddingModel for EmbeddingModel {
@@ -75,68 +555,904 @@ impl embeddings::EmbeddingModel for EmbeddingModel {
         &self,
         documents: impl IntoIterator<Item = String>,
     ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        let (embeddings, _) = self.embed_texts_with_warnings(documents).await?;
+        Ok(embeddings)
+    }
+}
+
+impl EmbeddingModel {
+    pub fn new(
+        client: Client,
+        model: &str,
+        input_type: impl Into<InputType>,
+        ndims: usize,
+    ) -> Self {
+        Self {
+            client,
+            model: model.to_string(),
+            input_type: input_type.into(),
+            truncate: None,
+            ndims,
+            batch_size: None,
+            deduplicate: false,
+            cache: None,
+        }
+    }
+
+    /// Create a builder for an embedding model, to configure it beyond [EmbeddingModel::new]
+    /// (e.g.: batch size and retries).
+    pub fn builder(client: Client, model: &str) -> EmbeddingModelBuilder {
+        EmbeddingModelBuilder::new(client, model)
+    }
+
+    /// Maximum number of documents embedded per request.
+    fn batch_size(&self) -> usize {
+        self.batch_size
+            .unwrap_or(<Self as embeddings::EmbeddingModel>::MAX_DOCUMENTS)
+    }
+
+    /// Embed a single search query, e.g.: to look up documents embedded with
+    /// [InputType::SearchDocument] in a RAG setup. The query is embedded with the
+    /// [InputType::SearchQuery] input type, whatever the input type of the model.
+    pub async fn embed_query(&self, text: &str) -> Result<embeddings::Embedding, EmbeddingError> {
+        use embeddings::EmbeddingModel as _;
+
+        let mut embeddings = self.query_model().embed_texts([text.to_string()]).await?;
+        embeddings.pop().ok_or_else(|| {
+            EmbeddingError::ResponseError("No embedding returned for the query".to_string())
+        })
+    }
+
+    fn query_model(&self) -> Self {
+        Self {
+            input_type: InputType::SearchQuery,
+            ..self.clone()
+        }
+    }
+
+    /// Authenticate the requests of this model with `api_key` instead of the client's API key,
+    /// sharing the client's connection pool (see [Client::with_api_key]).
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.client = self.client.with_api_key(api_key);
+        self
+    }
+
+    /// Set how inputs longer than the maximum token length of the model are handled.
+    pub fn with_truncate(mut self, truncate: Truncate) -> Self {
+        self.truncate = Some(truncate);
+        self
+    }
+
+    /// Embed identical documents only once (see [EmbeddingModelBuilder::deduplicate]).
+    pub fn with_deduplication(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Cache the embeddings of this model in `cache` (see [EmbeddingModelBuilder::cache]).
+    pub fn with_cache(mut self, cache: Arc<dyn EmbeddingCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Embeddings of `inputs` found in the cache of the model (if any), and the inputs missing
+    /// from the cache, to embed.
+    fn cached_embeddings<'a>(
+        &self,
+        inputs: &'a [String],
+    ) -> (Vec<Option<Vec<f64>>>, Cow<'a, [String]>) {
+        let Some(cache) = &self.cache else {
+            return (vec![None; inputs.len()], Cow::Borrowed(inputs));
+        };
+
+        let cached = inputs
+            .iter()
+            .map(|input| cache.get(&cache_key(&self.model, &self.input_type, input)))
+            .collect::<Vec<_>>();
+        let misses = inputs
+            .iter()
+            .zip(&cached)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(input, _)| input.clone())
+            .collect::<Vec<_>>();
+
+        tracing::debug!(
+            target: "rig",
+            "Found {} embeddings out of {} in the cache",
+            inputs.len() - misses.len(),
+            inputs.len()
+        );
+        (cached, Cow::Owned(misses))
+    }
+
+    /// Cache the embeddings of the inputs missing from the cache, and merge them with the cached
+    /// embeddings, in the order of the inputs.
+    fn merge_cached_embeddings(
+        &self,
+        cached: Vec<Option<Vec<f64>>>,
+        misses: &[String],
+        embeddings: Vec<Vec<f64>>,
+    ) -> Result<Vec<Vec<f64>>, EmbeddingError> {
+        if let Some(cache) = &self.cache {
+            for (input, embedding) in misses.iter().zip(&embeddings) {
+                cache.insert(&cache_key(&self.model, &self.input_type, input), embedding);
+            }
+        }
+
+        let mut embeddings = embeddings.into_iter();
+        cached
+            .into_iter()
+            .map(|cached| cached.or_else(|| embeddings.next()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                EmbeddingError::ResponseError("Missing embeddings for cache misses".to_string())
+            })
+    }
+
+    /// Unique documents to embed, if deduplication is enabled and `documents` has duplicates.
+    fn unique_documents(&self, documents: &[String]) -> Option<UniqueDocuments> {
+        if !self.deduplicate {
+            return None;
+        }
+
+        let unique = UniqueDocuments::new(documents);
+        if unique.documents.len() == documents.len() {
+            return None;
+        }
+
+        tracing::debug!(
+            target: "rig",
+            "Embedding {} unique documents out of {}",
+            unique.documents.len(),
+            documents.len()
+        );
+        Some(unique)
+    }
+
+    fn create_embed_request(&self, documents: &[String]) -> serde_json::Value {
+        let mut request = json!({
+            "model": self.model,
+            "texts": documents,
+            "input_type": self.input_type,
+        });
+
+        if let Some(truncate) = self.truncate {
+            request["truncate"] = json!(truncate);
+        }
+
+        request
+    }
+
+    /// Embed `documents` like [embed_texts](embeddings::EmbeddingModel::embed_texts), also
+    /// returning the warnings of the Cohere API (e.g.: deprecated model or truncated inputs).
+    /// Warnings are also logged at the warn level.
+    pub async fn embed_texts_with_warnings(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<(Vec<embeddings::Embedding>, Vec<String>), EmbeddingError> {
+        let (embeddings, warnings, _) = self.embed_texts_with_meta(documents).await?;
+        Ok((embeddings, warnings))
+    }
+
+    /// Embed `documents` like [embed_texts](embeddings::EmbeddingModel::embed_texts), also
+    /// returning the units billed for all the requests sent (none for the documents found in
+    /// the [cache](EmbeddingModelBuilder::cache)).
+    pub async fn embed_texts_with_usage(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<(Vec<embeddings::Embedding>, BilledUnits), EmbeddingError> {
+        let (embeddings, _, billed_units) = self.embed_texts_with_meta(documents).await?;
+        Ok((embeddings, billed_units))
+    }
+
+    /// Embed `documents`, returning the embeddings with the warnings and billed units of the
+    /// requests sent.
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_embed",
+        skip_all,
+        fields(
+            model = %self.model,
+            input_type = %self.input_type,
+            documents = tracing::field::Empty,
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
+    async fn embed_texts_with_meta(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<(Vec<embeddings::Embedding>, Vec<String>, BilledUnits), EmbeddingError> {
         let documents = documents.into_iter().collect::<Vec<_>>();
+        tracing::Span::current().record("documents", documents.len());
+        check_documents(&documents)?;
+
+        let unique = self.unique_documents(&documents);
+        let inputs = unique
+            .as_ref()
+            .map_or(&documents[..], |unique| &unique.documents[..]);
+
+        let (cached, misses) = self.cached_embeddings(inputs);
+
+        let mut embeddings = Vec::with_capacity(misses.len());
+        let mut warnings = vec![];
+        let mut billed_units = BilledUnits::default();
+        for batch in misses.chunks(self.batch_size()) {
+            let response: EmbeddingResponse = self
+                .send_embed_request(&self.create_embed_request(batch))
+                .await?;
+            log_meta(response.meta.as_ref());
+            self.check_embeddings(batch.len(), &response.embeddings)?;
+            embeddings.extend(response.embeddings);
+            if let Some(meta) = response.meta {
+                billed_units += meta.billed_units;
+                warnings.extend(meta.warnings);
+            }
+        }
+        let mut embeddings = self.merge_cached_embeddings(cached, &misses, embeddings)?;
+        if let Some(unique) = unique {
+            embeddings = unique.fan_out(embeddings);
+        }
+
+        let embeddings = embeddings
+            .into_iter()
+            .zip(documents.into_iter())
+            .map(|(embedding, document)| embeddings::Embedding {
+                document,
+                vec: embedding,
+            })
+            .collect();
 
+        Ok((embeddings, warnings, billed_units))
+    }
+
+    /// Embed `documents` as single precision vectors, halving the memory taken by the embeddings.
+    /// The embeddings are requested with the `float` embedding type and deserialized directly
+    /// into `f32`. The [cache](EmbeddingModelBuilder::cache) of the model, holding double
+    /// precision embeddings, is not used.
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_embed",
+        skip_all,
+        fields(
+            model = %self.model,
+            input_type = %self.input_type,
+            documents = tracing::field::Empty,
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
+    pub async fn embed_texts_f32(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<Vec<embeddings::EmbeddingF32>, EmbeddingError> {
+        let documents = documents.into_iter().collect::<Vec<_>>();
+        tracing::Span::current().record("documents", documents.len());
+        check_documents(&documents)?;
+
+        let unique = self.unique_documents(&documents);
+        let inputs = unique
+            .as_ref()
+            .map_or(&documents[..], |unique| &unique.documents[..]);
+
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(self.batch_size()) {
+            let mut request = self.create_embed_request(batch);
+            request["embedding_types"] = json!(["float"]);
+
+            let response: EmbeddingsByTypeResponse = self.send_embed_request(&request).await?;
+            log_meta(response.meta.as_ref());
+            self.check_embeddings(batch.len(), &response.embeddings.float)?;
+            embeddings.extend(response.embeddings.float);
+        }
+        if let Some(unique) = unique {
+            embeddings = unique.fan_out(embeddings);
+        }
+
+        Ok(embeddings
+            .into_iter()
+            .zip(documents.into_iter())
+            .map(|(embedding, document)| embeddings::EmbeddingF32 {
+                document,
+                vec: embedding,
+            })
+            .collect())
+    }
+
+    /// Embed `images` in the same vector space as the texts embedded by the model (requires a
+    /// multimodal model, e.g.: [EMBED_ENGLISH_V3](super::EMBED_ENGLISH_V3)). The images are
+    /// embedded with the `image` input type, one image per request as required by the Cohere API.
+    ///
+    /// The `document` of each embedding is the data URI of its image.
+    /// Returns a [EmbeddingError::DocumentError] if an image is too large or has an unsupported
+    /// media type, before sending any request.
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_embed",
+        skip_all,
+        fields(
+            model = %self.model,
+            input_type = "image",
+            documents = tracing::field::Empty,
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
+    pub async fn embed_images(
+        &self,
+        images: Vec<ImageInput>,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        tracing::Span::current().record("documents", images.len());
+        check_images(&images)?;
+
+        let mut embeddings = Vec::with_capacity(images.len());
+        for image in images {
+            let data_uri = image.data_uri();
+            let response: EmbeddingsByTypeResponse = self
+                .send_embed_request(&self.create_image_embed_request(&data_uri))
+                .await?;
+            log_meta(response.meta.as_ref());
+            self.check_embeddings(1, &response.embeddings.float)?;
+
+            embeddings.extend(response.embeddings.float.into_iter().map(|embedding| {
+                embeddings::Embedding {
+                    document: data_uri.clone(),
+                    vec: embedding.into_iter().map(f64::from).collect(),
+                }
+            }));
+        }
+
+        Ok(embeddings)
+    }
+
+    fn create_image_embed_request(&self, data_uri: &str) -> serde_json::Value {
+        json!({
+            "model": self.model,
+            "images": [data_uri],
+            "input_type": "image",
+            "embedding_types": ["float"],
+        })
+    }
+
+    async fn send_embed_request<T: DeserializeOwned>(
+        &self,
+        request: &serde_json::Value,
+    ) -> Result<T, EmbeddingError> {
         let response = self
             .client
-            .post("/v1/embed")
-            .json(&json!({
-                "model": self.model,
-                "texts": documents,
-                "input_type": self.input_type,
-            }))
-            .send()
+            .send(self.client.post(self.client.embed_path()).json(request))
             .await?;
 
         if response.status().is_success() {
-            match response.json::<ApiResponse<EmbeddingResponse>>().await? {
-                ApiResponse::Ok(response) => {
-                    match response.meta {
-                        Some(meta) => tracing::info!(target: "rig",
-                            "Cohere embeddings billed units: {}",
-                            meta.billed_units,
-                        ),
-                        None => tracing::info!(target: "rig",
-                            "Cohere embeddings billed units: n/a",
-                        ),
-                    };
-
-                    if response.embeddings.len() != documents.len() {
-                        return Err(EmbeddingError::DocumentError(
-                            format!(
-                                "Expected {} embeddings, got {}",
-                                documents.len(),
-                                response.embeddings.len()
-                            )
-                            .into(),
-                        ));
-                    }
-
-                    Ok(response
-                        .embeddings
-                        .into_iter()
-                        .zip(documents.into_iter())
-                        .map(|(embedding, document)| embeddings::Embedding {
-                            document,
-                            vec: embedding,
-                        })
-                        .collect())
-                }
+            // Read the body first, so that deserialization errors are reported as
+            // `JsonError` instead of `HttpError`
+            match serde_json::from_str::<ApiResponse<T>>(&response.text().await?)? {
+                ApiResponse::Ok(response) => Ok(response),
                 ApiResponse::Err(error) => Err(EmbeddingError::ProviderError(error.message)),
             }
         } else {
-            Err(EmbeddingError::ProviderError(response.text().await?))
+            let status = response.status();
+            let message = ApiErrorResponse::message_from_body(response.text().await?);
+            Err(EmbeddingError::ProviderError(format!(
+                "{status}: {message}"
+            )))
+        }
+    }
+
+    /// Check that there is one embedding per document, each with the expected number of dimensions.
+    fn check_embeddings<T>(
+        &self,
+        documents: usize,
+        embeddings: &[Vec<T>],
+    ) -> Result<(), EmbeddingError> {
+        if embeddings.len() != documents {
+            return Err(EmbeddingError::DocumentError(
+                format!(
+                    "Expected {} embeddings, got {}",
+                    documents,
+                    embeddings.len()
+                )
+                .into(),
+            ));
+        }
+
+        self.check_dimensions(embeddings)
+    }
+
+    /// Check that every embedding has the expected number of dimensions.
+    /// The check is skipped when the dimensions of the model are unknown (i.e.: `ndims` is 0).
+    fn check_dimensions<T>(&self, embeddings: &[Vec<T>]) -> Result<(), EmbeddingError> {
+        if self.ndims == 0 {
+            return Ok(());
+        }
+
+        match embeddings
+            .iter()
+            .enumerate()
+            .find(|(_, embedding)| embedding.len() != self.ndims)
+        {
+            Some((i, embedding)) => Err(EmbeddingError::ResponseError(format!(
+                "Embedding at index {i} has {} dimensions, expected {}",
+                embedding.len(),
+                self.ndims
+            ))),
+            None => Ok(()),
         }
     }
 }
 
-impl EmbeddingModel {
-    pub fn new(client: Client, model: &str, input_type: &str, ndims: usize) -> Self {
-        Self {
-            client,
-            model: model.to_string(),
-            input_type: input_type.to_string(),
-            ndims,
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        check_images, log_meta, DocumentEmbeddingModel, EmbeddingModel, EmbeddingResponse,
+        ImageInput, InputType, Truncate, UniqueDocuments, MAX_IMAGE_SIZE,
+    };
+    use crate::{message::ImageMediaType, providers::cohere::Client};
+
+    #[test]
+    fn test_input_type_from_str() {
+        assert_eq!(
+            InputType::from("search_document"),
+            InputType::SearchDocument
+        );
+        assert_eq!(InputType::from("search_query"), InputType::SearchQuery);
+        assert_eq!(
+            InputType::from("image"),
+            InputType::Other("image".to_string())
+        );
+    }
+
+    #[test]
+    fn test_input_type_serialization() {
+        assert_eq!(
+            serde_json::to_value(InputType::SearchQuery).unwrap(),
+            "search_query"
+        );
+        assert_eq!(
+            serde_json::to_value(InputType::Other("image".to_string())).unwrap(),
+            "image"
+        );
+        assert_eq!(InputType::Clustering.to_string(), "clustering");
+    }
+
+    #[tokio::test]
+    async fn test_json_and_http_errors_are_distinct() {
+        use crate::embeddings::{EmbeddingError, EmbeddingModel as _};
+        use crate::providers::cohere::{
+            testing::{TestResponse, TestServer},
+            RetryPolicy,
+        };
+
+        // Server answering with a response that does not match the schema of the embed endpoint
+        let server = TestServer::serve(vec![TestResponse::json(
+            r#"{"id": "1", "embeddings": "unexpected"}"#,
+        )])
+        .await;
+        let base_url = server.base_url;
+
+        let model = Client::from_url("dummy-key", &base_url)
+            .with_retry_policy(RetryPolicy::none())
+            .embedding_model(super::super::EMBED_ENGLISH_V3, "search_document");
+        let error = model
+            .embed_texts(vec!["hello".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, EmbeddingError::JsonError(_)), "{error}");
+
+        // Nothing listens on the port anymore
+        let model = Client::from_url("dummy-key", &base_url)
+            .with_retry_policy(RetryPolicy::none())
+            .embedding_model(super::super::EMBED_ENGLISH_V3, "search_document");
+        let error = model
+            .embed_texts(vec!["hello".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, EmbeddingError::HttpError(_)), "{error}");
+    }
+
+    #[test]
+    fn test_image_input() {
+        let image = ImageInput::from_bytes(b"image", ImageMediaType::PNG);
+        assert_eq!(image.data, "aW1hZ2U=");
+        assert_eq!(image.size(), 5);
+        assert_eq!(image.data_uri(), "data:image/png;base64,aW1hZ2U=");
+
+        let model = EmbeddingModel::new(
+            Client::new("dummy-key"),
+            super::super::EMBED_ENGLISH_V3,
+            "search_document",
+            1024,
+        );
+        assert_eq!(
+            model.create_image_embed_request(&image.data_uri()),
+            json!({
+                "model": "embed-english-v3.0",
+                "images": ["data:image/png;base64,aW1hZ2U="],
+                "input_type": "image",
+                "embedding_types": ["float"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_images() {
+        assert!(check_images(&[ImageInput::from_bytes(b"image", ImageMediaType::JPEG)]).is_ok());
+
+        let oversized = ImageInput::from_bytes(&vec![0; MAX_IMAGE_SIZE + 1], ImageMediaType::PNG);
+        let error = check_images(&[
+            ImageInput::from_bytes(b"image", ImageMediaType::PNG),
+            oversized,
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "DocumentError: Image at index 1 is {} bytes, larger than the limit of {MAX_IMAGE_SIZE} bytes",
+                MAX_IMAGE_SIZE + 1
+            )
+        );
+
+        let error =
+            check_images(&[ImageInput::from_bytes(b"image", ImageMediaType::SVG)]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "DocumentError: Image at index 0 has an unsupported media type: image/svg+xml"
+        );
+    }
+
+    #[test]
+    fn test_check_dimensions() {
+        let client = Client::new("dummy-key");
+
+        let model = EmbeddingModel::new(client.clone(), "embed-english-v3.0", "search_query", 3);
+        assert!(model
+            .check_dimensions(&[vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]])
+            .is_ok());
+
+        let error = model
+            .check_dimensions(&[vec![0.1, 0.2, 0.3], vec![0.4, 0.5]])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ResponseError: Embedding at index 1 has 2 dimensions, expected 3"
+        );
+
+        let model = EmbeddingModel::new(client, "custom-model", "search_query", 0);
+        assert!(model.check_dimensions(&[vec![0.1], vec![0.4, 0.5]]).is_ok());
+    }
+
+    #[test]
+    fn test_embed_request_truncate() {
+        let model = EmbeddingModel::new(
+            Client::new("dummy-key"),
+            "embed-english-v3.0",
+            "search_document",
+            1024,
+        );
+        let documents = vec!["hello".to_string()];
+
+        assert!(model
+            .create_embed_request(&documents)
+            .get("truncate")
+            .is_none());
+
+        assert_eq!(
+            model
+                .with_truncate(Truncate::End)
+                .create_embed_request(&documents),
+            json!({
+                "model": "embed-english-v3.0",
+                "texts": ["hello"],
+                "input_type": "search_document",
+                "truncate": "END",
+            })
+        );
+    }
+
+    #[test]
+    fn test_query_model_input_type() {
+        let model = EmbeddingModel::new(
+            Client::new("dummy-key"),
+            "embed-english-v3.0",
+            "search_document",
+            1024,
+        )
+        .with_truncate(Truncate::End);
+
+        assert_eq!(
+            model
+                .query_model()
+                .create_embed_request(&["what is a flurbo?".to_string()]),
+            json!({
+                "model": "embed-english-v3.0",
+                "texts": ["what is a flurbo?"],
+                "input_type": "search_query",
+                "truncate": "END",
+            })
+        );
+        assert_eq!(model.input_type, InputType::SearchDocument);
+    }
+
+    #[test]
+    fn test_typed_embedding_models() {
+        let model = EmbeddingModel::builder(Client::new("dummy-key"), "embed-english-v3.0")
+            .input_type("search_query")
+            .truncate(Truncate::Start)
+            .build();
+
+        let documents = DocumentEmbeddingModel::from_model(model);
+        assert_eq!(documents.inner().input_type, InputType::SearchDocument);
+
+        let queries = documents.query_model();
+        assert_eq!(queries.inner().input_type, InputType::SearchQuery);
+        assert_eq!(queries.inner().model, "embed-english-v3.0");
+        assert_eq!(queries.inner().truncate, Some(Truncate::Start));
+        assert_eq!(
+            queries.document_model().inner().input_type,
+            InputType::SearchDocument
+        );
+    }
+
+    #[test]
+    fn test_deserialize_meta_warnings() {
+        use std::sync::{Arc, Mutex};
+
+        /// Writer of the logs into a shared buffer
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let response: EmbeddingResponse = serde_json::from_value(json!({
+            "id": "abc123",
+            "embeddings": [[0.1, 0.2]],
+            "texts": ["hello"],
+            "meta": {
+                "api_version": { "version": "1", "is_deprecated": true },
+                "billed_units": { "input_tokens": 1 },
+                "warnings": ["embed-english-v2.0 is deprecated"]
+            }
+        }))
+        .unwrap();
+
+        let meta = response.meta.unwrap();
+        assert_eq!(meta.warnings, vec!["embed-english-v2.0 is deprecated"]);
+        assert_eq!(meta.api_version.is_deprecated, Some(true));
+
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || log_meta(Some(&meta)));
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let warnings = logs
+            .lines()
+            .filter(|line| line.contains("WARN"))
+            .collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 2, "{logs}");
+        assert!(warnings[0].contains("Cohere API version 1 is deprecated"));
+        assert!(warnings[1].contains("Cohere embeddings warning: embed-english-v2.0 is deprecated"));
+    }
+
+    #[test]
+    fn test_deserialize_embeddings_by_type_response() {
+        let json_data = r#"
+        {
+            "id": "da6e531f-54c6-4a73-bf92-f60566d8d753",
+            "response_type": "embeddings_by_type",
+            "embeddings": { "float": [[0.016296387, -0.008354187, -0.04699707]] },
+            "texts": ["hello"],
+            "meta": {
+                "api_version": { "version": "1" },
+                "billed_units": { "input_tokens": 1 }
+            }
         }
+        "#;
+
+        let response: super::EmbeddingsByTypeResponse = serde_json::from_str(json_data).unwrap();
+
+        assert_eq!(
+            response.embeddings.float,
+            vec![vec![0.016296387f32, -0.008354187, -0.04699707]]
+        );
+        assert_eq!(response.meta.unwrap().billed_units.input_tokens, 1);
+    }
+
+    #[test]
+    fn test_check_documents() {
+        assert!(super::check_documents(&["hello".to_string(), "world".to_string()]).is_ok());
+
+        let error = super::check_documents(&[
+            "hello".to_string(),
+            "world".to_string(),
+            " \n\t".to_string(),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "DocumentError: Document at index 2 is empty or whitespace-only"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_document_index_in_builder() {
+        use crate::embeddings::EmbeddingsBuilder;
+        use crate::providers::cohere::{
+            testing::{TestResponse, TestServer},
+            RetryPolicy,
+        };
+
+        // Server embedding the first batch of documents
+        let batch_size = <EmbeddingModel as crate::embeddings::EmbeddingModel>::MAX_DOCUMENTS;
+        let server = TestServer::serve(vec![TestResponse::json(json!({
+            "id": "1",
+            "embeddings": vec![[1.0, 0.0]; batch_size],
+            "texts": vec!["document"; batch_size],
+        }))])
+        .await;
+
+        let client =
+            Client::from_url("dummy-key", &server.base_url).with_retry_policy(RetryPolicy::none());
+        let model = EmbeddingModel::builder(client, "custom").ndims(2).build();
+        let mut documents = vec!["document".to_string(); 120];
+        documents[100] = " ".to_string();
+
+        let error = EmbeddingsBuilder::new(model)
+            .documents(documents)
+            .unwrap()
+            .max_concurrency(1)
+            .build()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "DocumentError: Document at index 100 is empty or whitespace-only"
+        );
+    }
+
+    #[test]
+    fn test_embedding_model_builder() {
+        let model = EmbeddingModel::builder(Client::new("dummy-key"), "embed-english-v3.0")
+            .truncate(Truncate::Start)
+            .batch_size(500)
+            .build();
+
+        assert_eq!(model.input_type, InputType::SearchDocument);
+        assert_eq!(model.truncate, Some(Truncate::Start));
+        assert_eq!(model.ndims, 1024);
+        assert_eq!(model.batch_size(), 96);
+
+        let model = EmbeddingModel::builder(Client::new("dummy-key"), "custom-model")
+            .input_type("search_query")
+            .ndims(256)
+            .batch_size(8)
+            .build();
+
+        assert_eq!(model.input_type, InputType::SearchQuery);
+        assert_eq!(model.ndims, 256);
+        assert_eq!(model.batch_size(), 8);
+    }
+
+    #[test]
+    fn test_unique_documents() {
+        let documents = ["a", "b", "a", "c", "b"].map(String::from);
+        let unique = UniqueDocuments::new(&documents);
+
+        assert_eq!(unique.documents, vec!["a", "b", "c"]);
+        assert_eq!(unique.fan_out(vec![1, 2, 3]), vec![1, 2, 1, 3, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_embed_deduplicated_documents() {
+        use crate::embeddings::EmbeddingModel as _;
+        use crate::providers::cohere::testing::{TestResponse, TestServer};
+
+        // Server embedding the texts "a" and "b"
+        let server = TestServer::serve(vec![TestResponse::json(
+            r#"{"id": "1", "embeddings": [[1.0, 0.0], [0.0, 1.0]], "texts": ["a", "b"]}"#,
+        )])
+        .await;
+
+        let model =
+            EmbeddingModel::builder(Client::from_url("dummy-key", &server.base_url), "custom")
+                .ndims(2)
+                .deduplicate(true)
+                .build();
+        let embeddings = model
+            .embed_texts(["a", "b", "a", "a"].map(String::from))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            embeddings
+                .iter()
+                .map(|embedding| (embedding.document.as_str(), embedding.vec.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a", vec![1.0, 0.0]),
+                ("b", vec![0.0, 1.0]),
+                ("a", vec![1.0, 0.0]),
+                ("a", vec![1.0, 0.0]),
+            ]
+        );
+        // Each distinct text is embedded once
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains(r#""texts":["a","b"]"#), "{requests:?}");
+    }
+
+    #[tokio::test]
+    async fn test_embed_cached_documents() {
+        use crate::embeddings::EmbeddingModel as _;
+        use crate::providers::cohere::cache::{EmbeddingCache, InMemoryEmbeddingCache};
+        use crate::providers::cohere::testing::{TestResponse, TestServer};
+        use std::sync::Arc;
+
+        // Server embedding "a" and "b", then "c"
+        let server = TestServer::serve(vec![
+            TestResponse::json(
+                r#"{"id": "1", "embeddings": [[1.0, 0.0], [0.0, 1.0]], "texts": ["a", "b"]}"#,
+            ),
+            TestResponse::json(r#"{"id": "2", "embeddings": [[1.0, 1.0]], "texts": ["c"]}"#),
+        ])
+        .await;
+
+        let cache = Arc::new(InMemoryEmbeddingCache::new());
+        let model =
+            EmbeddingModel::builder(Client::from_url("dummy-key", &server.base_url), "custom")
+                .ndims(2)
+                .build()
+                .with_cache(cache.clone());
+
+        model
+            .embed_texts(["a", "b"].map(String::from))
+            .await
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        let embeddings = model
+            .embed_texts(["b", "c", "a"].map(String::from))
+            .await
+            .unwrap();
+        assert_eq!(
+            embeddings
+                .iter()
+                .map(|embedding| (embedding.document.as_str(), embedding.vec.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("b", vec![0.0, 1.0]),
+                ("c", vec![1.0, 1.0]),
+                ("a", vec![1.0, 0.0]),
+            ]
+        );
+        assert_eq!(cache.len(), 3);
+        assert!(cache
+            .get(&super::cache_key("custom", &InputType::SearchQuery, "a"))
+            .is_none());
+
+        // Only the texts missing from the cache are embedded
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(r#""texts":["a","b"]"#), "{requests:?}");
+        assert!(requests[1].contains(r#""texts":["c"]"#), "{requests:?}");
     }
 }
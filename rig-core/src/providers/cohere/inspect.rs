@@ -0,0 +1,187 @@
+//! Hook to inspect the requests sent to the Cohere API and the responses it returns
+//! (e.g.: to log them after redacting sensitive data).
+//!
+//! The `Authorization` header is stripped before the request is passed to the inspector.
+//! No inspector is set by default, in which case requests and responses are not inspected.
+//!
+//! # Example
+//! ```
+//! use std::sync::Arc;
+//! use rig::providers::cohere::{self, InspectedRequest, InspectedResponse, RequestInspector};
+//!
+//! struct Logger;
+//!
+//! impl RequestInspector for Logger {
+//!     fn inspect_response(&self, request: &InspectedRequest, response: &InspectedResponse) {
+//!         println!(
+//!             "{} {} -> {}: {}",
+//!             request.method,
+//!             request.url,
+//!             response.status,
+//!             response.body_text().unwrap_or_default(),
+//!         );
+//!     }
+//! }
+//!
+//! let client = cohere::Client::new("YOUR_API_KEY").with_request_inspector(Arc::new(Logger));
+//! ```
+
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE},
+    Method, RequestBuilder, Response, StatusCode, Url,
+};
+
+use super::RetryPolicy;
+
+/// A request sent to the Cohere API, without its `Authorization` header.
+#[derive(Debug)]
+pub struct InspectedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// Serialized body of the request, if any
+    pub body: Option<Vec<u8>>,
+}
+
+impl InspectedRequest {
+    fn new(request: &reqwest::Request) -> Self {
+        let mut headers = request.headers().clone();
+        headers.remove(AUTHORIZATION);
+
+        Self {
+            method: request.method().clone(),
+            url: request.url().clone(),
+            headers,
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(<[u8]>::to_vec),
+        }
+    }
+
+    /// The body of the request as text, if any.
+    pub fn body_text(&self) -> Option<&str> {
+        self.body
+            .as_deref()
+            .and_then(|body| std::str::from_utf8(body).ok())
+    }
+}
+
+/// A response returned by the Cohere API.
+#[derive(Debug)]
+pub struct InspectedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// Body of the response. Not available for streamed responses, nor on `wasm32` targets.
+    pub body: Option<Vec<u8>>,
+}
+
+impl InspectedResponse {
+    /// The body of the response as text, if available.
+    pub fn body_text(&self) -> Option<&str> {
+        self.body
+            .as_deref()
+            .and_then(|body| std::str::from_utf8(body).ok())
+    }
+}
+
+/// Callbacks receiving the requests sent to the Cohere API and the responses it returns.
+/// Both methods do nothing by default.
+pub trait RequestInspector: Send + Sync {
+    /// Called before the request is sent (once, even if it is retried).
+    fn inspect_request(&self, _request: &InspectedRequest) {}
+
+    /// Called once the final response to the request is received.
+    fn inspect_response(&self, _request: &InspectedRequest, _response: &InspectedResponse) {}
+}
+
+/// Send the request according to the retry policy, passing the request and its response to
+/// the inspector.
+pub(crate) async fn send(
+    inspector: &dyn RequestInspector,
+    retry_policy: &RetryPolicy,
+    request: RequestBuilder,
+) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+
+    let inspected_request = InspectedRequest::new(&request);
+    inspector.inspect_request(&inspected_request);
+
+    let response = retry_policy
+        .send(RequestBuilder::from_parts(client, request))
+        .await?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+
+    let (body, response) = if is_event_stream(&headers) {
+        (None, response)
+    } else {
+        read_body(response).await?
+    };
+
+    inspector.inspect_response(
+        &inspected_request,
+        &InspectedResponse {
+            status,
+            headers,
+            body,
+        },
+    );
+
+    Ok(response)
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/event-stream"))
+}
+
+/// Read the body of the response, returning it along with an equivalent response.
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_body(response: Response) -> reqwest::Result<(Option<Vec<u8>>, Response)> {
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+
+    let body = response.bytes().await?;
+    let response = builder
+        .body(body.clone())
+        .expect("Response parts should be valid");
+
+    Ok((Some(body.to_vec()), response.into()))
+}
+
+/// Responses cannot be rebuilt on `wasm32` targets, so their body is not read.
+#[cfg(target_arch = "wasm32")]
+async fn read_body(response: Response) -> reqwest::Result<(Option<Vec<u8>>, Response)> {
+    Ok((None, response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InspectedRequest;
+    use crate::providers::cohere::Client;
+
+    #[test]
+    fn test_inspected_request_strips_authorization() {
+        let request = Client::new("test-key")
+            .post("/v2/chat")
+            .header("X-Client-Name", "rig")
+            .json(&serde_json::json!({"model": "command-r"}))
+            .build()
+            .unwrap();
+
+        let inspected = InspectedRequest::new(&request);
+
+        assert!(inspected.headers.get("Authorization").is_none());
+        assert_eq!(inspected.headers["X-Client-Name"], "rig");
+        assert_eq!(inspected.body_text(), Some(r#"{"model":"command-r"}"#));
+    }
+}
@@ -0,0 +1,263 @@
+//! Offline stand-ins for the Cohere models, for testing code built on top of them without
+//! calling the Cohere API. Requires the `mock` feature.
+//!
+//! [MockCompletionModel] replies with scripted Cohere chat responses, in order, and records the
+//! request bodies it would have sent. [MockEmbeddingModel] returns deterministic embeddings.
+//!
+//! # Example
+//! ```
+//! use rig::{completion::Prompt, providers::cohere::mock::MockCompletionModel};
+//!
+//! # tokio_test::block_on(async {
+//! let model = MockCompletionModel::new().with_text_response("2 + 3 = 5");
+//!
+//! let agent = rig::agent::AgentBuilder::new(model.clone()).build();
+//! assert_eq!(agent.prompt("What is 2 + 3?").await.unwrap(), "2 + 3 = 5");
+//!
+//! let request = &model.requests()[0];
+//! let prompt = request["messages"].as_array().unwrap().last().unwrap();
+//! assert_eq!(prompt["content"][0]["text"], "What is 2 + 3?");
+//! # });
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    completion::{self, CompletionError},
+    embeddings::{self, EmbeddingError},
+};
+
+use super::{completion::CompletionResponse, Client, CompletionModel};
+
+/// Completion model returning scripted Cohere chat responses instead of calling the API.
+/// Clones share the same script and recorded requests.
+#[derive(Clone)]
+pub struct MockCompletionModel {
+    model: CompletionModel,
+    responses: Arc<Mutex<VecDeque<Value>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl Default for MockCompletionModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockCompletionModel {
+    pub fn new() -> Self {
+        Self::from_model(Client::new("mock").completion_model(super::COMMAND_R))
+    }
+
+    /// Create a mock that builds its requests like `model` (e.g.: to check the model parameters
+    /// sent with each request).
+    pub fn from_model(model: CompletionModel) -> Self {
+        Self {
+            model,
+            responses: Default::default(),
+            requests: Default::default(),
+        }
+    }
+
+    /// Add a raw Cohere chat response (i.e.: the JSON body returned by `/v2/chat`) to the script.
+    pub fn with_response(self, response: Value) -> Self {
+        self.responses
+            .lock()
+            .expect("Mock responses lock should not be poisoned")
+            .push_back(response);
+        self
+    }
+
+    /// Add a text reply to the script.
+    pub fn with_text_response(self, text: &str) -> Self {
+        self.with_response(json!({
+            "id": "mock",
+            "finish_reason": "COMPLETE",
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": text }],
+            },
+        }))
+    }
+
+    /// Add a reply calling the tool `name` with `arguments` to the script.
+    pub fn with_tool_call_response(self, id: &str, name: &str, arguments: Value) -> Self {
+        self.with_response(json!({
+            "id": "mock",
+            "finish_reason": "TOOL_CALL",
+            "message": {
+                "role": "assistant",
+                "tool_calls": [{
+                    "id": id,
+                    "type": "function",
+                    "function": { "name": name, "arguments": arguments.to_string() },
+                }],
+            },
+        }))
+    }
+
+    /// Request bodies the model would have sent to the Cohere API, in order.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests
+            .lock()
+            .expect("Mock requests lock should not be poisoned")
+            .clone()
+    }
+}
+
+impl completion::CompletionModel for MockCompletionModel {
+    type Response = CompletionResponse;
+
+    #[cfg_attr(feature = "worker", worker::send)]
+    async fn completion(
+        &self,
+        completion_request: completion::CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let request = self.model.create_completion_request(completion_request)?;
+        self.requests
+            .lock()
+            .expect("Mock requests lock should not be poisoned")
+            .push(request);
+
+        let response = self
+            .responses
+            .lock()
+            .expect("Mock responses lock should not be poisoned")
+            .pop_front()
+            .ok_or_else(|| {
+                CompletionError::ProviderError("No scripted mock response left".to_string())
+            })?;
+
+        serde_json::from_value::<CompletionResponse>(response)?.try_into()
+    }
+}
+
+/// Embedding model returning deterministic embeddings instead of calling the API.
+/// Each text is embedded into a unit vector derived from its bytes, unless a canned embedding
+/// was registered for it with [MockEmbeddingModel::with_embedding].
+#[derive(Clone)]
+pub struct MockEmbeddingModel {
+    ndims: usize,
+    embeddings: HashMap<String, Vec<f64>>,
+}
+
+impl MockEmbeddingModel {
+    pub fn new(ndims: usize) -> Self {
+        Self {
+            ndims,
+            embeddings: HashMap::new(),
+        }
+    }
+
+    /// Return `embedding` whenever `text` is embedded.
+    pub fn with_embedding(mut self, text: &str, embedding: Vec<f64>) -> Self {
+        self.embeddings.insert(text.to_string(), embedding);
+        self
+    }
+
+    fn embed(&self, text: &str) -> Vec<f64> {
+        if let Some(embedding) = self.embeddings.get(text) {
+            return embedding.clone();
+        }
+
+        let mut vec = vec![0.0; self.ndims];
+        if self.ndims == 0 {
+            return vec;
+        }
+        text.bytes()
+            .enumerate()
+            .for_each(|(i, byte)| vec[i % self.ndims] += byte as f64);
+
+        let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vec.iter_mut().for_each(|x| *x /= norm);
+        }
+        vec
+    }
+}
+
+impl embeddings::EmbeddingModel for MockEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 96;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    #[cfg_attr(feature = "worker", worker::send)]
+    async fn embed_texts(
+        &self,
+        documents: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        Ok(documents
+            .into_iter()
+            .map(|document| embeddings::Embedding {
+                vec: self.embed(&document),
+                document,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        completion::{AssistantContent, CompletionModel, Prompt},
+        embeddings::EmbeddingModel,
+    };
+
+    use super::{MockCompletionModel, MockEmbeddingModel};
+
+    #[tokio::test]
+    async fn test_mock_completion_model() {
+        let model = MockCompletionModel::new()
+            .with_tool_call_response("call_0", "add", json!({"x": 2, "y": 3}))
+            .with_text_response("2 + 3 = 5");
+
+        let response = model
+            .completion_request("What is 2 + 3?")
+            .send()
+            .await
+            .unwrap();
+        match response.choice.first() {
+            AssistantContent::ToolCall(tool_call) => {
+                assert_eq!(tool_call.function.name, "add");
+                assert_eq!(tool_call.function.arguments, json!({"x": 2, "y": 3}));
+            }
+            content => panic!("Expected a tool call, got {content:?}"),
+        }
+
+        let agent = crate::agent::AgentBuilder::new(model.clone()).build();
+        assert_eq!(agent.prompt("What is 2 + 3?").await.unwrap(), "2 + 3 = 5");
+
+        let requests = model.requests();
+        assert_eq!(requests.len(), 2);
+        let prompt = requests[1]["messages"].as_array().unwrap().last().unwrap();
+        assert_eq!(prompt["content"][0]["text"], "What is 2 + 3?");
+
+        assert!(agent.prompt("Anything else?").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_embedding_model() {
+        let model = MockEmbeddingModel::new(8).with_embedding("canned", vec![1.0; 8]);
+
+        let embeddings = model
+            .embed_texts(vec!["hello".to_string(), "canned".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            embeddings[0].vec,
+            model.embed_text("hello").await.unwrap().vec
+        );
+        assert!(embeddings[0].is_normalized());
+        assert_eq!(embeddings[1].vec, vec![1.0; 8]);
+    }
+}
@@ -9,15 +9,37 @@
 //! let command_r = client.completion_model(cohere::COMMAND_R);
 //! ```
 
+pub mod cache;
+pub mod classify;
 pub mod client;
 pub mod completion;
 pub mod embeddings;
+pub mod inspect;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod pricing;
+pub mod retry;
 pub mod streaming;
+#[cfg(test)]
+mod testing;
+pub mod usage;
 
-pub use client::Client;
+pub use classify::ClassifyModel;
 pub use client::{ApiErrorResponse, ApiResponse};
-pub use completion::CompletionModel;
-pub use embeddings::EmbeddingModel;
+pub use client::{Client, ClientBuilder, CohereError, Middleware, ModelInfo, Rating};
+pub use completion::{
+    raw_chat_history, restore_chat_history, CompletionModel, DocumentLimits, DocumentOverflow,
+    SafetyMode, DEFAULT_BATCH_CONCURRENCY, MAX_STOP_SEQUENCES,
+};
+pub use embeddings::{
+    CohereInputType, DocumentEmbeddingModel, EmbeddingModel, EmbeddingModelBuilder, ImageInput,
+    InputType, QueryEmbeddingModel, Truncate, MAX_IMAGE_SIZE,
+};
+pub use inspect::{InspectedRequest, InspectedResponse, RequestInspector};
+pub use retry::RetryPolicy;
+pub use usage::BilledUnits;
+
+pub use crate::json_utils::ArrayMerge;
 
 // ================================================================
 // Cohere Completion Models
@@ -36,6 +58,28 @@ pub const COMMAND_LIGHT: &str = "command-light";
 /// `command-light-nightly` completion model
 pub const COMMAND_LIGHT_NIGHTLY: &str = "command-light-nightly";
 
+/// Context window sizes (in tokens) of the Cohere completion models. The nightly aliases are
+/// left out: the model behind them changes, so they get [DEFAULT_CONTEXT_WINDOW].
+pub const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    (COMMAND_R_PLUS, 128_000),
+    (COMMAND_R, 128_000),
+    (COMMAND, 4_096),
+    (COMMAND_LIGHT, 4_096),
+];
+
+/// Context window size (in tokens) assumed for the Cohere completion models missing from
+/// [CONTEXT_WINDOWS], the smallest context window of the Cohere models.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 4_096;
+
+/// Context window size (in tokens) of a Cohere completion model, or `None` if the model is
+/// unknown. Useful to size the budget of a [HistoryTruncation](crate::memory::HistoryTruncation).
+pub fn context_window(model: &str) -> Option<usize> {
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, size)| *size)
+}
+
 // ================================================================
 // Cohere Embedding Models
 // ================================================================
@@ -54,3 +98,33 @@ pub const EMBED_ENGLISH_V2: &str = "embed-english-v2.0";
 pub const EMBED_ENGLISH_LIGHT_V2: &str = "embed-english-light-v2.0";
 /// `embed-multilingual-v2.0` embedding model
 pub const EMBED_MULTILINGUAL_V2: &str = "embed-multilingual-v2.0";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(context_window(COMMAND_R_PLUS), Some(128_000));
+        assert_eq!(context_window(COMMAND_LIGHT), Some(4_096));
+        assert_eq!(context_window("unknown-model"), None);
+        assert_eq!(context_window(COMMAND_NIGHTLY), None);
+    }
+
+    #[test]
+    fn test_max_context_tokens() {
+        use crate::completion::CompletionModel as _;
+
+        let client = Client::new("dummy-key");
+        assert_eq!(
+            client.completion_model(COMMAND_R).max_context_tokens(),
+            Some(128_000)
+        );
+        assert_eq!(
+            client
+                .completion_model("unknown-model")
+                .max_context_tokens(),
+            Some(DEFAULT_CONTEXT_WINDOW)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Pricing of the Cohere models, to estimate the cost of a batch of completions or embeddings
+//! before running it. The billed units of each response (see [BilledUnits]) give the actual usage.
+//!
+//! Prices are the list prices of the Cohere API in USD, and may change: check the Cohere pricing
+//! page for up-to-date prices, and use [ModelPricing] directly for custom prices.
+//!
+//! # Example
+//! ```
+//! use rig::providers::cohere::{self, pricing};
+//!
+//! let documents = vec!["The quick brown fox".to_string(), "jumps over the lazy dog".to_string()];
+//! let cost = pricing::estimate_embedding_cost(cohere::EMBED_ENGLISH_V3, &documents).unwrap();
+//! ```
+
+use super::usage::BilledUnits;
+pub use crate::tokens::estimate_tokens;
+
+/// Prices of a model, in USD per million tokens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPricing {
+    pub const fn new(input_per_million: f64, output_per_million: f64) -> Self {
+        Self {
+            input_per_million,
+            output_per_million,
+        }
+    }
+
+    /// Cost of `input_tokens` and `output_tokens`, in USD.
+    pub fn cost(&self, input_tokens: usize, output_tokens: usize) -> f64 {
+        (input_tokens as f64 * self.input_per_million
+            + output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+
+    /// Actual cost of the billed units of a response, in USD.
+    pub fn billed_cost(&self, billed_units: &BilledUnits) -> f64 {
+        self.cost(
+            billed_units.input_tokens as usize,
+            billed_units.output_tokens as usize,
+        )
+    }
+}
+
+/// List prices of the Cohere models
+pub const PRICING: &[(&str, ModelPricing)] = &[
+    (super::COMMAND_R_PLUS, ModelPricing::new(2.5, 10.0)),
+    (super::COMMAND_R, ModelPricing::new(0.15, 0.6)),
+    (super::COMMAND, ModelPricing::new(1.0, 2.0)),
+    (super::COMMAND_NIGHTLY, ModelPricing::new(1.0, 2.0)),
+    (super::COMMAND_LIGHT, ModelPricing::new(0.3, 0.6)),
+    (super::COMMAND_LIGHT_NIGHTLY, ModelPricing::new(0.3, 0.6)),
+    (super::EMBED_ENGLISH_V3, ModelPricing::new(0.1, 0.0)),
+    (super::EMBED_ENGLISH_LIGHT_V3, ModelPricing::new(0.1, 0.0)),
+    (super::EMBED_MULTILINGUAL_V3, ModelPricing::new(0.1, 0.0)),
+    (
+        super::EMBED_MULTILINGUAL_LIGHT_V3,
+        ModelPricing::new(0.1, 0.0),
+    ),
+    (super::EMBED_ENGLISH_V2, ModelPricing::new(0.1, 0.0)),
+    (super::EMBED_ENGLISH_LIGHT_V2, ModelPricing::new(0.1, 0.0)),
+    (super::EMBED_MULTILINGUAL_V2, ModelPricing::new(0.1, 0.0)),
+];
+
+/// Prices of a Cohere model, or `None` if the model is unknown.
+pub fn pricing(model: &str) -> Option<ModelPricing> {
+    PRICING
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, pricing)| *pricing)
+}
+
+/// Estimated cost (in USD) of a completion of `prompt` by `model`, generating at most
+/// `max_output_tokens` tokens, or `None` if the model is unknown.
+pub fn estimate_completion_cost(
+    model: &str,
+    prompt: &str,
+    max_output_tokens: usize,
+) -> Option<f64> {
+    pricing(model).map(|pricing| pricing.cost(estimate_tokens(prompt), max_output_tokens))
+}
+
+/// Estimated cost (in USD) of embedding `documents` with `model`, or `None` if the model is unknown.
+pub fn estimate_embedding_cost(model: &str, documents: &[String]) -> Option<f64> {
+    let tokens = documents
+        .iter()
+        .map(|document| estimate_tokens(document))
+        .sum();
+
+    pricing(model).map(|pricing| pricing.cost(tokens, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::cohere;
+
+    #[test]
+    fn test_estimate_costs() {
+        assert_eq!(pricing("unknown-model"), None);
+
+        // 1M prompt tokens and 500k output tokens
+        let prompt = "a".repeat(4_000_000);
+        let cost = estimate_completion_cost(cohere::COMMAND_R_PLUS, &prompt, 500_000).unwrap();
+        assert!((cost - 7.5).abs() < 1e-9);
+
+        let documents = vec!["a".repeat(2_000_000), "b".repeat(2_000_000)];
+        let cost = estimate_embedding_cost(cohere::EMBED_ENGLISH_V3, &documents).unwrap();
+        assert!((cost - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_billed_cost() {
+        let billed_units: BilledUnits = serde_json::from_value(serde_json::json!({
+            "input_tokens": 2000,
+            "output_tokens": 1000
+        }))
+        .unwrap();
+
+        let cost = pricing(cohere::COMMAND_R)
+            .unwrap()
+            .billed_cost(&billed_units);
+        assert!((cost - 0.0009).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,260 @@
+//! Retry policy used by the Cohere [Client](super::Client) when the API responds with
+//! a rate limit (429) or a transient server error (502, 503, 504).
+//!
+//! # Example
+//! ```
+//! use std::time::Duration;
+//! use rig::providers::cohere::{self, RetryPolicy};
+//!
+//! let client = cohere::Client::new("YOUR_API_KEY").with_retry_policy(
+//!     RetryPolicy::default()
+//!         .max_attempts(5)
+//!         .base_delay(Duration::from_secs(1)),
+//! );
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request exactly once.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Maximum number of attempts per request, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry. Subsequent retries double it.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Upper bound on the delay between two attempts, including delays requested by the API.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Whether to randomize the backoff delay to avoid retrying concurrent requests in lockstep.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub(crate) fn is_retryable(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+
+    /// Delay before the retry following the given (zero-based) attempt.
+    /// A `Retry-After` delay sent by the API takes precedence over the exponential backoff.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let delay = retry_after.unwrap_or_else(|| {
+            let backoff = self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(self.max_delay);
+
+            if self.jitter {
+                backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+            } else {
+                backoff
+            }
+        });
+
+        delay.min(self.max_delay)
+    }
+
+    /// Send the request, retrying it according to the policy.
+    /// Requests whose body cannot be cloned (e.g.: streams) are sent once.
+    pub(crate) async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+
+        loop {
+            let retry = match request.try_clone() {
+                Some(retry) if attempt + 1 < self.max_attempts => retry,
+                _ => return request.send().await,
+            };
+
+            let response = retry.send().await?;
+
+            if !Self::is_retryable(response.status()) {
+                return Ok(response);
+            }
+
+            let delay = self.delay(attempt, retry_after(&response));
+            tracing::warn!(target: "rig",
+                "Cohere request failed with status {}, retrying in {:?} (attempt {}/{})",
+                response.status(),
+                delay,
+                attempt + 1,
+                self.max_attempts,
+            );
+
+            futures_timer::Delay::new(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Delay requested by the `Retry-After` header of the response, if any. The header is either a
+/// number of seconds or an HTTP date. A missing or invalid header gives `None`, i.e.: the
+/// exponential backoff of the [RetryPolicy].
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    // `SystemTime` is not available on `wasm32-unknown-unknown`, where HTTP dates are ignored
+    #[cfg(not(target_arch = "wasm32"))]
+    let now = || Some(SystemTime::now());
+    #[cfg(target_arch = "wasm32")]
+    let now = || None;
+
+    parse_retry_after(value, now)
+}
+
+/// Delay requested by a `Retry-After` header value, an HTTP date being relative to the time
+/// given by `now` (zero for a past date).
+fn parse_retry_after(value: &str, now: impl FnOnce() -> Option<SystemTime>) -> Option<Duration> {
+    let value = value.trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(now()?).unwrap_or_default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use reqwest::StatusCode;
+
+    use super::{parse_retry_after, RetryPolicy};
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(RetryPolicy::is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(RetryPolicy::is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!RetryPolicy::is_retryable(
+            StatusCode::INTERNAL_SERVER_ERROR
+        ));
+        assert!(!RetryPolicy::is_retryable(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let policy = RetryPolicy::default()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350))
+            .jitter(false);
+
+        assert_eq!(policy.delay(0, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(1, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(350));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_millis(300))),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(60))),
+            Duration::from_millis(350)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        let now = || Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777));
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // Obsolete RFC 850 format
+        assert_eq!(
+            parse_retry_after("Sunday, 06-Nov-94 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // Dates in the past allow an immediate retry
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+        assert_eq!(parse_retry_after("", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_header() {
+        use super::retry_after;
+
+        let response = |retry_after: Option<&str>| {
+            let mut response = http::Response::builder().status(429);
+            if let Some(retry_after) = retry_after {
+                response = response.header("Retry-After", retry_after);
+            }
+            reqwest::Response::from(response.body("").unwrap())
+        };
+
+        assert_eq!(
+            retry_after(&response(Some("3"))),
+            Some(Duration::from_secs(3))
+        );
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(61));
+        let delay = retry_after(&response(Some(&in_a_minute))).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(61));
+        assert_eq!(retry_after(&response(None)), None);
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let policy = RetryPolicy::default().base_delay(Duration::from_millis(100));
+
+        for _ in 0..100 {
+            let delay = policy.delay(0, None);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+}
@@ -1,25 +1,49 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+
 use crate::completion::{CompletionError, CompletionRequest};
-use crate::providers::cohere::completion::Usage;
+use crate::providers::cohere::completion::{Citation, Usage};
 use crate::providers::cohere::CompletionModel;
 use crate::streaming::{RawStreamingChoice, StreamingCompletionModel};
 use crate::{json_utils, streaming};
 use async_stream::stream;
-use futures::StreamExt;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
 use serde::Deserialize;
 use serde_json::json;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 enum StreamingEvent {
-    MessageStart,
+    MessageStart {
+        #[serde(default)]
+        id: Option<String>,
+    },
     ContentStart,
-    ContentDelta { delta: Option<Delta> },
+    ContentDelta {
+        delta: Option<Delta>,
+    },
     ContentEnd,
     ToolPlan,
-    ToolCallStart { delta: Option<Delta> },
-    ToolCallDelta { delta: Option<Delta> },
+    ToolCallStart {
+        delta: Option<Delta>,
+    },
+    ToolCallDelta {
+        delta: Option<Delta>,
+    },
     ToolCallEnd,
-    MessageEnd { delta: Option<MessageEndDelta> },
+    CitationStart {
+        #[serde(default)]
+        index: Option<usize>,
+        delta: Option<CitationDelta>,
+    },
+    CitationEnd {
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    MessageEnd {
+        delta: Option<MessageEndDelta>,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,19 +79,95 @@ struct MessageEndDelta {
     usage: Option<Usage>,
 }
 
+#[derive(Debug, Deserialize)]
+struct CitationDelta {
+    message: Option<CitationMessageDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CitationMessageDelta {
+    citations: Option<Citation>,
+}
+
 #[derive(Clone)]
 pub struct StreamingCompletionResponse {
     pub usage: Option<Usage>,
+    /// Id of the generation, sent by the Cohere API at the start of the stream
+    pub generation_id: Option<String>,
+    /// Citations of the response, in the order they were streamed
+    pub citations: Vec<Citation>,
 }
 
-impl StreamingCompletionModel for CompletionModel {
-    type StreamingResponse = StreamingCompletionResponse;
+impl StreamingCompletionResponse {
+    /// Units billed for the response (zero if the stream ended without usage), in the shape
+    /// shared by all the Cohere endpoints.
+    pub fn billed_units(&self) -> crate::providers::cohere::BilledUnits {
+        self.usage
+            .as_ref()
+            .map(Usage::billed_units)
+            .unwrap_or_default()
+    }
+}
 
-    async fn stream(
+/// Chunk of a Cohere chat stream (see [CompletionModel::stream_chunks]).
+#[derive(Clone)]
+pub enum StreamChunk {
+    /// Text chunk of the message
+    Text(String),
+    /// Complete tool call
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// Complete citation of the text streamed so far
+    Citation(Citation),
+    /// End of the stream
+    Final(StreamingCompletionResponse),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type StreamChunks = Pin<Box<dyn Stream<Item = Result<StreamChunk, CompletionError>> + Send>>;
+
+#[cfg(target_arch = "wasm32")]
+pub type StreamChunks = Pin<Box<dyn Stream<Item = Result<StreamChunk, CompletionError>>>>;
+
+impl CompletionModel {
+    /// Stream the response to `request` as Cohere chunks, including the citations of the
+    /// response as soon as they are complete (e.g.: to highlight the sources of a grounded
+    /// answer while it streams). [stream](StreamingCompletionModel::stream) streams the same
+    /// response without the citations, which are only available at the end of the stream.
+    ///
+    /// # Example
+    /// ```
+    /// use futures::StreamExt;
+    /// use rig::providers::cohere::streaming::StreamChunk;
+    ///
+    /// let mut chunks = model.stream_chunks(request).await?;
+    /// while let Some(chunk) = chunks.next().await {
+    ///     match chunk? {
+    ///         StreamChunk::Text(text) => print!("{text}"),
+    ///         StreamChunk::Citation(citation) => highlight(citation),
+    ///         _ => {}
+    ///     }
+    /// }
+    /// ```
+    #[tracing::instrument(
+        target = "rig",
+        name = "cohere_stream",
+        skip_all,
+        fields(
+            model = %self.model,
+            documents = request.documents.len(),
+            http.status = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        ),
+        err,
+    )]
+    pub async fn stream_chunks(
         &self,
         request: CompletionRequest,
-    ) -> Result<streaming::StreamingCompletionResponse<Self::StreamingResponse>, CompletionError>
-    {
+    ) -> Result<StreamChunks, CompletionError> {
         let request = self.create_completion_request(request)?;
         let request = json_utils::merge(request, json!({"stream": true}));
 
@@ -76,113 +176,324 @@ impl StreamingCompletionModel for CompletionModel {
             serde_json::to_string_pretty(&request)?
         );
 
-        let response = self.client.post("/v2/chat").json(&request).send().await?;
+        let response = self.client.send(self.chat_request(&request)).await?;
 
         if !response.status().is_success() {
-            return Err(CompletionError::ProviderError(format!(
-                "{}: {}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(super::completion::completion_error(response).await);
         }
 
-        let stream = Box::pin(stream! {
-            let mut stream = response.bytes_stream();
-            let mut current_tool_call: Option<(String, String, String)> = None;
+        Ok(stream_chunks(response.bytes_stream()))
+    }
+}
 
-            while let Some(chunk_result) = stream.next().await {
-               let chunk = match chunk_result {
-                    Ok(c) => c,
-                    Err(e) => {
-                        yield Err(CompletionError::from(e));
-                        break;
+impl StreamingCompletionModel for CompletionModel {
+    type StreamingResponse = StreamingCompletionResponse;
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<streaming::StreamingCompletionResponse<Self::StreamingResponse>, CompletionError>
+    {
+        // Citations are only returned with the final response
+        let stream = self
+            .stream_chunks(request)
+            .await?
+            .filter_map(|chunk| async move {
+                match chunk {
+                    Ok(StreamChunk::Text(text)) => Some(Ok(RawStreamingChoice::Message(text))),
+                    Ok(StreamChunk::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    }) => Some(Ok(RawStreamingChoice::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    })),
+                    Ok(StreamChunk::Citation(_)) => None,
+                    Ok(StreamChunk::Final(response)) => {
+                        Some(Ok(RawStreamingChoice::FinalResponse(response)))
                     }
-                };
+                    Err(e) => Some(Err(e)),
+                }
+            });
 
-               let text = match String::from_utf8(chunk.to_vec()) {
-                    Ok(t) => t,
+        Ok(streaming::StreamingCompletionResponse::new(Box::pin(
+            stream,
+        )))
+    }
+}
+
+/// Parse the server-sent events of a Cohere chat stream into chunks.
+fn stream_chunks(
+    mut stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin + 'static,
+) -> StreamChunks {
+    Box::pin(stream! {
+        let mut current_tool_call: Option<(String, String, String)> = None;
+        let mut generation_id: Option<String> = None;
+        // Citations are complete at their `citation-end` event
+        let mut current_citations: HashMap<usize, Citation> = HashMap::new();
+        let mut citations: Vec<Citation> = vec![];
+        // Bytes of the incomplete line at the end of the last chunk, as network chunks may
+        // split events (and multi-byte characters)
+        let mut buffer: Vec<u8> = vec![];
+        let mut finished = false;
+
+        while !finished {
+            match stream.next().await {
+                Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    yield Err(CompletionError::from(e));
+                    break;
+                }
+                // The last line of the stream may not end with a newline
+                None => {
+                    finished = true;
+                    if !buffer.is_empty() {
+                        buffer.push(b'\n');
+                    }
+                }
+            }
+
+            while let Some(line) = next_line(&mut buffer) {
+                let line = match String::from_utf8(line) {
+                    Ok(line) => line,
                     Err(e) => {
                         yield Err(CompletionError::ResponseError(e.to_string()));
-                        break;
+                        continue;
                     }
-               };
+                };
 
-                for line in text.lines() {
+                let Some(line) = line.trim_end().strip_prefix("data: ") else {
+                    continue;
+                };
 
-                    let Some(line) = line.strip_prefix("data: ") else {
-                        continue;
-                    };
-
-                    let event = {
-                       let result = serde_json::from_str::<StreamingEvent>(line);
-
-                       let Ok(event) = result else {
-                           continue;
-                       };
-
-                        event
-                    };
-
-                    match event {
-                        StreamingEvent::ContentDelta { delta: Some(delta) } => {
-                            let Some(message) = &delta.message else { continue; };
-                            let Some(content) = &message.content else { continue; };
-                            let Some(text) = &content.text else { continue; };
-
-                            yield Ok(RawStreamingChoice::Message(text.clone()));
-                        },
-                        StreamingEvent::MessageEnd {delta: Some(delta)} => {
-                            yield Ok(RawStreamingChoice::FinalResponse(StreamingCompletionResponse {
-                                usage: delta.usage.clone()
-                            }));
-                        },
-                        StreamingEvent::ToolCallStart { delta: Some(delta)} => {
-                            // Skip the delta if there's any missing information,
-                            // though this *should* all be present
-                            let Some(message) = &delta.message else { continue; };
-                            let Some(tool_calls) = &message.tool_calls else { continue; };
-                            let Some(id) = tool_calls.id.clone() else { continue; };
-                            let Some(function) = &tool_calls.function else { continue; };
-                            let Some(name) = function.name.clone() else { continue; };
-                            let Some(arguments) = function.arguments.clone() else { continue; };
-
-                            current_tool_call = Some((id, name, arguments));
-                        },
-                        StreamingEvent::ToolCallDelta { delta: Some(delta)} => {
-                            // Skip the delta if there's any missing information,
-                            // though this *should* all be present
-                            let Some(message) = &delta.message else { continue; };
-                            let Some(tool_calls) = &message.tool_calls else { continue; };
-                            let Some(function) = &tool_calls.function else { continue; };
-                            let Some(arguments) = function.arguments.clone() else { continue; };
-
-                            if let Some(tc) = current_tool_call.clone() {
-                                current_tool_call = Some((
-                                    tc.0,
-                                    tc.1,
-                                    format!("{}{}", tc.2, arguments)
-                                ));
-                            };
-                        },
-                        StreamingEvent::ToolCallEnd => {
-                            let Some(tc) = current_tool_call.clone() else { continue; };
-
-                            let Ok(args) = serde_json::from_str(&tc.2) else { continue; };
-
-                            yield Ok(RawStreamingChoice::ToolCall {
-                                id: tc.0,
-                                name: tc.1,
-                                arguments: args
-                            });
-
-                            current_tool_call = None;
-                        },
-                        _ => {}
-                    };
-                }
+                let event = {
+                   let result = serde_json::from_str::<StreamingEvent>(line);
+
+                   let Ok(event) = result else {
+                       continue;
+                   };
+
+                    event
+                };
+
+                match event {
+                    StreamingEvent::MessageStart { id } => {
+                        generation_id = id;
+                    },
+                    StreamingEvent::ContentDelta { delta: Some(delta) } => {
+                        let Some(message) = &delta.message else { continue; };
+                        let Some(content) = &message.content else { continue; };
+                        let Some(text) = &content.text else { continue; };
+
+                        yield Ok(StreamChunk::Text(text.clone()));
+                    },
+                    StreamingEvent::CitationStart { index, delta: Some(delta) } => {
+                        let Some(message) = delta.message else { continue; };
+                        let Some(citation) = message.citations else { continue; };
+
+                        current_citations.insert(index.unwrap_or_default(), citation);
+                    },
+                    StreamingEvent::CitationEnd { index } => {
+                        let Some(citation) = current_citations.remove(&index.unwrap_or_default()) else { continue; };
+
+                        citations.push(citation.clone());
+                        yield Ok(StreamChunk::Citation(citation));
+                    },
+                    StreamingEvent::MessageEnd {delta: Some(delta)} => {
+                        yield Ok(StreamChunk::Final(StreamingCompletionResponse {
+                            usage: delta.usage.clone(),
+                            generation_id: generation_id.clone(),
+                            citations: citations.clone(),
+                        }));
+                    },
+                    StreamingEvent::ToolCallStart { delta: Some(delta)} => {
+                        // Skip the delta if there's any missing information,
+                        // though this *should* all be present
+                        let Some(message) = &delta.message else { continue; };
+                        let Some(tool_calls) = &message.tool_calls else { continue; };
+                        let Some(id) = tool_calls.id.clone() else { continue; };
+                        let Some(function) = &tool_calls.function else { continue; };
+                        let Some(name) = function.name.clone() else { continue; };
+                        let Some(arguments) = function.arguments.clone() else { continue; };
+
+                        let previous = current_tool_call.replace((id, name, arguments));
+                        if let Some((id, name, _)) = previous {
+                            tracing::warn!(
+                                target: "rig",
+                                "Dropped the unfinished tool call `{name}` ({id}) of the stream"
+                            );
+                        }
+                    },
+                    StreamingEvent::ToolCallDelta { delta: Some(delta)} => {
+                        // Skip the delta if there's any missing information,
+                        // though this *should* all be present
+                        let Some(message) = &delta.message else { continue; };
+                        let Some(tool_calls) = &message.tool_calls else { continue; };
+                        let Some(function) = &tool_calls.function else { continue; };
+                        let Some(arguments) = function.arguments.clone() else { continue; };
+
+                        if let Some(tc) = current_tool_call.clone() {
+                            current_tool_call = Some((
+                                tc.0,
+                                tc.1,
+                                format!("{}{}", tc.2, arguments)
+                            ));
+                        };
+                    },
+                    StreamingEvent::ToolCallEnd => {
+                        // The arguments are only parsed once complete, and the tool call is
+                        // emitted right away so that it can be dispatched before the end of
+                        // the response
+                        let Some((id, name, arguments)) = current_tool_call.take() else { continue; };
+
+                        match parse_tool_arguments(&arguments) {
+                            Ok(arguments) => yield Ok(StreamChunk::ToolCall {
+                                id,
+                                name,
+                                arguments,
+                            }),
+                            Err(e) => yield Err(CompletionError::ResponseError(format!(
+                                "Invalid arguments for tool call `{name}` ({id}): {e}"
+                            ))),
+                        }
+                    },
+                    _ => {}
+                };
             }
-        });
+        }
+    })
+}
+
+/// Split the first complete line (without its `\n`) off `buffer`, if any.
+fn next_line(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let newline = buffer.iter().position(|byte| *byte == b'\n')?;
+    let mut line = buffer.drain(..=newline).collect::<Vec<_>>();
+    line.pop();
+    Some(line)
+}
+
+/// Parse the complete arguments of a streamed tool call. Tools without parameters may be called
+/// with empty arguments.
+fn parse_tool_arguments(arguments: &str) -> Result<serde_json::Value, serde_json::Error> {
+    if arguments.trim().is_empty() {
+        Ok(json!({}))
+    } else {
+        serde_json::from_str(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{next_line, parse_tool_arguments, stream_chunks, StreamChunk, StreamingEvent};
+
+    #[test]
+    fn test_next_line_buffers_split_events() {
+        let mut buffer = b"data: {\"type\": \"content-".to_vec();
+        assert_eq!(next_line(&mut buffer), None);
+
+        buffer.extend_from_slice("end\"}\r\ndata: caf\u{e9}".as_bytes());
+        assert_eq!(
+            next_line(&mut buffer).unwrap(),
+            b"data: {\"type\": \"content-end\"}\r"
+        );
+        assert_eq!(next_line(&mut buffer), None);
+
+        buffer.extend_from_slice(b"\n\n");
+        assert_eq!(
+            String::from_utf8(next_line(&mut buffer).unwrap()).unwrap(),
+            "data: caf\u{e9}"
+        );
+        assert_eq!(next_line(&mut buffer).unwrap(), b"");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_parse_tool_arguments() {
+        assert_eq!(
+            parse_tool_arguments(r#"{"x": 2, "y": 5}"#).unwrap(),
+            json!({"x": 2, "y": 5})
+        );
+        assert_eq!(parse_tool_arguments("").unwrap(), json!({}));
+        assert!(parse_tool_arguments(r#"{"x": 2,"#).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_message_start() {
+        let event: StreamingEvent = serde_json::from_str(
+            r#"{"type": "message-start", "id": "gen_0", "delta": {"message": {"role": "assistant"}}}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            event,
+            StreamingEvent::MessageStart { id: Some(id) } if id == "gen_0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_citations() {
+        use futures::StreamExt;
+
+        let events = [
+            r#"{"type": "message-start", "id": "gen_0"}"#,
+            r#"{"type": "content-delta", "index": 0, "delta": {"message": {"content": {"text": "Flurbos are green."}}}}"#,
+            r#"{"type": "citation-start", "index": 0, "delta": {"message": {"citations": {"start": 11, "end": 17, "text": "green.", "sources": [{"type": "document", "id": "doc0", "document": {"id": "doc0"}}]}}}}"#,
+            r#"{"type": "citation-end", "index": 0}"#,
+            r#"{"type": "message-end", "delta": {"finish_reason": "COMPLETE"}}"#,
+        ]
+        .map(|event| format!("event: x\ndata: {event}\n\n"))
+        .concat();
+        // Network chunks splitting the events
+        let (first, second) = events.split_at(events.find("citation-end").unwrap());
+        let bytes = futures::stream::iter([
+            Ok(bytes::Bytes::from(first.to_string())),
+            Ok(bytes::Bytes::from(second.to_string())),
+        ]);
+
+        let chunks = stream_chunks(bytes)
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(&chunks[0], StreamChunk::Text(text) if text == "Flurbos are green."));
+
+        let StreamChunk::Citation(citation) = &chunks[1] else {
+            panic!("Expected a citation");
+        };
+        assert_eq!(citation.text.as_deref(), Some("green."));
+        assert_eq!((citation.start, citation.end), (Some(11), Some(17)));
+        assert_eq!(citation.sources.len(), 1);
+
+        let StreamChunk::Final(response) = &chunks[2] else {
+            panic!("Expected the final response");
+        };
+        assert_eq!(response.generation_id.as_deref(), Some("gen_0"));
+        assert_eq!(response.citations, vec![citation.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_last_line_without_newline() {
+        use futures::StreamExt;
+
+        let bytes = futures::stream::iter([
+            Ok(bytes::Bytes::from(
+                "data: {\"type\": \"content-delta\", \"delta\": {\"message\": {\"content\": {\"text\": \"Hi\"}}}}\n",
+            )),
+            Ok(bytes::Bytes::from(
+                r#"data: {"type": "message-end", "delta": {"finish_reason": "COMPLETE"}}"#,
+            )),
+        ]);
 
-        Ok(streaming::StreamingCompletionResponse::new(stream))
+        let chunks = stream_chunks(bytes)
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[0], StreamChunk::Text(text) if text == "Hi"));
+        assert!(matches!(&chunks[1], StreamChunk::Final(_)));
     }
 }
@@ -0,0 +1,159 @@
+//! HTTP server standing in for the Cohere API in the tests of the Cohere provider.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Response sent by a [TestServer].
+pub(crate) struct TestResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub(crate) fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+
+        Self {
+            status,
+            headers: vec![("content-length".to_string(), body.len().to_string())],
+            body,
+        }
+    }
+
+    /// `200 OK` response with a JSON body.
+    pub(crate) fn json(body: impl ToString) -> Self {
+        Self::new(200, body.to_string()).header("content-type", "application/json")
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send the response without a `content-length` header, the end of the body being the end
+    /// of the connection.
+    pub(crate) fn without_content_length(mut self) -> Self {
+        self.headers.retain(|(name, _)| name != "content-length");
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let reason = reqwest::StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default();
+        let mut response = format!("HTTP/1.1 {} {reason}\r\n", self.status);
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str("connection: close\r\n\r\n");
+
+        [response.into_bytes(), self.body.clone()].concat()
+    }
+}
+
+/// Local HTTP server answering one request per connection, and recording the requests it
+/// receives (request line, headers and body).
+pub(crate) struct TestServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Server answering the requests it receives with `responses`, in order, then closing.
+    pub(crate) async fn serve(responses: Vec<TestResponse>) -> Self {
+        let (listener, server) = Self::bind().await;
+
+        let requests = server.requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                requests.lock().unwrap().push(request);
+                socket.write_all(&response.to_bytes()).await.unwrap();
+            }
+        });
+
+        server
+    }
+
+    /// Server answering each request it receives with `respond(request)`, concurrently.
+    pub(crate) async fn serve_with<F, Fut>(respond: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TestResponse> + Send + 'static,
+    {
+        let (listener, server) = Self::bind().await;
+
+        let requests = server.requests.clone();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (requests, respond) = (requests.clone(), respond.clone());
+                tokio::spawn(async move {
+                    let request = read_request(&mut socket).await;
+                    requests.lock().unwrap().push(request.clone());
+                    let response = respond(request).await;
+                    socket.write_all(&response.to_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        server
+    }
+
+    /// Requests received so far, in order of arrival.
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn bind() -> (TcpListener, Self) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        (
+            listener,
+            Self {
+                base_url,
+                requests: Arc::default(),
+            },
+        )
+    }
+}
+
+/// Read a request up to the end of its body (according to its `content-length` header).
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = socket.read(&mut buffer).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            let content_length = String::from_utf8_lossy(&request[..end])
+                .to_lowercase()
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|length| length.trim().parse::<usize>().ok())
+                .unwrap_or_default();
+            if request.len() >= end + 4 + content_length {
+                break;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&request).into_owned()
+}
@@ -0,0 +1,115 @@
+//! Usage of the Cohere API, as billed by Cohere for each request.
+
+use serde::{Deserialize, Serialize};
+
+/// Units billed by the Cohere API for a request, in the same shape for every endpoint (chat, embed,
+/// classify), so that the usage of requests to different endpoints can be added up.
+///
+/// # Example
+/// ```
+/// use rig::{
+///     completion::CompletionResponse,
+///     providers::cohere::{self, BilledUnits, ClassifyModel, EmbeddingModel},
+/// };
+///
+/// # async fn total_usage(
+/// #     embedding_model: EmbeddingModel,
+/// #     classifier: ClassifyModel,
+/// #     completion: CompletionResponse<cohere::completion::CompletionResponse>,
+/// # ) -> Result<BilledUnits, Box<dyn std::error::Error>> {
+/// let documents = vec!["The quick brown fox".to_string()];
+/// let (_, embed_units) = embedding_model.embed_texts_with_usage(documents).await?;
+///
+/// let inputs = vec!["I love it".to_string()];
+/// let examples = vec![
+///     ("Great product".to_string(), "positive".to_string()),
+///     ("Terrible service".to_string(), "negative".to_string()),
+/// ];
+/// let (_, classify_units) = classifier.classify_with_usage(inputs, examples).await?;
+///
+/// let total: BilledUnits = [completion.raw_response.billed_units(), embed_units, classify_units]
+///     .into_iter()
+///     .sum();
+/// # Ok(total)
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BilledUnits {
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub search_units: u32,
+    #[serde(default)]
+    pub classifications: u32,
+}
+
+impl std::ops::AddAssign for BilledUnits {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens = self.input_tokens.saturating_add(other.input_tokens);
+        self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
+        self.search_units = self.search_units.saturating_add(other.search_units);
+        self.classifications = self.classifications.saturating_add(other.classifications);
+    }
+}
+
+impl std::ops::Add for BilledUnits {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        self += other;
+        self
+    }
+}
+
+impl std::iter::Sum for BilledUnits {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, units| total + units)
+    }
+}
+
+impl std::fmt::Display for BilledUnits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Input tokens: {}\nOutput tokens: {}\nSearch units: {}\nClassifications: {}",
+            self.input_tokens, self.output_tokens, self.search_units, self.classifications
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BilledUnits;
+
+    #[test]
+    fn test_billed_units_sum() {
+        let units = [
+            BilledUnits {
+                input_tokens: 10,
+                output_tokens: 5,
+                ..Default::default()
+            },
+            BilledUnits {
+                search_units: 1,
+                ..Default::default()
+            },
+            BilledUnits {
+                input_tokens: 3,
+                classifications: 2,
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            units.into_iter().sum::<BilledUnits>(),
+            BilledUnits {
+                input_tokens: 13,
+                output_tokens: 5,
+                search_units: 1,
+                classifications: 2,
+            }
+        );
+    }
+}
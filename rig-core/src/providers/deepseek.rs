@@ -380,6 +380,12 @@ impl DeepSeekCompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<serde_json::Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "DeepSeek",
+            seed: None,
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Build up the order of messages (context, chat_history, prompt)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -419,6 +425,8 @@ impl DeepSeekCompletionModel {
             })
         };
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
@@ -112,6 +112,30 @@ pub(crate) fn create_request_body(
         generation_config.max_output_tokens = Some(max_tokens);
     }
 
+    if let Some(top_p) = completion_request.top_p {
+        generation_config.top_p = Some(top_p);
+    }
+
+    if let Some(top_k) = completion_request.top_k {
+        generation_config.top_k = Some(i32::try_from(top_k).unwrap_or(i32::MAX));
+    }
+
+    if let Some(stop_sequences) = completion_request.stop_sequences {
+        generation_config.stop_sequences = Some(stop_sequences);
+    }
+
+    if let Some(seed) = completion_request.seed {
+        generation_config.seed = Some(seed);
+    }
+
+    if let Some(frequency_penalty) = completion_request.frequency_penalty {
+        generation_config.frequency_penalty = Some(frequency_penalty);
+    }
+
+    if let Some(presence_penalty) = completion_request.presence_penalty {
+        generation_config.presence_penalty = Some(presence_penalty);
+    }
+
     let system_instruction = completion_request.preamble.clone().map(|preamble| Content {
         parts: OneOrMany::one(preamble.into()),
         role: Some(Role::Model),
@@ -792,6 +816,9 @@ pub mod gemini_api_types {
         /// the model to  repeating a common token until it hits the maxOutputTokens limit: "...the the the the the...".
         #[serde(skip_serializing_if = "Option::is_none")]
         pub frequency_penalty: Option<f64>,
+        /// Seed used in decoding. If not set, the request uses a randomly generated seed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub seed: Option<u64>,
         /// If true, export the logprobs results in response.
         #[serde(skip_serializing_if = "Option::is_none")]
         pub response_logprobs: Option<bool>,
@@ -814,6 +841,7 @@ pub mod gemini_api_types {
                 top_k: None,
                 presence_penalty: None,
                 frequency_penalty: None,
+                seed: None,
                 response_logprobs: None,
                 logprobs: None,
             }
@@ -280,6 +280,11 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Groq",
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Build up the order of messages (context, chat_history, prompt)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -322,6 +327,8 @@ impl CompletionModel {
             })
         };
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
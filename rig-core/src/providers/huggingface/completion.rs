@@ -498,6 +498,12 @@ impl CompletionModel {
         &self,
         completion_request: &CompletionRequest,
     ) -> Result<serde_json::Value, CompletionError> {
+        // Sampling parameters (e.g.: top-p) set on the request
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Hugging Face",
+            ..completion::SamplingParams::OPENAI
+        });
+
         let mut full_history: Vec<Message> = match &completion_request.preamble {
             Some(preamble) => vec![Message::system(preamble)],
             None => vec![],
@@ -536,6 +542,9 @@ impl CompletionModel {
                 "tool_choice": "auto",
             })
         };
+
+        let request = json_utils::merge(request, sampling_params);
+
         Ok(request)
     }
 }
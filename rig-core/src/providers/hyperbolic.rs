@@ -307,6 +307,12 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Hyperbolic",
+            top_k: Some("top_k"),
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Build up the order of messages (context, chat_history, prompt)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -336,6 +342,8 @@ impl CompletionModel {
             "temperature": completion_request.temperature,
         });
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
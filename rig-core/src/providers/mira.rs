@@ -229,6 +229,11 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Mira",
+            ..completion::SamplingParams::OPENAI
+        });
+
         let mut messages = Vec::new();
 
         // Add preamble as user message if available
@@ -299,6 +304,8 @@ impl CompletionModel {
             "stream": false
         });
 
+        let request = merge(request, sampling_params);
+
         Ok(request)
     }
 }
@@ -325,6 +325,16 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Ollama",
+            top_p: Some("top_p"),
+            top_k: Some("top_k"),
+            stop_sequences: Some("stop"),
+            seed: Some("seed"),
+            frequency_penalty: Some("frequency_penalty"),
+            presence_penalty: Some("presence_penalty"),
+        });
+
         // Build up the order of messages (context, chat_history)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -346,13 +356,15 @@ impl CompletionModel {
         );
 
         // Convert internal prompt into a provider Message
+        // Sampling parameters are model options for Ollama
+        let options = json_utils::merge(
+            json!({ "temperature": completion_request.temperature }),
+            sampling_params,
+        );
         let options = if let Some(extra) = completion_request.additional_params {
-            json_utils::merge(
-                json!({ "temperature": completion_request.temperature }),
-                extra,
-            )
+            json_utils::merge(options, extra)
         } else {
-            json!({ "temperature": completion_request.temperature })
+            options
         };
 
         let mut request_payload = json!({
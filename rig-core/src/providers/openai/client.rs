@@ -530,4 +530,92 @@ mod tests {
         assert_eq!(original_user_message[0], user_message);
         assert_eq!(original_assistant_message[0], assistant_message);
     }
+
+    #[test]
+    fn test_max_tokens_field() {
+        use crate::completion::CompletionRequest;
+        use crate::providers::openai::{Client, MaxTokensField};
+
+        let request = || CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(message::Message::user("Hello")),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: Some(256),
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            additional_params: None,
+        };
+        let client = Client::new("dummy-key");
+
+        let legacy = client
+            .completion_model("gpt-4o")
+            .create_completion_request(request())
+            .unwrap();
+        assert_eq!(legacy["max_tokens"], 256);
+        assert!(legacy.get("max_completion_tokens").is_none());
+
+        let new = client
+            .completion_model("o3-mini")
+            .create_completion_request(request())
+            .unwrap();
+        assert_eq!(new["max_completion_tokens"], 256);
+        assert!(new.get("max_tokens").is_none());
+
+        let overridden = client
+            .completion_model("gpt-4o")
+            .with_max_tokens_field(MaxTokensField::New(1024))
+            .create_completion_request(request())
+            .unwrap();
+        assert_eq!(overridden["max_completion_tokens"], 256);
+
+        let default_limit = client
+            .completion_model("gpt-4o")
+            .with_max_tokens_field(MaxTokensField::Legacy(1024))
+            .create_completion_request(CompletionRequest {
+                max_tokens: None,
+                ..request()
+            })
+            .unwrap();
+        assert_eq!(default_limit["max_tokens"], 1024);
+    }
+
+    #[test]
+    fn test_sampling_params() {
+        use crate::completion::CompletionRequest;
+        use crate::providers::openai::Client;
+
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(message::Message::user("Hello")),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            top_p: Some(0.9),
+            top_k: Some(40),
+            stop_sequences: Some(vec!["END".to_string()]),
+            seed: Some(42),
+            frequency_penalty: Some(-1.5),
+            presence_penalty: None,
+            additional_params: Some(serde_json::json!({ "top_p": 0.5 })),
+        };
+
+        let request = Client::new("dummy-key")
+            .completion_model("gpt-4o")
+            .create_completion_request(request)
+            .unwrap();
+        assert_eq!(request["stop"], serde_json::json!(["END"]));
+        assert_eq!(request["seed"], 42);
+        assert_eq!(request["frequency_penalty"], -1.5);
+        assert!(request.get("presence_penalty").is_none());
+        // OpenAI has no top-k, and additional parameters take precedence
+        assert!(request.get("top_k").is_none());
+        assert_eq!(request["top_p"], 0.5);
+    }
 }
@@ -606,11 +606,62 @@ impl FromStr for SystemContent {
     }
 }
 
+/// Request field used to cap the number of generated tokens.
+///
+/// OpenAI deprecated `max_tokens` in favor of `max_completion_tokens`, and newer models
+/// (e.g.: the `o1`/`o3` reasoning models and `gpt-4.1`) reject the legacy field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaxTokensField {
+    /// Sends the limit as `max_tokens`
+    Legacy(u32),
+    /// Sends the limit as `max_completion_tokens`
+    New(u32),
+}
+
+impl MaxTokensField {
+    /// Pick the field supported by `model`, based on its name prefix.
+    pub fn for_model(model: &str, max_tokens: u32) -> Self {
+        const NEW_FIELD_PREFIXES: [&str; 4] = ["o1", "o3", "o4", "gpt-4.1"];
+
+        if NEW_FIELD_PREFIXES
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            Self::New(max_tokens)
+        } else {
+            Self::Legacy(max_tokens)
+        }
+    }
+
+    /// Name of the request field
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Legacy(_) => "max_tokens",
+            Self::New(_) => "max_completion_tokens",
+        }
+    }
+
+    /// Token limit carried by the field
+    pub fn value(&self) -> u32 {
+        match self {
+            Self::Legacy(value) | Self::New(value) => *value,
+        }
+    }
+
+    fn with_value(self, value: u32) -> Self {
+        match self {
+            Self::Legacy(_) => Self::Legacy(value),
+            Self::New(_) => Self::New(value),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CompletionModel {
     pub(crate) client: Client,
     /// Name of the model (e.g.: gpt-3.5-turbo-1106)
     pub model: String,
+    max_tokens_field: Option<MaxTokensField>,
 }
 
 impl CompletionModel {
@@ -618,6 +669,28 @@ impl CompletionModel {
         Self {
             client,
             model: model.to_string(),
+            max_tokens_field: None,
+        }
+    }
+
+    /// Set the token limit and the request field used to send it.
+    ///
+    /// A `max_tokens` set on the completion request takes precedence over the limit given here,
+    /// but is still sent using this field. When no field is set, it is picked from the model name
+    /// (see [MaxTokensField::for_model]).
+    pub fn with_max_tokens_field(mut self, field: MaxTokensField) -> Self {
+        self.max_tokens_field = Some(field);
+        self
+    }
+
+    fn max_tokens_field(&self, max_tokens: Option<u64>) -> Option<MaxTokensField> {
+        let max_tokens = max_tokens.map(|max_tokens| max_tokens.min(u32::MAX as u64) as u32);
+
+        match (self.max_tokens_field, max_tokens) {
+            (Some(field), Some(max_tokens)) => Some(field.with_value(max_tokens)),
+            (Some(field), None) => Some(field),
+            (None, Some(max_tokens)) => Some(MaxTokensField::for_model(&self.model, max_tokens)),
+            (None, None) => None,
         }
     }
 
@@ -625,6 +698,9 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params =
+            completion_request.sampling_params(&completion::SamplingParams::OPENAI);
+
         // Build up the order of messages (context, chat_history)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -676,6 +752,14 @@ impl CompletionModel {
             request
         };
 
+        let request = if let Some(field) = self.max_tokens_field(completion_request.max_tokens) {
+            json_utils::merge(request, json!({ field.key(): field.value() }))
+        } else {
+            request
+        };
+
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
@@ -127,6 +127,12 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "OpenRouter",
+            top_k: Some("top_k"),
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Add preamble to chat history (if available)
         let mut full_history: Vec<Message> = match &completion_request.preamble {
             Some(preamble) => vec![Message::system(preamble)],
@@ -159,6 +165,8 @@ impl CompletionModel {
             "tool_calls": completion_request.tools
         });
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
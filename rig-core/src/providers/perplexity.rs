@@ -205,6 +205,13 @@ impl CompletionModel {
         &self,
         completion_request: CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Perplexity",
+            top_k: Some("top_k"),
+            seed: None,
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Build up the order of messages (context, chat_history, prompt)
         let mut partial_history = vec![];
         if let Some(docs) = completion_request.normalized_documents() {
@@ -238,6 +245,8 @@ impl CompletionModel {
             "temperature": completion_request.temperature,
         });
 
+        let request = json_utils::merge(request, sampling_params);
+
         let request = if let Some(ref params) = completion_request.additional_params {
             json_utils::merge(request, params.clone())
         } else {
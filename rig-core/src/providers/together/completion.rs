@@ -144,6 +144,12 @@ impl CompletionModel {
         &self,
         completion_request: completion::CompletionRequest,
     ) -> Result<serde_json::Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "Together AI",
+            top_k: Some("top_k"),
+            ..completion::SamplingParams::OPENAI
+        });
+
         let mut full_history: Vec<openai::Message> = match &completion_request.preamble {
             Some(preamble) => vec![openai::Message::system(preamble)],
             None => vec![],
@@ -178,6 +184,8 @@ impl CompletionModel {
                 "tool_choice": "auto",
             })
         };
+        request = json_utils::merge(request, sampling_params);
+
         request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
@@ -31,6 +31,11 @@ impl CompletionModel {
         &self,
         completion_request: completion::CompletionRequest,
     ) -> Result<Value, CompletionError> {
+        let sampling_params = completion_request.sampling_params(&completion::SamplingParams {
+            provider: "xAI",
+            ..completion::SamplingParams::OPENAI
+        });
+
         // Convert documents into user message
         let docs: Option<Vec<Message>> = completion_request
             .normalized_documents()
@@ -77,6 +82,8 @@ impl CompletionModel {
             })
         };
 
+        request = json_utils::merge(request, sampling_params);
+
         request = if let Some(params) = completion_request.additional_params {
             json_utils::merge(request, params)
         } else {
@@ -0,0 +1,20 @@
+//! Rough token estimates, for budgeting purposes (e.g.: fitting a chat history in a context
+//! window, or estimating the cost of a request) when the tokenizer of the model is not available.
+
+/// Rough estimate of the number of tokens of `text` (about 4 characters per token), for
+/// budgeting purposes only.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("1234"), 1);
+        assert_eq!(estimate_tokens("12345"), 2);
+    }
+}
@@ -185,6 +185,28 @@ impl<T: Tool> ToolDyn for T {
     }
 }
 
+/// Boxed tools, e.g.: to register tools of different types at once with
+/// [AgentBuilder::tools](crate::agent::AgentBuilder::tools).
+impl ToolDyn for Box<dyn ToolDyn> {
+    fn name(&self) -> String {
+        self.as_ref().name()
+    }
+
+    fn definition(
+        &self,
+        prompt: String,
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + Sync + '_>> {
+        self.as_ref().definition(prompt)
+    }
+
+    fn call(
+        &self,
+        args: String,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + Sync + '_>> {
+        self.as_ref().call(args)
+    }
+}
+
 #[cfg(feature = "mcp")]
 pub struct McpTool<T: mcp_core::transport::Transport> {
     definition: mcp_core::types::Tool,
@@ -373,6 +395,10 @@ pub enum ToolSetError {
     #[error("ToolNotFoundError: {0}")]
     ToolNotFoundError(String),
 
+    /// Several tools have the same name
+    #[error("DuplicateToolError: a tool named `{0}` is already registered")]
+    DuplicateToolError(String),
+
     // TODO: Revisit this
     #[error("JsonError: {0}")]
     JsonError(#[from] serde_json::Error),
@@ -9,10 +9,34 @@ use serde::{Deserialize, Serialize};
 
 use super::{VectorStoreError, VectorStoreIndex};
 use crate::{
-    embeddings::{distance::VectorDistance, Embedding, EmbeddingModel},
+    embeddings::{distance::VectorDistance, Embedding, EmbeddingModel, QueryEmbedder},
     OneOrMany,
 };
 
+/// Metric used by [InMemoryVectorStore] to score documents against a query embedding.
+/// Higher scores are always better.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    /// Cosine similarity of the embeddings
+    #[default]
+    Cosine,
+    /// Dot product of the embeddings (equivalent to cosine similarity for normalized embeddings)
+    DotProduct,
+    /// Euclidean distance of the embeddings, negated so that closer documents score higher
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Score `embedding` against `query`
+    pub fn score(&self, embedding: &Embedding, query: &Embedding) -> f64 {
+        match self {
+            SimilarityMetric::Cosine => embedding.cosine_similarity(query, false),
+            SimilarityMetric::DotProduct => embedding.dot_product(query),
+            SimilarityMetric::Euclidean => -embedding.euclidean_distance(query),
+        }
+    }
+}
+
 /// [InMemoryVectorStore] is a simple in-memory vector store that stores embeddings
 /// in-memory using a HashMap.
 #[derive(Clone, Default)]
@@ -21,6 +45,8 @@ pub struct InMemoryVectorStore<D: Serialize> {
     /// Hashmap key is the document id.
     /// Hashmap value is a tuple of the serializable document and its corresponding embeddings.
     embeddings: HashMap<String, (D, OneOrMany<Embedding>)>,
+    /// Metric used to rank documents, cosine similarity by default.
+    metric: SimilarityMetric,
 }
 
 impl<D: Serialize + Eq> InMemoryVectorStore<D> {
@@ -36,7 +62,10 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
                 store.insert(format!("doc{i}"), (doc, embeddings));
             });
 
-        Self { embeddings: store }
+        Self {
+            embeddings: store,
+            metric: SimilarityMetric::default(),
+        }
     }
 
     /// Create a new [InMemoryVectorStore] from documents and and their corresponding embeddings with ids.
@@ -48,7 +77,10 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
             store.insert(i.to_string(), (doc, embeddings));
         });
 
-        Self { embeddings: store }
+        Self {
+            embeddings: store,
+            metric: SimilarityMetric::default(),
+        }
     }
 
     /// Create a new [InMemoryVectorStore] from documents and their corresponding embeddings.
@@ -62,7 +94,27 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
             store.insert(f(&doc), (doc, embeddings));
         });
 
-        Self { embeddings: store }
+        Self {
+            embeddings: store,
+            metric: SimilarityMetric::default(),
+        }
+    }
+
+    /// Set the metric used to rank documents (cosine similarity by default).
+    pub fn with_metric(mut self, metric: SimilarityMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Get the `k` documents closest to `query_embedding` according to the store's metric, as
+    /// `(score, id, document)` tuples sorted from best to worst.
+    /// For documents with multiple embeddings, the best scoring embedding is used.
+    pub fn top_k(&self, query_embedding: &Embedding, k: usize) -> Vec<(f64, String, &D)> {
+        self.vector_search(query_embedding, k)
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(RankingItem(score, id, doc, _))| (score.0, id.clone(), doc))
+            .collect()
     }
 
     /// Implement vector search on [InMemoryVectorStore].
@@ -77,7 +129,7 @@ impl<D: Serialize + Eq> InMemoryVectorStore<D> {
                 .iter()
                 .map(|embedding| {
                     (
-                        OrderedFloat(embedding.cosine_similarity(prompt_embedding, false)),
+                        OrderedFloat(self.metric.score(embedding, prompt_embedding)),
                         &embedding.document,
                     )
                 })
@@ -176,7 +228,12 @@ impl<D: Serialize + Eq> PartialOrd for RankingItem<'_, D> {
 type EmbeddingRanking<'a, D> = BinaryHeap<Reverse<RankingItem<'a, D>>>;
 
 impl<D: Serialize> InMemoryVectorStore<D> {
-    pub fn index<M: EmbeddingModel>(self, model: M) -> InMemoryVectorIndex<M, D> {
+    /// Index the store to search it with the queries embedded by `model` (any [EmbeddingModel],
+    /// or a model only meant to embed queries).
+    pub fn index<M: EmbeddingModel>(
+        self,
+        model: impl QueryEmbedder<Model = M>,
+    ) -> InMemoryVectorIndex<M, D> {
         InMemoryVectorIndex::new(model, self)
     }
 
@@ -199,8 +256,11 @@ pub struct InMemoryVectorIndex<M: EmbeddingModel, D: Serialize> {
 }
 
 impl<M: EmbeddingModel, D: Serialize> InMemoryVectorIndex<M, D> {
-    pub fn new(model: M, store: InMemoryVectorStore<D>) -> Self {
-        Self { model, store }
+    pub fn new(model: impl QueryEmbedder<Model = M>, store: InMemoryVectorStore<D>) -> Self {
+        Self {
+            model: model.into_query_model(),
+            store,
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&String, &(D, OneOrMany<Embedding>))> {
@@ -265,7 +325,7 @@ mod tests {
 
     use crate::{embeddings::embedding::Embedding, OneOrMany};
 
-    use super::{InMemoryVectorStore, RankingItem};
+    use super::{InMemoryVectorStore, RankingItem, SimilarityMetric};
 
     #[test]
     fn test_auto_ids() {
@@ -502,4 +562,40 @@ mod tests {
             )]
         )
     }
+
+    #[test]
+    fn test_top_k_with_metric() {
+        let embedding = |vec: Vec<f64>| {
+            OneOrMany::one(Embedding {
+                document: String::new(),
+                vec,
+            })
+        };
+        let vector_store = InMemoryVectorStore::from_documents_with_ids(vec![
+            ("small", "small", embedding(vec![1.0, 0.0])),
+            ("large", "large", embedding(vec![10.0, 1.0])),
+            ("opposite", "opposite", embedding(vec![-1.0, 0.0])),
+        ]);
+        let query = Embedding {
+            document: "query".to_string(),
+            vec: vec![2.0, 0.0],
+        };
+
+        let ids = |ranking: Vec<(f64, String, &&str)>| {
+            ranking.into_iter().map(|(_, id, _)| id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(vector_store.top_k(&query, 3)),
+            vec!["small", "large", "opposite"]
+        );
+
+        let vector_store = vector_store.with_metric(SimilarityMetric::DotProduct);
+        assert_eq!(ids(vector_store.top_k(&query, 2)), vec!["large", "small"]);
+
+        let vector_store = vector_store.with_metric(SimilarityMetric::Euclidean);
+        let ranking = vector_store.top_k(&query, 2);
+        assert_eq!(ranking[0], (-1.0, "small".to_string(), &"small"));
+        assert_eq!(ranking[1].1, "opposite");
+    }
 }